@@ -0,0 +1,108 @@
+use autherium_rs::{Announcement, Severity};
+use eframe::egui::{self, Color32, RichText};
+
+/// Extra window height while announcements are shown.
+pub const HEIGHT: f32 = 160.0;
+
+fn severity_color(severity: Severity) -> Color32 {
+    match severity {
+        Severity::Info => Color32::GRAY,
+        Severity::Warning => Color32::from_rgb(230, 180, 60),
+        Severity::Critical => Color32::LIGHT_RED,
+    }
+}
+
+pub fn show(ui: &mut egui::Ui, announcements: &[Announcement]) {
+    egui::ScrollArea::vertical()
+        .max_height(HEIGHT - 20.0)
+        .max_width(440.0)
+        .show(ui, |ui| {
+            for announcement in announcements {
+                let color = severity_color(announcement.severity);
+                egui::Frame::new()
+                    .stroke((1.0, color))
+                    .inner_margin(8.0)
+                    .show(ui, |ui| {
+                        ui.set_width(420.0);
+                        ui.label(
+                            RichText::new(&announcement.title)
+                                .strong()
+                                .size(16.0)
+                                .color(color),
+                        );
+                        markdown(ui, &announcement.body);
+                    });
+                ui.add_space(6.0);
+            }
+        });
+}
+
+/// Renders the bits of markdown that fit in the loader: headings, bullet points,
+/// `**bold**` and `[links](url)`. Anything else is shown as written.
+fn markdown(ui: &mut egui::Ui, text: &str) {
+    for line in text.lines() {
+        let line = line.trim_end();
+        if line.trim().is_empty() {
+            ui.add_space(4.0);
+        } else if let Some(heading) = line.strip_prefix('#') {
+            let heading = heading.trim_start_matches('#').trim();
+            ui.label(RichText::new(heading).strong().color(Color32::LIGHT_GRAY));
+        } else if let Some(item) = line
+            .trim_start()
+            .strip_prefix("- ")
+            .or_else(|| line.trim_start().strip_prefix("* "))
+        {
+            ui.horizontal_wrapped(|ui| {
+                ui.label(RichText::new("•").color(Color32::GRAY));
+                inline(ui, item);
+            });
+        } else {
+            ui.horizontal_wrapped(|ui| inline(ui, line));
+        }
+    }
+}
+
+fn inline(ui: &mut egui::Ui, mut text: &str) {
+    ui.spacing_mut().item_spacing.x = 0.0;
+    while !text.is_empty() {
+        let bold = text.find("**");
+        let link = text.find('[');
+        match (bold, link) {
+            (Some(start), _) if link.is_none_or(|link| start < link) => {
+                let rest = &text[start + 2..];
+                let Some(end) = rest.find("**") else {
+                    break;
+                };
+                plain(ui, &text[..start]);
+                ui.label(
+                    RichText::new(&rest[..end])
+                        .strong()
+                        .color(Color32::LIGHT_GRAY),
+                );
+                text = &rest[end + 2..];
+            }
+            (_, Some(start)) => {
+                let parsed = text[start..].split_once("](").and_then(|(label, rest)| {
+                    let (url, rest) = rest.split_once(')')?;
+                    Some((&label[1..], url, rest))
+                });
+                let Some((label, url, rest)) = parsed else {
+                    plain(ui, &text[..=start]);
+                    text = &text[start + 1..];
+                    continue;
+                };
+                plain(ui, &text[..start]);
+                ui.hyperlink_to(label, url);
+                text = rest;
+            }
+            _ => break,
+        }
+    }
+    plain(ui, text);
+}
+
+fn plain(ui: &mut egui::Ui, text: &str) {
+    if !text.is_empty() {
+        ui.label(RichText::new(text).color(Color32::GRAY));
+    }
+}
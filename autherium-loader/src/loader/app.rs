@@ -1,3 +1,4 @@
+use autherium_rs::Announcement;
 use core::f32;
 use eframe::egui::{self, Color32, ImageButton, RichText, Style, Vec2, include_image, vec2};
 use std::sync::mpsc;
@@ -16,6 +17,9 @@ pub(crate) struct MyApp {
     pub product_id: String,
     pub discord_url: String,
     pub website_url: String,
+    pub announcements: Vec<Announcement>,
+    // Whether the window has grown to make room for the announcements
+    pub announcements_shown: bool,
 }
 #[derive(Default, PartialEq, Debug)]
 pub enum UiState {
@@ -29,7 +33,7 @@ pub enum UiState {
 // Result type for license verification
 #[derive(Debug, Clone)]
 pub enum LicenseResult {
-    Success(u64, u64),
+    Success(u64, u64, Vec<Announcement>),
     Error(String),
 }
 
@@ -254,6 +258,17 @@ impl eframe::App for MyApp {
                                 .fill(Color32::GRAY),
                             );
                             ui.add_space(25.0);
+                            if !self.announcements.is_empty() {
+                                if !self.announcements_shown {
+                                    self.announcements_shown = true;
+                                    ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(vec2(
+                                        520.0,
+                                        240.0 + super::announcements::HEIGHT,
+                                    )));
+                                }
+                                super::announcements::show(ui, &self.announcements);
+                                ui.add_space(10.0);
+                            }
                             if ui
                                 .add(
                                     egui::widgets::Button::new(RichText::new("Load").size(50.0))
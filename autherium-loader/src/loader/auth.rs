@@ -19,14 +19,21 @@ impl crate::loader::app::MyApp {
         // Spawn background thread for license verification
         thread::spawn(move || {
             let autherium = autherium_rs::Autherium::new(&autherium_url).unwrap();
-            match autherium.authenticate(&license, product_id) {
+            match autherium.authenticate(&license, product_id.clone()) {
                 Ok(response) => match response {
                     AuthResponse::Success {
                         license_start,
                         license_duration,
-                        time_remaining: _,
+                        ..
                     } => {
-                        let _ = tx.send(LicenseResult::Success(license_start, license_duration));
+                        // Announcements are nice to have, so failing to fetch them doesn't stop the load
+                        let announcements =
+                            autherium.announcements(&product_id).unwrap_or_default();
+                        let _ = tx.send(LicenseResult::Success(
+                            license_start,
+                            license_duration,
+                            announcements,
+                        ));
                         return;
                     }
                     AuthResponse::Error { error } => {
@@ -34,9 +41,18 @@ impl crate::loader::app::MyApp {
                         return;
                     }
                 },
-                Err(_) => {
-                    tx.send(LicenseResult::Error("Failed to authenticate!".to_string()))
-                        .unwrap();
+                Err(e) => {
+                    let message = if let Some(update) =
+                        e.downcast_ref::<autherium_rs::UpdateRequired>()
+                    {
+                        update.to_string()
+                    } else if let Some(disabled) = e.downcast_ref::<autherium_rs::ProductDisabled>()
+                    {
+                        disabled.to_string()
+                    } else {
+                        "Failed to authenticate!".to_string()
+                    };
+                    tx.send(LicenseResult::Error(message)).unwrap();
                     return;
                 }
             }
@@ -50,9 +66,10 @@ impl crate::loader::app::MyApp {
                     self.license_receiver = None;
 
                     match result {
-                        LicenseResult::Success(license_start, license_duration) => {
+                        LicenseResult::Success(license_start, license_duration, announcements) => {
                             self.ui_state = UiState::Verified;
                             self.license_timing = (license_start, license_duration);
+                            self.announcements = announcements;
                         }
                         LicenseResult::Error(error) => {
                             self.failed_reason = error;
@@ -5,7 +5,7 @@ fn main() {
     let time_remaining = Arc::new(AtomicI64::new(0));
     crate::loader::start::start(
         "thrum",
-        "http://localhost:8080",
+        "https://localhost:8443",
         "thrum",
         "https://discord.com",
         "https://thrummenu.dev",
@@ -5,31 +5,164 @@ struct MyApp {
     days: String,
     alert: String,
     product_id: String,
+    ban_reason: String,
+    ban_hours: String,
+    ban_ip: String,
+    ip_bans: Vec<autherium_rs::IpBan>,
+    ban_license: String,
+    cascade: bool,
+    maintenance_message: String,
+    pause_time: bool,
+}
+
+impl MyApp {
+    /// Ban options from the reason and duration fields.
+    fn ban_options(&self) -> Result<autherium_rs::BanOptions, String> {
+        let duration_secs = if self.ban_hours.trim().is_empty() {
+            None
+        } else if let Ok(hours) = self.ban_hours.trim().parse::<u64>() {
+            Some(hours * 60 * 60)
+        } else {
+            return Err("ban hours must be a number".into());
+        };
+        Ok(autherium_rs::BanOptions {
+            reason: Some(self.ban_reason.clone()).filter(|reason| !reason.is_empty()),
+            created_by: Some("panel".into()),
+            duration_secs,
+            show_reason: true,
+        })
+    }
 }
 
 impl eframe::App for MyApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut self.ban_reason);
+                ui.label("Ban Reason")
+            });
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut self.ban_hours);
+                ui.label("Ban Hours (empty for permanent)")
+            });
+            ui.checkbox(
+                &mut self.cascade,
+                "also ban the licenses and HWIDs linked to a banned HWID",
+            );
             if ui.button("ban self hwid").clicked() {
-                let autherium = autherium_rs::Autherium::new(&self.autherium_url.clone()).unwrap();
+                let options = match self.ban_options() {
+                    Ok(options) => options,
+                    Err(e) => {
+                        self.alert = e;
+                        return;
+                    }
+                };
+                let autherium =
+                    autherium_rs::Autherium::new_allow_http(&self.autherium_url.clone()).unwrap();
                 match autherium.ban_hwid(
                     &autherium_rs::Autherium::get_hwid().unwrap().into(),
                     &"super_secret_key".to_string(),
+                    &options,
+                    self.cascade,
                 ) {
                     Ok(_) => self.alert = "banned".into(),
                     Err(e) => self.alert = format!("Error: {}", e),
                 }
             }
             if ui.button("unban self hwid").clicked() {
-                let autherium = autherium_rs::Autherium::new(&self.autherium_url.clone()).unwrap();
+                let autherium =
+                    autherium_rs::Autherium::new_allow_http(&self.autherium_url.clone()).unwrap();
                 match autherium.unban_hwid(
                     &autherium_rs::Autherium::get_hwid().unwrap().into(),
                     &"super_secret_key".to_string(),
+                    Some("panel"),
                 ) {
                     Ok(_) => self.alert = "unbanned".into(),
                     Err(e) => self.alert = format!("Error: {}", e),
                 }
             }
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut self.ban_license);
+                ui.label("License")
+            });
+            ui.horizontal(|ui| {
+                if ui.button("ban license").clicked() {
+                    let options = match self.ban_options() {
+                        Ok(options) => options,
+                        Err(e) => {
+                            self.alert = e;
+                            return;
+                        }
+                    };
+                    let autherium =
+                        autherium_rs::Autherium::new_allow_http(&self.autherium_url.clone())
+                            .unwrap();
+                    match autherium.ban_license(&self.ban_license, "super_secret_key", &options) {
+                        Ok(_) => self.alert = "license banned".into(),
+                        Err(e) => self.alert = format!("Error: {}", e),
+                    }
+                }
+                if ui.button("unban license").clicked() {
+                    let autherium =
+                        autherium_rs::Autherium::new_allow_http(&self.autherium_url.clone())
+                            .unwrap();
+                    match autherium.unban_license(
+                        &self.ban_license,
+                        "super_secret_key",
+                        Some("panel"),
+                    ) {
+                        Ok(_) => self.alert = "license unbanned".into(),
+                        Err(e) => self.alert = format!("Error: {}", e),
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut self.ban_ip);
+                ui.label("IP or CIDR range")
+            });
+            ui.horizontal(|ui| {
+                if ui.button("ban ip").clicked() {
+                    let options = match self.ban_options() {
+                        Ok(options) => options,
+                        Err(e) => {
+                            self.alert = e;
+                            return;
+                        }
+                    };
+                    let autherium =
+                        autherium_rs::Autherium::new_allow_http(&self.autherium_url.clone())
+                            .unwrap();
+                    match autherium.ban_ip(&self.ban_ip, "super_secret_key", &options) {
+                        Ok(_) => self.alert = format!("banned {}", self.ban_ip),
+                        Err(e) => self.alert = format!("Error: {}", e),
+                    }
+                }
+                if ui.button("unban ip").clicked() {
+                    let autherium =
+                        autherium_rs::Autherium::new_allow_http(&self.autherium_url.clone())
+                            .unwrap();
+                    match autherium.unban_ip(&self.ban_ip, "super_secret_key", Some("panel")) {
+                        Ok(_) => self.alert = format!("unbanned {}", self.ban_ip),
+                        Err(e) => self.alert = format!("Error: {}", e),
+                    }
+                }
+                if ui.button("list ip bans").clicked() {
+                    let autherium =
+                        autherium_rs::Autherium::new_allow_http(&self.autherium_url.clone())
+                            .unwrap();
+                    match autherium.ip_bans("super_secret_key") {
+                        Ok(bans) => self.ip_bans = bans,
+                        Err(e) => self.alert = format!("Error: {}", e),
+                    }
+                }
+            });
+            for ban in &self.ip_bans {
+                ui.label(format!(
+                    "{} - {}",
+                    ban.network,
+                    ban.reason.as_deref().unwrap_or("no reason")
+                ));
+            }
             ui.horizontal(|ui| {
                 ui.text_edit_singleline(&mut self.days);
                 ui.label("License Days")
@@ -46,7 +179,8 @@ impl eframe::App for MyApp {
                     }
                     let days = self.days.parse::<u32>().unwrap();
                     let autherium =
-                        autherium_rs::Autherium::new(&self.autherium_url.clone()).unwrap();
+                        autherium_rs::Autherium::new_allow_http(&self.autherium_url.clone())
+                            .unwrap();
                     match autherium.create_license(
                         days as u64,
                         &"super_secret_key".to_string(),
@@ -57,6 +191,46 @@ impl eframe::App for MyApp {
                     }
                 }
             });
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut self.maintenance_message);
+                ui.label("Maintenance Message (empty for default)")
+            });
+            ui.checkbox(
+                &mut self.pause_time,
+                "pause license time while the product is disabled",
+            );
+            ui.horizontal(|ui| {
+                if ui.button("disable product").clicked() {
+                    let autherium =
+                        autherium_rs::Autherium::new_allow_http(&self.autherium_url.clone())
+                            .unwrap();
+                    match autherium.disable_product(
+                        "super_secret_key",
+                        &self.product_id,
+                        Some(self.maintenance_message.as_str())
+                            .filter(|message| !message.is_empty()),
+                        self.pause_time,
+                        Some("panel"),
+                    ) {
+                        Ok(()) => self.alert = format!("disabled product {}", self.product_id),
+                        Err(e) => self.alert = format!("Error: {}", e),
+                    }
+                }
+                if ui.button("enable product").clicked() {
+                    let autherium =
+                        autherium_rs::Autherium::new_allow_http(&self.autherium_url.clone())
+                            .unwrap();
+                    match autherium.enable_product("super_secret_key", &self.product_id) {
+                        Ok(enabled) => {
+                            self.alert = format!(
+                                "enabled product {}, {} license(s) extended by {}s",
+                                enabled.product_id, enabled.licenses_extended, enabled.paused_secs
+                            )
+                        }
+                        Err(e) => self.alert = format!("Error: {}", e),
+                    }
+                }
+            });
             ui.label(format!("{}", self.alert));
         });
     }
@@ -65,7 +239,7 @@ impl eframe::App for MyApp {
 fn main() {
     let options = eframe::NativeOptions {
         viewport: eframe::egui::ViewportBuilder::default()
-            .with_inner_size([520.0, 240.0])
+            .with_inner_size([520.0, 480.0])
             .with_decorations(true)
             .with_active(true)
             .with_taskbar(true)
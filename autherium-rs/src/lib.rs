@@ -1,23 +1,37 @@
 pub struct Autherium {
     base_url: String,
     hwid: String,
+    client_version: String,
     client: reqwest::blocking::Client,
     license_regex: Regex,
+    /// Token of the latest session and the key agreed on with the server for it. The
+    /// server hands out the same token again while the session lasts, so older ones
+    /// aren't kept.
+    session_key: Mutex<Option<(String, [u8; 32])>>,
 }
 
 use std::{
-    sync::{Arc, atomic::AtomicI64},
+    collections::HashMap,
+    fs,
+    io::{self, Read},
+    path::Path,
+    sync::{Arc, Mutex, atomic::AtomicI64},
     thread::JoinHandle,
+    time::Duration,
 };
 
 use regex::Regex;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AuthRequest {
     license: String,
     hwid: String,
     product_id: String,
+    client_version: String,
+    /// Hex X25519 public key, for agreeing on a key that encrypts payload downloads.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_public_key: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -27,12 +41,241 @@ pub enum AuthResponse {
         license_start: u64,
         license_duration: u64,
         time_remaining: i64,
+        /// Token for [`Autherium::download_payload`].
+        #[serde(default)]
+        session: Option<String>,
+        /// Hex X25519 public key the server agreed on the session key with.
+        #[serde(default)]
+        server_public_key: Option<String>,
+        /// Set when a newer version of the client is available.
+        #[serde(default)]
+        update: Option<UpdateInfo>,
     },
     Error {
         error: String,
     },
 }
 
+/// Where to get a newer version of the client.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UpdateInfo {
+    pub min_version: Option<String>,
+    pub latest_version: Option<String>,
+    pub download_url: Option<String>,
+    pub changelog: Option<String>,
+}
+
+/// Error returned by [`Autherium::authenticate`] when the server no longer supports
+/// this client version.
+#[derive(Debug, Clone)]
+pub struct UpdateRequired(pub UpdateInfo);
+
+impl std::fmt::Display for UpdateRequired {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Update required")?;
+        if let Some(version) = &self.0.latest_version {
+            write!(f, ", the latest version is {}", version)?;
+        }
+        if let Some(url) = &self.0.download_url {
+            write!(f, ", download it from {}", url)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for UpdateRequired {}
+
+/// Error returned by [`Autherium::authenticate`] and the session endpoints while the
+/// product is disabled, holding the server's message for the user.
+#[derive(Debug, Clone)]
+pub struct ProductDisabled(pub String);
+
+impl std::fmt::Display for ProductDisabled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ProductDisabled {}
+
+/// A product switched off with [`Autherium::disable_product`].
+#[derive(Deserialize, Clone, Debug)]
+pub struct DisabledProduct {
+    pub message: String,
+    pub since: u64,
+    pub pause_time: bool,
+    pub created_by: Option<String>,
+}
+
+/// What [`Autherium::enable_product`] did.
+#[derive(Deserialize, Clone, Debug)]
+pub struct ProductEnabled {
+    pub product_id: String,
+    /// Seconds added to the licenses of the product, when their time was paused.
+    pub paused_secs: u64,
+    pub licenses_extended: usize,
+}
+
+#[derive(Deserialize)]
+struct DisabledProductsResponse {
+    products: HashMap<String, DisabledProduct>,
+}
+
+/// A payload saved by [`Autherium::download_payload`].
+#[derive(Clone, Debug)]
+pub struct PayloadInfo {
+    pub version: String,
+    /// Hex SHA-256 of the file, checked against the one the server sent.
+    pub sha256: String,
+    pub size: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    #[default]
+    Info,
+    Warning,
+    Critical,
+}
+
+/// A message for the users of a product, from [`Autherium::announcements`].
+#[derive(Deserialize, Clone, Debug)]
+pub struct Announcement {
+    pub id: String,
+    pub title: String,
+    /// Markdown.
+    pub body: String,
+    pub severity: Severity,
+    pub starts_at: Option<u64>,
+    pub ends_at: Option<u64>,
+    /// Products it is shown for, every product when empty.
+    #[serde(default)]
+    pub product_ids: Vec<String>,
+    pub created_at: u64,
+}
+
+/// The content of an announcement to create or update.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct NewAnnouncement {
+    pub title: String,
+    /// Markdown.
+    pub body: String,
+    pub severity: Severity,
+    /// Shown from this Unix time on, right away when unset.
+    pub starts_at: Option<u64>,
+    /// Shown until this Unix time, for good when unset.
+    pub ends_at: Option<u64>,
+    /// Products to show it for, every product when empty.
+    pub product_ids: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct AnnouncementRequest<'a> {
+    key: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<&'a str>,
+    #[serde(flatten)]
+    announcement: &'a NewAnnouncement,
+}
+
+#[derive(Deserialize)]
+struct AnnouncementsResponse {
+    announcements: Vec<Announcement>,
+}
+
+/// Remote variables of a product, from [`Autherium::variables`].
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct Variables {
+    pub product_id: String,
+    pub variables: HashMap<String, serde_json::Value>,
+}
+
+impl Variables {
+    pub fn get(&self, name: &str) -> Option<&serde_json::Value> {
+        self.variables.get(name)
+    }
+
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        self.get(name)?.as_str()
+    }
+
+    pub fn get_i64(&self, name: &str) -> Option<i64> {
+        self.get(name)?.as_i64()
+    }
+
+    pub fn get_u64(&self, name: &str) -> Option<u64> {
+        self.get(name)?.as_u64()
+    }
+
+    pub fn get_f64(&self, name: &str) -> Option<f64> {
+        self.get(name)?.as_f64()
+    }
+
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        self.get(name)?.as_bool()
+    }
+
+    /// Deserializes a variable, e.g. a JSON object of offsets into a struct. `None` if
+    /// it is missing or has another shape.
+    pub fn get_as<T: DeserializeOwned>(&self, name: &str) -> Option<T> {
+        T::deserialize(self.get(name)?).ok()
+    }
+}
+
+/// Payloads can be large, so downloads get far longer than the client's default timeout.
+const PAYLOAD_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// HKDF info of the session key, as used by the server.
+const SESSION_KEY_INFO: &[u8] = b"autherium session key v1";
+
+/// HKDF info of the key of one encrypted download, as used by the server.
+const DOWNLOAD_KEY_INFO: &[u8] = b"autherium payload key v1";
+
+/// Largest chunk size accepted from the server, so a bogus header can't make the
+/// client allocate without bound.
+const MAX_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hex_decode(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn hkdf_sha256(
+    secret: &[u8],
+    salt: &[u8],
+    info: &[u8],
+) -> Result<[u8; 32], ring::error::Unspecified> {
+    let mut key = [0; 32];
+    ring::hkdf::Salt::new(ring::hkdf::HKDF_SHA256, salt)
+        .extract(secret)
+        .expand(&[info], ring::hkdf::HKDF_SHA256)?
+        .fill(&mut key)?;
+    Ok(key)
+}
+
+/// Nonce of sealed chunk `index`, whose first byte marks the last chunk.
+fn chunk_nonce(index: u64, last: bool) -> ring::aead::Nonce {
+    let mut nonce = [0; ring::aead::NONCE_LEN];
+    nonce[0] = u8::from(last);
+    nonce[4..].copy_from_slice(&index.to_be_bytes());
+    ring::aead::Nonce::assume_unique_for_key(nonce)
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct UpdateRequiredResponse {
+    update: UpdateInfo,
+}
+
 #[derive(Serialize, Clone, Debug)]
 struct CreateRequest {
     days: u64,
@@ -47,6 +290,195 @@ enum CreateResponse {
     Error { error: String },
 }
 
+/// Details recorded with a license, HWID or IP ban.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct BanOptions {
+    pub reason: Option<String>,
+    pub created_by: Option<String>,
+    /// Seconds until the ban lifts. Permanent when unset.
+    pub duration_secs: Option<u64>,
+    /// Show `reason` to the banned user when they try to authenticate.
+    pub show_reason: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct LicenseBanRequest<'a> {
+    license: &'a str,
+    key: &'a str,
+    #[serde(flatten)]
+    options: &'a BanOptions,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct BanRequest<'a> {
+    hwid: &'a str,
+    key: &'a str,
+    cascade: bool,
+    #[serde(flatten)]
+    options: &'a BanOptions,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct IpBanRequest<'a> {
+    ip: &'a str,
+    key: &'a str,
+    #[serde(flatten)]
+    options: &'a BanOptions,
+}
+
+/// An active IP ban as reported by the server.
+#[derive(Deserialize, Clone, Debug)]
+pub struct IpBan {
+    /// Banned address or CIDR range.
+    pub network: String,
+    pub reason: Option<String>,
+    pub created_at: u64,
+    pub created_by: Option<String>,
+    pub expires_at: Option<u64>,
+    pub show_reason: bool,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+enum IpBansResponse {
+    Bans { bans: Vec<IpBan> },
+    Error { error: String },
+}
+
+/// What the server does when a canary license is used.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CanaryAction {
+    /// Ban the HWID and IP that used it.
+    Ban,
+    /// Only record the use for review.
+    Flag,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct CanaryCreateRequest<'a> {
+    key: &'a str,
+    days: u64,
+    product_ids: &'a [&'a str],
+    label: &'a str,
+    action: CanaryAction,
+    count: usize,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+enum CanaryCreateResponse {
+    Licenses { licenses: Vec<String> },
+    Error { error: String },
+}
+
+/// A recorded use of a canary license.
+#[derive(Deserialize, Clone, Debug)]
+pub struct CanaryHit {
+    pub id: u64,
+    pub license: String,
+    /// Label given when the canary was created, e.g. the channel it was handed to.
+    pub label: String,
+    pub action: CanaryAction,
+    pub product_id: String,
+    pub hwid: String,
+    pub ip: Option<String>,
+    pub at: u64,
+    pub reviewed: bool,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+enum CanaryHitsResponse {
+    Hits { hits: Vec<CanaryHit> },
+    Error { error: String },
+}
+
+/// A license flagged for suspicious activity.
+#[derive(Deserialize, Clone, Debug)]
+pub struct AnomalyFlag {
+    pub id: u64,
+    pub license: String,
+    pub product_id: String,
+    /// `distinct_ips`, `impossible_travel` or `hwid_churn`.
+    pub rule: String,
+    pub detail: String,
+    pub at: u64,
+    /// Whether the license was suspended because of this flag.
+    pub suspended: bool,
+    pub reviewed: bool,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+enum AnomalyFlagsResponse {
+    Flags { flags: Vec<AnomalyFlag> },
+    Error { error: String },
+}
+
+/// A webhook delivery that ran out of attempts.
+#[derive(Deserialize, Clone, Debug)]
+pub struct WebhookDeadLetter {
+    /// Event ID, also sent as `X-Autherium-Delivery`.
+    pub id: String,
+    pub event: String,
+    pub url: String,
+    pub body: String,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub at: u64,
+}
+
+/// A reseller account and what it has created so far.
+#[derive(Deserialize, Clone, Debug)]
+pub struct Reseller {
+    pub id: String,
+    pub product_ids: Vec<String>,
+    pub max_keys: Option<u64>,
+    pub max_days: Option<u64>,
+    pub keys_created: u64,
+    pub days_created: u64,
+    pub created_at: u64,
+    pub disabled: bool,
+    pub live_licenses: usize,
+    pub unused_licenses: usize,
+    pub banned_licenses: usize,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+enum ResellerCreateResponse {
+    Created { reseller_key: String },
+    Error { error: String },
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+enum ResellersResponse {
+    Resellers { resellers: Vec<Reseller> },
+    Error { error: String },
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct ResellerRevokeRequest<'a> {
+    key: &'a str,
+    id: &'a str,
+    disable: bool,
+    #[serde(flatten)]
+    options: &'a BanOptions,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+enum WebhookDeadLettersResponse {
+    DeadLetters {
+        dead_letters: Vec<WebhookDeadLetter>,
+    },
+    Error {
+        error: String,
+    },
+}
+
 pub fn register_callback(
     instance: Autherium,
     product_id: String,
@@ -56,11 +488,7 @@ pub fn register_callback(
     std::thread::spawn(move || {
         loop {
             match instance.authenticate(&license, product_id.clone()) {
-                Ok(AuthResponse::Success {
-                    license_duration: _,
-                    license_start: _,
-                    time_remaining,
-                }) => {
+                Ok(AuthResponse::Success { time_remaining, .. }) => {
                     if let Some(ref targ) = callback_target {
                         targ.store(time_remaining, std::sync::atomic::Ordering::Relaxed)
                     }
@@ -73,16 +501,65 @@ pub fn register_callback(
 }
 
 impl Autherium {
+    /// Creates a client for the server at `base_url`, which must be an `https://` URL.
     pub fn new(base_url: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let client = reqwest::blocking::ClientBuilder::new().build()?;
+        Self::with_http_allowed(base_url, false)
+    }
+
+    /// Like [`Autherium::new`], but also accepts plain `http://` URLs.
+    ///
+    /// License and API keys are sent in the clear over HTTP, so this is only meant for
+    /// local development and testing.
+    pub fn new_allow_http(base_url: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_http_allowed(base_url, true)
+    }
+
+    fn with_http_allowed(
+        base_url: &str,
+        allow_http: bool,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let scheme = base_url
+            .split_once("://")
+            .map(|(scheme, _)| scheme.to_ascii_lowercase());
+        match scheme.as_deref() {
+            Some("https") => {}
+            Some("http") if allow_http => {}
+            Some("http") => {
+                return Err(
+                    "Refusing insecure http:// base URL, use https:// or Autherium::new_allow_http"
+                        .into(),
+                );
+            }
+            _ => return Err(format!("Unsupported base URL: {}", base_url).into()),
+        }
+        let client = reqwest::blocking::ClientBuilder::new()
+            .https_only(!allow_http)
+            .build()?;
         Ok(Self {
             base_url: base_url.into(),
             hwid: Self::get_hwid()?, // Placeholder HWID
+            client_version: env!("CARGO_PKG_VERSION").to_string(),
             client,
             license_regex: Regex::new(r"^[A-Z0-9]{16}$").unwrap(),
+            session_key: Mutex::new(None),
         })
     }
 
+    /// Reports `version` as the client version instead of the version of this crate.
+    ///
+    /// Pass the application's own version, e.g. `env!("CARGO_PKG_VERSION")`, so the
+    /// server can turn away outdated builds.
+    pub fn with_version(mut self, version: &str) -> Self {
+        self.client_version = version.to_string();
+        self
+    }
+
+    /// Talks to hosted application `app_id` instead of the server's main application.
+    pub fn with_app(mut self, app_id: &str) -> Self {
+        self.base_url = format!("{}/apps/{}", self.base_url.trim_end_matches('/'), app_id);
+        self
+    }
+
     pub fn get_hwid() -> Result<String, Box<dyn std::error::Error>> {
         use machineid_rs::{Encryption, HWIDComponent, IdBuilder};
         Ok(IdBuilder::new(Encryption::SHA256)
@@ -105,10 +582,22 @@ impl Autherium {
             return Err("Invalid license format".into());
         }
 
+        // Fresh for every auth, so an old session key says nothing about the next one
+        let private_key = ring::agreement::EphemeralPrivateKey::generate(
+            &ring::agreement::X25519,
+            &ring::rand::SystemRandom::new(),
+        )
+        .map_err(|_| "Failed to generate a session key")?;
+        let public_key = private_key
+            .compute_public_key()
+            .map_err(|_| "Failed to generate a session key")?;
+
         let request = AuthRequest {
             license: license.clone(),
             hwid: self.hwid.clone(),
             product_id: product_id,
+            client_version: self.client_version.clone(),
+            client_public_key: Some(hex_encode(public_key.as_ref())),
         };
 
         let response = self
@@ -117,6 +606,15 @@ impl Autherium {
             .json(&request)
             .send()?;
 
+        if response.status() == reqwest::StatusCode::UPGRADE_REQUIRED {
+            let UpdateRequiredResponse { update } = response.json()?;
+            return Err(Box::new(UpdateRequired(update)));
+        }
+        if response.status() == reqwest::StatusCode::SERVICE_UNAVAILABLE {
+            let error: serde_json::Value = response.json()?;
+            let message = error["error"].as_str().unwrap_or("Product disabled");
+            return Err(Box::new(ProductDisabled(message.to_string())));
+        }
         let response_body: AuthResponse = response.json()?;
 
         match response_body {
@@ -124,11 +622,35 @@ impl Autherium {
                 license_start,
                 license_duration,
                 time_remaining,
-            } => Ok(AuthResponse::Success {
-                license_start,
-                license_duration,
-                time_remaining,
-            }),
+                session,
+                server_public_key,
+                update,
+            } => {
+                if let (Some(session), Some(server_public_key)) = (&session, &server_public_key) {
+                    let server_key = hex_decode(server_public_key)
+                        .ok_or("The server sent an invalid public key")?;
+                    let mut salt = public_key.as_ref().to_vec();
+                    salt.extend_from_slice(&server_key);
+                    let peer = ring::agreement::UnparsedPublicKey::new(
+                        &ring::agreement::X25519,
+                        server_key,
+                    );
+                    let key = ring::agreement::agree_ephemeral(private_key, &peer, |secret| {
+                        hkdf_sha256(secret, &salt, SESSION_KEY_INFO)
+                    })
+                    .and_then(|key| key)
+                    .map_err(|_| "Failed to agree on a session key")?;
+                    *self.session_key.lock().unwrap() = Some((session.clone(), key));
+                }
+                Ok(AuthResponse::Success {
+                    license_start,
+                    license_duration,
+                    time_remaining,
+                    session,
+                    server_public_key,
+                    update,
+                })
+            }
             AuthResponse::Error { error } => {
                 Err(format!("Authentication failed: {}", error).into())
             }
@@ -163,11 +685,24 @@ impl Autherium {
         }
     }
 
-    pub fn ban_hwid(&self, hwid: &String, key: &String) -> Result<(), Box<dyn std::error::Error>> {
+    /// Bans a HWID. With `cascade`, also bans every license the HWID has used and every
+    /// other HWID those licenses were used on.
+    pub fn ban_hwid(
+        &self,
+        hwid: &String,
+        key: &String,
+        options: &BanOptions,
+        cascade: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         let response = self
             .client
             .post(&format!("{}/api/v1/ban-hwid", self.base_url))
-            .json(&serde_json::json!({ "hwid": hwid, "key": key }))
+            .json(&BanRequest {
+                hwid,
+                key,
+                cascade,
+                options,
+            })
             .send()?;
 
         if response.status().is_success() {
@@ -181,11 +716,12 @@ impl Autherium {
         &self,
         hwid: &String,
         key: &String,
+        unbanned_by: Option<&str>,
     ) -> Result<(), Box<dyn std::error::Error>> {
         let response = self
             .client
             .post(&format!("{}/api/v1/unban-hwid", self.base_url))
-            .json(&serde_json::json!({ "hwid": hwid, "key": key }))
+            .json(&serde_json::json!({ "hwid": hwid, "key": key, "unbanned_by": unbanned_by }))
             .send()?;
 
         if response.status().is_success() {
@@ -195,4 +731,813 @@ impl Autherium {
             Err(format!("Failed to unban HWID: {}", error["error"]).into())
         }
     }
+
+    /// Bans a single IP address or a CIDR range such as `203.0.113.0/24`.
+    pub fn ban_ip(
+        &self,
+        ip: &str,
+        key: &str,
+        options: &BanOptions,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/ban-ip", self.base_url))
+            .json(&IpBanRequest { ip, key, options })
+            .send()?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            let error: serde_json::Value = response.json()?;
+            Err(format!("Failed to ban IP: {}", error["error"]).into())
+        }
+    }
+
+    pub fn unban_ip(
+        &self,
+        ip: &str,
+        key: &str,
+        unbanned_by: Option<&str>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/unban-ip", self.base_url))
+            .json(&serde_json::json!({ "ip": ip, "key": key, "unbanned_by": unbanned_by }))
+            .send()?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            let error: serde_json::Value = response.json()?;
+            Err(format!("Failed to unban IP: {}", error["error"]).into())
+        }
+    }
+
+    pub fn ip_bans(&self, key: &str) -> Result<Vec<IpBan>, Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/ip-bans", self.base_url))
+            .json(&serde_json::json!({ "key": key }))
+            .send()?;
+
+        match response.json::<IpBansResponse>()? {
+            IpBansResponse::Bans { bans } => Ok(bans),
+            IpBansResponse::Error { error } => {
+                Err(format!("Failed to list IP bans: {}", error).into())
+            }
+        }
+    }
+
+    /// Bans a license key, rejecting it even while it has time left.
+    pub fn ban_license(
+        &self,
+        license: &str,
+        key: &str,
+        options: &BanOptions,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/ban-license", self.base_url))
+            .json(&LicenseBanRequest {
+                license,
+                key,
+                options,
+            })
+            .send()?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            let error: serde_json::Value = response.json()?;
+            Err(format!("Failed to ban license: {}", error["error"]).into())
+        }
+    }
+
+    pub fn unban_license(
+        &self,
+        license: &str,
+        key: &str,
+        unbanned_by: Option<&str>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/unban-license", self.base_url))
+            .json(&serde_json::json!({
+                "license": license,
+                "key": key,
+                "unbanned_by": unbanned_by,
+            }))
+            .send()?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            let error: serde_json::Value = response.json()?;
+            Err(format!("Failed to unban license: {}", error["error"]).into())
+        }
+    }
+
+    /// Mints `count` canary licenses tagged with `label`. They authenticate like normal
+    /// licenses, but every use is recorded and, for [`CanaryAction::Ban`], banned.
+    pub fn create_canaries(
+        &self,
+        days: u64,
+        key: &str,
+        product_ids: &[&str],
+        label: &str,
+        action: CanaryAction,
+        count: usize,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/create-canaries", self.base_url))
+            .json(&CanaryCreateRequest {
+                key,
+                days,
+                product_ids,
+                label,
+                action,
+                count,
+            })
+            .send()?;
+
+        match response.json::<CanaryCreateResponse>()? {
+            CanaryCreateResponse::Licenses { licenses } => Ok(licenses),
+            CanaryCreateResponse::Error { error } => {
+                Err(format!("Failed to create canary licenses: {}", error).into())
+            }
+        }
+    }
+
+    /// Lists canary license uses, optionally only those not yet reviewed.
+    pub fn canary_hits(
+        &self,
+        key: &str,
+        unreviewed_only: bool,
+    ) -> Result<Vec<CanaryHit>, Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/canary-hits", self.base_url))
+            .json(&serde_json::json!({ "key": key, "unreviewed_only": unreviewed_only }))
+            .send()?;
+
+        match response.json::<CanaryHitsResponse>()? {
+            CanaryHitsResponse::Hits { hits } => Ok(hits),
+            CanaryHitsResponse::Error { error } => {
+                Err(format!("Failed to list canary hits: {}", error).into())
+            }
+        }
+    }
+
+    /// Marks a canary hit as reviewed.
+    pub fn review_canary_hit(&self, key: &str, id: u64) -> Result<(), Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/canary-hits/review", self.base_url))
+            .json(&serde_json::json!({ "key": key, "id": id }))
+            .send()?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            let error: serde_json::Value = response.json()?;
+            Err(format!("Failed to review canary hit: {}", error["error"]).into())
+        }
+    }
+
+    /// Lists anomaly flags, optionally for one license or only those not yet reviewed.
+    pub fn anomaly_flags(
+        &self,
+        key: &str,
+        license: Option<&str>,
+        unreviewed_only: bool,
+    ) -> Result<Vec<AnomalyFlag>, Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/anomaly-flags", self.base_url))
+            .json(&serde_json::json!({
+                "key": key,
+                "license": license,
+                "unreviewed_only": unreviewed_only,
+            }))
+            .send()?;
+
+        match response.json::<AnomalyFlagsResponse>()? {
+            AnomalyFlagsResponse::Flags { flags } => Ok(flags),
+            AnomalyFlagsResponse::Error { error } => {
+                Err(format!("Failed to list anomaly flags: {}", error).into())
+            }
+        }
+    }
+
+    /// Marks an anomaly flag as reviewed, lifting the suspension it caused if asked to.
+    pub fn review_anomaly_flag(
+        &self,
+        key: &str,
+        id: u64,
+        lift_suspension: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/anomaly-flags/review", self.base_url))
+            .json(&serde_json::json!({
+                "key": key,
+                "id": id,
+                "lift_suspension": lift_suspension,
+            }))
+            .send()?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            let error: serde_json::Value = response.json()?;
+            Err(format!("Failed to review anomaly flag: {}", error["error"]).into())
+        }
+    }
+
+    /// Lists webhook deliveries the server gave up on.
+    pub fn webhook_dead_letters(
+        &self,
+        key: &str,
+    ) -> Result<Vec<WebhookDeadLetter>, Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/webhooks/dead-letters", self.base_url))
+            .json(&serde_json::json!({ "key": key }))
+            .send()?;
+
+        match response.json::<WebhookDeadLettersResponse>()? {
+            WebhookDeadLettersResponse::DeadLetters { dead_letters } => Ok(dead_letters),
+            WebhookDeadLettersResponse::Error { error } => {
+                Err(format!("Failed to list webhook dead letters: {}", error).into())
+            }
+        }
+    }
+
+    /// Queues dead-lettered webhook deliveries again, those of event `id` or all of them.
+    pub fn redeliver_webhooks(
+        &self,
+        key: &str,
+        id: Option<&str>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/webhooks/redeliver", self.base_url))
+            .json(&serde_json::json!({ "key": key, "id": id }))
+            .send()?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            let error: serde_json::Value = response.json()?;
+            Err(format!("Failed to redeliver webhooks: {}", error["error"]).into())
+        }
+    }
+
+    /// Creates a reseller account, returning the key it creates licenses with. The
+    /// server keeps only a hash of it, so it can't be retrieved later.
+    pub fn create_reseller(
+        &self,
+        key: &str,
+        id: &str,
+        product_ids: &[&str],
+        max_keys: Option<u64>,
+        max_days: Option<u64>,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/resellers/create", self.base_url))
+            .json(&serde_json::json!({
+                "key": key,
+                "id": id,
+                "product_ids": product_ids,
+                "max_keys": max_keys,
+                "max_days": max_days,
+            }))
+            .send()?;
+
+        match response.json::<ResellerCreateResponse>()? {
+            ResellerCreateResponse::Created { reseller_key } => Ok(reseller_key),
+            ResellerCreateResponse::Error { error } => {
+                Err(format!("Failed to create reseller: {}", error).into())
+            }
+        }
+    }
+
+    /// Lists reseller accounts with their usage.
+    pub fn resellers(&self, key: &str) -> Result<Vec<Reseller>, Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/resellers", self.base_url))
+            .json(&serde_json::json!({ "key": key }))
+            .send()?;
+
+        match response.json::<ResellersResponse>()? {
+            ResellersResponse::Resellers { resellers } => Ok(resellers),
+            ResellersResponse::Error { error } => {
+                Err(format!("Failed to list resellers: {}", error).into())
+            }
+        }
+    }
+
+    /// Changes a reseller's products, quotas or whether it is disabled. `None` leaves a
+    /// setting as it is.
+    pub fn update_reseller(
+        &self,
+        key: &str,
+        id: &str,
+        product_ids: Option<&[&str]>,
+        max_keys: Option<u64>,
+        max_days: Option<u64>,
+        disabled: Option<bool>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/resellers/update", self.base_url))
+            .json(&serde_json::json!({
+                "key": key,
+                "id": id,
+                "product_ids": product_ids,
+                "max_keys": max_keys,
+                "max_days": max_days,
+                "disabled": disabled,
+            }))
+            .send()?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            let error: serde_json::Value = response.json()?;
+            Err(format!("Failed to update reseller: {}", error["error"]).into())
+        }
+    }
+
+    /// Bans every license a reseller created, and with `disable` the reseller too.
+    pub fn revoke_reseller(
+        &self,
+        key: &str,
+        id: &str,
+        disable: bool,
+        options: &BanOptions,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/resellers/revoke", self.base_url))
+            .json(&ResellerRevokeRequest {
+                key,
+                id,
+                disable,
+                options,
+            })
+            .send()?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            let error: serde_json::Value = response.json()?;
+            Err(format!("Failed to revoke reseller: {}", error["error"]).into())
+        }
+    }
+
+    /// Turns away every client of `product_id` with `message`, or the server's default
+    /// message, until [`Autherium::enable_product`]. With `pause_time` the licenses of
+    /// the product get the downtime back once it is enabled.
+    pub fn disable_product(
+        &self,
+        key: &str,
+        product_id: &str,
+        message: Option<&str>,
+        pause_time: bool,
+        created_by: Option<&str>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/products/disable", self.base_url))
+            .json(&serde_json::json!({
+                "key": key,
+                "product_id": product_id,
+                "message": message,
+                "pause_time": pause_time,
+                "created_by": created_by,
+            }))
+            .send()?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            let error: serde_json::Value = response.json()?;
+            Err(format!("Failed to disable product: {}", error["error"]).into())
+        }
+    }
+
+    pub fn enable_product(
+        &self,
+        key: &str,
+        product_id: &str,
+    ) -> Result<ProductEnabled, Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/products/enable", self.base_url))
+            .json(&serde_json::json!({ "key": key, "product_id": product_id }))
+            .send()?;
+
+        if response.status().is_success() {
+            Ok(response.json()?)
+        } else {
+            let error: serde_json::Value = response.json()?;
+            Err(format!("Failed to enable product: {}", error["error"]).into())
+        }
+    }
+
+    /// Disabled products by product ID.
+    pub fn disabled_products(
+        &self,
+        key: &str,
+    ) -> Result<HashMap<String, DisabledProduct>, Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/products/disabled", self.base_url))
+            .json(&serde_json::json!({ "key": key }))
+            .send()?;
+
+        if response.status().is_success() {
+            let DisabledProductsResponse { products } = response.json()?;
+            Ok(products)
+        } else {
+            let error: serde_json::Value = response.json()?;
+            Err(format!("Failed to list disabled products: {}", error["error"]).into())
+        }
+    }
+
+    /// Fetches the announcements currently shown for `product_id`, most severe first.
+    pub fn announcements(
+        &self,
+        product_id: &str,
+    ) -> Result<Vec<Announcement>, Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .get(format!("{}/api/v1/announcements", self.base_url))
+            .query(&[("product_id", product_id)])
+            .send()?;
+
+        if response.status().is_success() {
+            let AnnouncementsResponse { announcements } = response.json()?;
+            Ok(announcements)
+        } else {
+            let error: serde_json::Value = response.json()?;
+            Err(format!("Failed to fetch announcements: {}", error["error"]).into())
+        }
+    }
+
+    pub fn create_announcement(
+        &self,
+        key: &str,
+        announcement: &NewAnnouncement,
+    ) -> Result<Announcement, Box<dyn std::error::Error>> {
+        self.write_announcement("create", key, None, announcement)
+    }
+
+    /// Replaces the content of announcement `id` with `announcement`.
+    pub fn update_announcement(
+        &self,
+        key: &str,
+        id: &str,
+        announcement: &NewAnnouncement,
+    ) -> Result<Announcement, Box<dyn std::error::Error>> {
+        self.write_announcement("update", key, Some(id), announcement)
+    }
+
+    fn write_announcement(
+        &self,
+        action: &str,
+        key: &str,
+        id: Option<&str>,
+        announcement: &NewAnnouncement,
+    ) -> Result<Announcement, Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/announcements/{}", self.base_url, action))
+            .json(&AnnouncementRequest {
+                key,
+                id,
+                announcement,
+            })
+            .send()?;
+
+        if response.status().is_success() {
+            Ok(response.json()?)
+        } else {
+            let error: serde_json::Value = response.json()?;
+            Err(format!("Failed to {} announcement: {}", action, error["error"]).into())
+        }
+    }
+
+    pub fn delete_announcement(
+        &self,
+        key: &str,
+        id: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/announcements/delete", self.base_url))
+            .json(&serde_json::json!({ "key": key, "id": id }))
+            .send()?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            let error: serde_json::Value = response.json()?;
+            Err(format!("Failed to delete announcement: {}", error["error"]).into())
+        }
+    }
+
+    /// Fetches the remote variables of the product of `session`, which comes from
+    /// [`Autherium::authenticate`].
+    pub fn variables(&self, session: &str) -> Result<Variables, Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .get(format!("{}/api/v1/variables", self.base_url))
+            .bearer_auth(session)
+            .send()?;
+
+        if response.status().is_success() {
+            Ok(response.json()?)
+        } else {
+            let error: serde_json::Value = response.json()?;
+            Err(format!("Failed to fetch variables: {}", error["error"]).into())
+        }
+    }
+
+    /// Sets a remote variable of a product, or with `license` its value for that license
+    /// only.
+    pub fn set_variable(
+        &self,
+        key: &str,
+        product_id: &str,
+        name: &str,
+        value: serde_json::Value,
+        license: Option<&str>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/variables/set", self.base_url))
+            .json(&serde_json::json!({
+                "key": key,
+                "product_id": product_id,
+                "name": name,
+                "value": value,
+                "license": license,
+            }))
+            .send()?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            let error: serde_json::Value = response.json()?;
+            Err(format!("Failed to set variable: {}", error["error"]).into())
+        }
+    }
+
+    /// Deletes a remote variable of a product, or with `license` only its value for that
+    /// license.
+    pub fn delete_variable(
+        &self,
+        key: &str,
+        product_id: &str,
+        name: &str,
+        license: Option<&str>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/v1/variables/delete", self.base_url))
+            .json(&serde_json::json!({
+                "key": key,
+                "product_id": product_id,
+                "name": name,
+                "license": license,
+            }))
+            .send()?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            let error: serde_json::Value = response.json()?;
+            Err(format!("Failed to delete variable: {}", error["error"]).into())
+        }
+    }
+
+    /// Downloads the payload of the licensed product to `path`, the newest version
+    /// unless `version` is given, and checks its SHA-256.
+    ///
+    /// `session` comes from a successful [`Autherium::authenticate`]. The file is
+    /// written to `<path>.part` first, and an interrupted download resumes from there
+    /// on the next call.
+    pub fn download_payload(
+        &self,
+        session: &str,
+        version: Option<&str>,
+        path: &Path,
+    ) -> Result<PayloadInfo, Box<dyn std::error::Error>> {
+        let file_name = path
+            .file_name()
+            .ok_or("The payload path has no file name")?
+            .to_string_lossy();
+        let partial = path.with_file_name(format!("{}.part", file_name));
+        let offset = fs::metadata(&partial).map_or(0, |metadata| metadata.len());
+
+        let mut request = self
+            .client
+            .get(format!("{}/api/v1/payload", self.base_url))
+            .bearer_auth(session)
+            .timeout(PAYLOAD_TIMEOUT);
+        if let Some(version) = version {
+            request = request.query(&[("version", version)]);
+        }
+        if offset > 0 {
+            request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+        }
+        let mut response = request.send()?;
+
+        let append = match response.status() {
+            reqwest::StatusCode::PARTIAL_CONTENT => true,
+            reqwest::StatusCode::OK => false,
+            // The partial file is no shorter than the payload, so it can't be a prefix
+            reqwest::StatusCode::RANGE_NOT_SATISFIABLE => {
+                fs::remove_file(&partial)?;
+                return self.download_payload(session, version, path);
+            }
+            _ => {
+                let error: serde_json::Value = response.json()?;
+                return Err(format!("Failed to download payload: {}", error["error"]).into());
+            }
+        };
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let expected = header("X-Autherium-SHA256").ok_or("The server sent no SHA-256")?;
+        let payload_version = header("X-Autherium-Version").unwrap_or_default();
+
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(&partial)?;
+        io::copy(&mut response, &mut file)?;
+        drop(file);
+
+        let sha256 = Self::sha256_file(&partial)?;
+        if !sha256.eq_ignore_ascii_case(&expected) {
+            fs::remove_file(&partial)?;
+            return Err(format!(
+                "Payload checksum mismatch, expected {} but got {}",
+                expected, sha256
+            )
+            .into());
+        }
+        let size = fs::metadata(&partial)?.len();
+        fs::rename(&partial, path)?;
+        Ok(PayloadInfo {
+            version: payload_version,
+            sha256,
+            size,
+        })
+    }
+
+    /// Downloads the payload of the licensed product encrypted with the session key, and
+    /// decrypts it into `buffer` so the plaintext never touches the disk.
+    ///
+    /// `session` must come from [`Autherium::authenticate`] on this client. `buffer` is
+    /// cleared first, and left empty if the download fails.
+    pub fn download_payload_to(
+        &self,
+        session: &str,
+        version: Option<&str>,
+        buffer: &mut Vec<u8>,
+    ) -> Result<PayloadInfo, Box<dyn std::error::Error>> {
+        buffer.clear();
+        let result = self.download_encrypted(session, version, buffer);
+        if result.is_err() {
+            buffer.clear();
+        }
+        result
+    }
+
+    fn download_encrypted(
+        &self,
+        session: &str,
+        version: Option<&str>,
+        buffer: &mut Vec<u8>,
+    ) -> Result<PayloadInfo, Box<dyn std::error::Error>> {
+        let session_key = self
+            .session_key
+            .lock()
+            .unwrap()
+            .as_ref()
+            .filter(|(token, _)| token == session)
+            .map(|(_, key)| *key)
+            .ok_or("No key was agreed on for this session")?;
+
+        let mut request = self
+            .client
+            .get(format!("{}/api/v1/payload", self.base_url))
+            .bearer_auth(session)
+            .timeout(PAYLOAD_TIMEOUT);
+        if let Some(version) = version {
+            request = request.query(&[("version", version)]);
+        }
+        let mut response = request.query(&[("encrypted", "true")]).send()?;
+        if response.status() != reqwest::StatusCode::OK {
+            let error: serde_json::Value = response.json()?;
+            return Err(format!("Failed to download payload: {}", error["error"]).into());
+        }
+
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        if header("X-Autherium-Encryption").as_deref() != Some("chacha20-poly1305") {
+            return Err("The server sent the payload unencrypted".into());
+        }
+        let expected = header("X-Autherium-SHA256").ok_or("The server sent no SHA-256")?;
+        let payload_version = header("X-Autherium-Version").unwrap_or_default();
+        let salt = header("X-Autherium-Salt")
+            .and_then(|salt| hex_decode(&salt))
+            .ok_or("The server sent no download salt")?;
+        let size = header("X-Autherium-Size")
+            .and_then(|size| size.parse::<u64>().ok())
+            .ok_or("The server sent no payload size")?;
+        let chunk_size = header("X-Autherium-Chunk-Size")
+            .and_then(|size| size.parse::<usize>().ok())
+            .filter(|size| (1..=MAX_CHUNK_SIZE).contains(size))
+            .ok_or("The server sent an invalid chunk size")?;
+
+        let key = hkdf_sha256(&session_key, &salt, DOWNLOAD_KEY_INFO)
+            .and_then(|key| ring::aead::UnboundKey::new(&ring::aead::CHACHA20_POLY1305, &key))
+            .map(ring::aead::LessSafeKey::new)
+            .map_err(|_| "Failed to derive the download key")?;
+        let tag_len = ring::aead::CHACHA20_POLY1305.tag_len();
+        let chunks = size.div_ceil(chunk_size as u64).max(1);
+        buffer.try_reserve(usize::try_from(size)?)?;
+
+        let mut sealed = Vec::with_capacity(chunk_size + tag_len);
+        for index in 0..chunks {
+            let remaining = size - index * chunk_size as u64;
+            let len = remaining.min(chunk_size as u64) as usize;
+            sealed.resize(len + tag_len, 0);
+            response.read_exact(&mut sealed)?;
+            let plaintext = key
+                .open_in_place(
+                    chunk_nonce(index, index + 1 == chunks),
+                    ring::aead::Aad::empty(),
+                    &mut sealed,
+                )
+                .map_err(|_| "Failed to decrypt the payload")?;
+            buffer.extend_from_slice(plaintext);
+        }
+        if response.read(&mut [0; 1])? != 0 {
+            return Err("The payload is longer than the server announced".into());
+        }
+
+        let sha256 = hex_encode(ring::digest::digest(&ring::digest::SHA256, buffer).as_ref());
+        if !sha256.eq_ignore_ascii_case(&expected) {
+            return Err(format!(
+                "Payload checksum mismatch, expected {} but got {}",
+                expected, sha256
+            )
+            .into());
+        }
+        Ok(PayloadInfo {
+            version: payload_version,
+            sha256,
+            size,
+        })
+    }
+
+    /// Whether the file at `path` has the hex SHA-256 `sha256`, e.g. to check a payload
+    /// downloaded earlier before running it.
+    pub fn verify_payload(path: &Path, sha256: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(Self::sha256_file(path)?.eq_ignore_ascii_case(sha256))
+    }
+
+    fn sha256_file(path: &Path) -> io::Result<String> {
+        let mut file = fs::File::open(path)?;
+        let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            context.update(&buffer[..read]);
+        }
+        Ok(hex_encode(context.finish().as_ref()))
+    }
 }
@@ -0,0 +1,165 @@
+use actix_web::{HttpResponse, Responder, Result, error, get, post, web};
+use rand::{Rng, distr::Alphanumeric};
+use std::cmp::Reverse;
+use tracing::info;
+
+use crate::{State, check_key, not_found, storage::DataFile, types::*};
+
+const MAX_TITLE_LEN: usize = 200;
+const MAX_BODY_LEN: usize = 10_000;
+
+fn validate(fields: &AnnouncementFields) -> Result<()> {
+    let title = fields.title.trim();
+    let valid = !title.is_empty()
+        && title.len() <= MAX_TITLE_LEN
+        && fields.body.len() <= MAX_BODY_LEN
+        && fields
+            .starts_at
+            .zip(fields.ends_at)
+            .is_none_or(|(starts_at, ends_at)| starts_at < ends_at);
+    if !valid {
+        return Err(error::InternalError::from_response(
+            "Invalid announcement.",
+            HttpResponse::BadRequest().json(ErrorResponse::new("Invalid announcement.")),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Live announcements for a product, most severe first and then newest first.
+#[get("/announcements")]
+async fn live_announcements(
+    query: web::Query<AnnouncementsQuery>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    let now = unix_now();
+    let mut announcements = state
+        .announcements
+        .read()
+        .unwrap()
+        .values()
+        .filter(|announcement| {
+            announcement.is_live(now) && announcement.targets(query.product_id.as_deref())
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    announcements.sort_by(|a, b| {
+        b.severity.cmp(&a.severity).then_with(|| {
+            b.starts_at
+                .unwrap_or(b.created_at)
+                .cmp(&a.starts_at.unwrap_or(a.created_at))
+        })
+    });
+
+    Ok(HttpResponse::Ok().json(AnnouncementsResponse { announcements }))
+}
+
+#[post("/announcements/create")]
+async fn create_announcement(
+    req: web::Json<AnnouncementCreateRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    check_key(&req.key, &state)?;
+    validate(&req.announcement)?;
+
+    let fields = &req.announcement;
+    let announcement = Announcement {
+        id: rand::rng()
+            .sample_iter(&Alphanumeric)
+            .take(12)
+            .map(char::from)
+            .collect(),
+        title: fields.title.trim().to_string(),
+        body: fields.body.clone(),
+        severity: fields.severity,
+        starts_at: fields.starts_at,
+        ends_at: fields.ends_at,
+        product_ids: fields.product_ids.clone(),
+        created_at: unix_now(),
+    };
+    state
+        .announcements
+        .write()
+        .unwrap()
+        .insert(announcement.id.clone(), announcement.clone());
+    state.schedule_save(DataFile::Announcements);
+    info!(
+        id = %announcement.id,
+        severity = ?announcement.severity,
+        product_ids = ?announcement.product_ids,
+        "Announcement created"
+    );
+
+    Ok(HttpResponse::Created().json(announcement))
+}
+
+#[post("/announcements/update")]
+async fn update_announcement(
+    req: web::Json<AnnouncementUpdateRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    check_key(&req.key, &state)?;
+    validate(&req.announcement)?;
+
+    let fields = &req.announcement;
+    let announcement = {
+        let mut announcements = state.announcements.write().unwrap();
+        let announcement = announcements
+            .get_mut(&req.id)
+            .ok_or_else(|| not_found("Announcement not found."))?;
+        announcement.title = fields.title.trim().to_string();
+        announcement.body = fields.body.clone();
+        announcement.severity = fields.severity;
+        announcement.starts_at = fields.starts_at;
+        announcement.ends_at = fields.ends_at;
+        announcement.product_ids = fields.product_ids.clone();
+        announcement.clone()
+    };
+    state.schedule_save(DataFile::Announcements);
+    info!(id = %req.id, "Announcement updated");
+
+    Ok(HttpResponse::Ok().json(announcement))
+}
+
+#[post("/announcements/delete")]
+async fn delete_announcement(
+    req: web::Json<AnnouncementDeleteRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    check_key(&req.key, &state)?;
+
+    if state
+        .announcements
+        .write()
+        .unwrap()
+        .remove(&req.id)
+        .is_none()
+    {
+        return Err(not_found("Announcement not found."));
+    }
+    state.schedule_save(DataFile::Announcements);
+    info!(id = %req.id, "Announcement deleted");
+
+    Ok(HttpResponse::Ok().json(ErrorResponse::new("Announcement deleted successfully.")))
+}
+
+/// Every announcement, including those that ended or have yet to start.
+#[post("/announcements/list")]
+async fn list_announcements(
+    req: web::Json<AdminRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    check_key(&req.key, &state)?;
+
+    let mut announcements = state
+        .announcements
+        .read()
+        .unwrap()
+        .values()
+        .cloned()
+        .collect::<Vec<_>>();
+    announcements.sort_by_key(|announcement| Reverse(announcement.created_at));
+
+    Ok(HttpResponse::Ok().json(AnnouncementsResponse { announcements }))
+}
@@ -0,0 +1,327 @@
+use actix_web::{HttpResponse, Responder, Result, error, post, web};
+use ipnet::IpNet;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    net::IpAddr,
+    sync::Mutex,
+};
+use tracing::{info, warn};
+
+use crate::{
+    State, config::AnomalyRules, logging::Redacted, metrics::METRICS, storage::DataFile, types::*,
+    webhooks,
+};
+
+/// Most auths remembered per license, so a hammered license can't grow its history
+/// without bound.
+const MAX_EVENTS_PER_LICENSE: usize = 1024;
+
+/// `created_by` of the bans put on suspended licenses.
+const SUSPENDED_BY: &str = "anomaly";
+
+struct AuthEvent {
+    at: u64,
+    ip: Option<IpAddr>,
+    hwid: String,
+}
+
+/// Recent successful auths per license, kept in memory for the anomaly rules.
+#[derive(Default)]
+pub struct AuthHistory {
+    events: Mutex<HashMap<String, VecDeque<AuthEvent>>>,
+}
+
+impl AuthHistory {
+    /// Adds an auth to the history of `license` and checks it against `rules`.
+    fn record(
+        &self,
+        license: &str,
+        event: AuthEvent,
+        keep_secs: u64,
+        rules: &AnomalyRules,
+    ) -> Vec<(AnomalyRule, String)> {
+        let now = event.at;
+        let mut history = self.events.lock().unwrap();
+        let events = history.entry(license.to_string()).or_default();
+        while events.front().is_some_and(|old| old.at + keep_secs < now) {
+            events.pop_front();
+        }
+        if events.len() >= MAX_EVENTS_PER_LICENSE {
+            events.pop_front();
+        }
+        events.push_back(event);
+        evaluate(events, rules, now)
+    }
+
+    /// Forgets auths older than `keep_secs`, and licenses left without any.
+    pub fn prune(&self, now: u64, keep_secs: u64) {
+        self.events.lock().unwrap().retain(|_, events| {
+            events.retain(|event| event.at + keep_secs >= now);
+            !events.is_empty()
+        });
+    }
+}
+
+/// Checks the history of a license, whose newest auth is last, against `rules`.
+fn evaluate(
+    events: &VecDeque<AuthEvent>,
+    rules: &AnomalyRules,
+    now: u64,
+) -> Vec<(AnomalyRule, String)> {
+    let mut findings = Vec::new();
+    let since = |secs: u64| events.iter().filter(move |event| event.at + secs >= now);
+
+    if let Some(max_ips) = rules.max_ips {
+        let ips = since(rules.ips_window_secs)
+            .filter_map(|event| event.ip)
+            .collect::<HashSet<_>>();
+        if ips.len() > max_ips {
+            findings.push((
+                AnomalyRule::DistinctIps,
+                format!("{} IP addresses in {}s", ips.len(), rules.ips_window_secs),
+            ));
+        }
+    }
+
+    if let Some(max_hwids) = rules.max_hwids {
+        let hwids = since(rules.hwids_window_secs)
+            .map(|event| event.hwid.as_str())
+            .collect::<HashSet<_>>();
+        if hwids.len() > max_hwids {
+            findings.push((
+                AnomalyRule::HwidChurn,
+                format!("{} HWIDs in {}s", hwids.len(), rules.hwids_window_secs),
+            ));
+        }
+    }
+
+    // Compare the newest auth with the one before it that has an address. Switching
+    // between IPv4 and IPv6 is normal for dual stack clients, so it is never flagged.
+    if let Some(window) = rules.travel_window_secs
+        && let Some(latest) = events.back()
+        && let Some(ip) = latest.ip
+        && let Some((earlier, earlier_ip)) = events
+            .iter()
+            .rev()
+            .skip(1)
+            .find_map(|event| event.ip.map(|ip| (event, ip)))
+        && latest.at.saturating_sub(earlier.at) <= window
+        && ip.is_ipv4() == earlier_ip.is_ipv4()
+    {
+        let (from, to) = (network(earlier_ip, rules), network(ip, rules));
+        if from != to {
+            findings.push((
+                AnomalyRule::ImpossibleTravel,
+                format!(
+                    "{} to {} in {}s",
+                    from,
+                    to,
+                    latest.at.saturating_sub(earlier.at)
+                ),
+            ));
+        }
+    }
+
+    findings
+}
+
+/// The network `ip` belongs to for the impossible travel rule.
+fn network(ip: IpAddr, rules: &AnomalyRules) -> IpNet {
+    let prefix = match ip {
+        IpAddr::V4(_) => rules.travel_prefix_v4,
+        IpAddr::V6(_) => rules.travel_prefix_v6,
+    };
+    // Prefix lengths are checked when the config is loaded
+    IpNet::new(ip, prefix).unwrap().trunc()
+}
+
+/// How long a rule looks back, and so how long one flag covers.
+fn window(rules: &AnomalyRules, rule: AnomalyRule) -> u64 {
+    match rule {
+        AnomalyRule::DistinctIps => rules.ips_window_secs,
+        AnomalyRule::HwidChurn => rules.hwids_window_secs,
+        AnomalyRule::ImpossibleTravel => rules.travel_window_secs.unwrap_or(0),
+    }
+}
+
+/// Records a successful auth and raises flags for any rule it breaks.
+///
+/// Returns the message to answer with when the license was suspended because of it.
+pub fn observe(
+    state: &State,
+    license: &str,
+    product_id: &str,
+    hwid: &str,
+    ip: Option<IpAddr>,
+    now: u64,
+) -> Option<String> {
+    let config = &state.config.anomaly;
+    if !config.enabled {
+        return None;
+    }
+    let rules = config.rules_for(product_id);
+    let event = AuthEvent {
+        at: now,
+        ip,
+        hwid: hwid.to_string(),
+    };
+    let findings = state
+        .auth_history
+        .record(license, event, config.history_secs(), rules);
+    if findings.is_empty() {
+        return None;
+    }
+
+    let mut raised = Vec::new();
+    {
+        let mut flags = state.anomaly_flags.write().unwrap();
+        for (rule, detail) in findings {
+            // One flag per product, rule and window rather than one per auth
+            let window = window(rules, rule);
+            let already_flagged = flags
+                .iter()
+                .rev()
+                .take_while(|flag| flag.at + window >= now)
+                .any(|flag| {
+                    flag.license == license && flag.product_id == product_id && flag.rule == rule
+                });
+            if already_flagged {
+                continue;
+            }
+            let flag = AnomalyFlag {
+                id: flags.last().map_or(1, |flag| flag.id + 1),
+                license: license.to_string(),
+                product_id: product_id.to_string(),
+                rule,
+                detail,
+                at: now,
+                suspended: rules.auto_suspend,
+                reviewed: false,
+            };
+            flags.push(flag.clone());
+            raised.push(flag);
+        }
+    }
+    if raised.is_empty() {
+        return None;
+    }
+    state.schedule_save(DataFile::AnomalyFlags);
+    for flag in &raised {
+        METRICS.record_anomaly_flag(flag.rule);
+        warn!(
+            license = %Redacted(license),
+            product_id = %product_id,
+            rule = flag.rule.as_str(),
+            detail = %flag.detail,
+            suspended = flag.suspended,
+            "Suspicious license activity"
+        );
+        webhooks::emit(
+            state,
+            WebhookEvent::AnomalyFlagged,
+            serde_json::to_value(flag).unwrap_or_default(),
+        );
+    }
+
+    if !rules.auto_suspend {
+        return None;
+    }
+    let details = BanDetails {
+        reason: Some(format!("Suspicious activity: {}", raised[0].detail)),
+        created_by: Some(SUSPENDED_BY.to_string()),
+        ..BanDetails::permanent(now)
+    };
+    let message = details.message("license");
+    if let Some(suspended) = state.licenses.write().unwrap().get_mut(license) {
+        suspended.ban = Some(details.clone());
+    }
+    state.schedule_save(DataFile::Licenses);
+    webhooks::license_revoked(state, license, &details);
+    Some(message)
+}
+
+#[post("/anomaly-flags")]
+async fn anomaly_flags(
+    req: web::Json<AnomalyFlagsRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    if req.key != state.config.security.api_key {
+        warn!(key = %Redacted(&req.key), "Rejected request with an invalid API key");
+        return Err(error::InternalError::from_response(
+            "Invalid API key.",
+            HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid API key.")),
+        )
+        .into());
+    }
+
+    let flags = state
+        .anomaly_flags
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|flag| req.license.as_ref().is_none_or(|key| flag.license == *key))
+        .filter(|flag| !req.unreviewed_only || !flag.reviewed)
+        .cloned()
+        .collect();
+    Ok(HttpResponse::Ok().json(AnomalyFlagsResponse { flags }))
+}
+
+#[post("/anomaly-flags/review")]
+async fn review_anomaly_flag(
+    req: web::Json<AnomalyReviewRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    if req.key != state.config.security.api_key {
+        warn!(key = %Redacted(&req.key), "Rejected request with an invalid API key");
+        return Err(error::InternalError::from_response(
+            "Invalid API key.",
+            HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid API key.")),
+        )
+        .into());
+    }
+
+    let flag = {
+        let mut flags = state.anomaly_flags.write().unwrap();
+        let Some(flag) = flags.iter_mut().find(|flag| flag.id == req.id) else {
+            return Err(error::InternalError::from_response(
+                "Anomaly flag not found.",
+                HttpResponse::NotFound().json(ErrorResponse::new("Anomaly flag not found.")),
+            )
+            .into());
+        };
+        flag.reviewed = true;
+        flag.clone()
+    };
+    state.schedule_save(DataFile::AnomalyFlags);
+
+    // Only lift suspensions, never a ban an admin placed by hand
+    let mut lifted = false;
+    if req.lift_suspension && flag.suspended {
+        let mut licenses = state.licenses.write().unwrap();
+        if let Some(license) = licenses.get_mut(&flag.license)
+            && license
+                .ban
+                .as_ref()
+                .is_some_and(|ban| ban.created_by.as_deref() == Some(SUSPENDED_BY))
+        {
+            license.ban = None;
+            lifted = true;
+        }
+    }
+    if lifted {
+        state.schedule_save(DataFile::Licenses);
+    }
+    info!(
+        id = req.id,
+        license = %Redacted(&flag.license),
+        suspension_lifted = lifted,
+        "Anomaly flag reviewed"
+    );
+
+    let message = if lifted {
+        "Anomaly flag marked as reviewed and suspension lifted."
+    } else {
+        "Anomaly flag marked as reviewed."
+    };
+    Ok(HttpResponse::Ok().json(ErrorResponse::new(message)))
+}
@@ -0,0 +1,418 @@
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tracing::{info, warn};
+
+use crate::{
+    config::ArchiveConfig,
+    types::{License, unix_now},
+};
+
+/// A license as stored in the archive, one JSON object per line.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ArchivedLicense {
+    pub archived_at: u64,
+    #[serde(flatten)]
+    pub license: License,
+}
+
+/// Append-only JSON-lines archive of expired licenses.
+///
+/// New records go to the active segment at `path`. Once it grows past the size limit or
+/// gets too old it is renamed to `<stem>-<timestamp>.jsonl` (gzipped if configured) and a
+/// fresh active segment is started.
+pub struct Archive {
+    path: PathBuf,
+    config: ArchiveConfig,
+    /// When the first record of the active segment was written.
+    segment_started: Mutex<Option<u64>>,
+}
+
+impl Archive {
+    pub fn new(path: &str, config: &ArchiveConfig) -> Self {
+        Self {
+            path: PathBuf::from(path),
+            config: config.clone(),
+            segment_started: Mutex::new(None),
+        }
+    }
+
+    /// Migrates a legacy archive if there is one and picks up the age of the active segment.
+    pub fn init(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.migrate_legacy()?;
+        let segment_started = self.first_record_time(&self.path)?;
+        *self.segment_started.lock().unwrap() = segment_started;
+        Ok(())
+    }
+
+    /// Appends `licenses` to the active segment, rotating it first if it is due.
+    pub fn append(&self, licenses: &[License]) -> Result<(), Box<dyn std::error::Error>> {
+        if licenses.is_empty() {
+            return Ok(());
+        }
+        let mut segment_started = self.segment_started.lock().unwrap();
+        let now = unix_now();
+        if self.rotation_due(*segment_started, now) {
+            self.rotate(now)?;
+            *segment_started = None;
+        }
+
+        let mut data = Vec::new();
+        for license in licenses {
+            serde_json::to_writer(
+                &mut data,
+                &ArchivedLicense {
+                    archived_at: now,
+                    license: license.clone(),
+                },
+            )?;
+            data.push(b'\n');
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(&data)?;
+        file.sync_data()?;
+        segment_started.get_or_insert(now);
+        Ok(())
+    }
+
+    fn rotation_due(&self, segment_started: Option<u64>, now: u64) -> bool {
+        let size = fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0);
+        if size == 0 {
+            return false;
+        }
+        let too_big = size >= self.config.rotate_max_bytes;
+        let too_old = match (segment_started, self.config.rotate_max_age_days) {
+            (Some(started), Some(days)) => now.saturating_sub(started) >= days * 24 * 60 * 60,
+            _ => false,
+        };
+        too_big || too_old
+    }
+
+    fn rotate(&self, now: u64) -> Result<(), Box<dyn std::error::Error>> {
+        let mut segment = self.segment_path(now, "jsonl");
+        let mut suffix = 1;
+        while segment.exists() || segment.with_extension("jsonl.gz").exists() {
+            segment = self.segment_path(now, &format!("{}.jsonl", suffix));
+            suffix += 1;
+        }
+        fs::rename(&self.path, &segment)?;
+
+        if self.config.compress {
+            let compressed = PathBuf::from(format!("{}.gz", segment.display()));
+            let mut encoder = GzEncoder::new(File::create(&compressed)?, Compression::default());
+            io::copy(&mut File::open(&segment)?, &mut encoder)?;
+            encoder.finish()?.sync_all()?;
+            fs::remove_file(&segment)?;
+            info!(segment = %compressed.display(), "Rotated license archive");
+        } else {
+            info!(segment = %segment.display(), "Rotated license archive");
+        }
+        Ok(())
+    }
+
+    fn segment_path(&self, rotated_at: u64, extension: &str) -> PathBuf {
+        let stem = self
+            .path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| "archive".to_string());
+        self.path
+            .with_file_name(format!("{}-{}.{}", stem, rotated_at, extension))
+    }
+
+    /// Rotated segments, oldest first, with the time they were rotated.
+    fn segments(&self) -> Result<Vec<(u64, PathBuf)>, Box<dyn std::error::Error>> {
+        let dir = match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let prefix = format!(
+            "{}-",
+            self.path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default()
+        );
+        let mut segments = Vec::new();
+        if !dir.exists() {
+            return Ok(segments);
+        }
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let Some(name) = path.file_name().map(|n| n.to_string_lossy().to_string()) else {
+                continue;
+            };
+            let Some(rest) = name.strip_prefix(&prefix) else {
+                continue;
+            };
+            if !(rest.ends_with(".jsonl") || rest.ends_with(".jsonl.gz")) {
+                continue;
+            }
+            let timestamp = rest.split('.').next().and_then(|t| t.parse::<u64>().ok());
+            if let Some(timestamp) = timestamp {
+                segments.push((timestamp, path));
+            }
+        }
+        segments.sort();
+        Ok(segments)
+    }
+
+    fn reader(path: &Path) -> Result<Box<dyn BufRead>, io::Error> {
+        let file = File::open(path)?;
+        if path.extension().is_some_and(|ext| ext == "gz") {
+            Ok(Box::new(BufReader::new(GzDecoder::new(file))))
+        } else {
+            Ok(Box::new(BufReader::new(file)))
+        }
+    }
+
+    /// Calls `visit` with every record of `path`, skipping lines that fail to parse
+    /// instead of giving up on the rest of the segment.
+    fn for_each_record(
+        path: &Path,
+        visit: impl FnMut(ArchivedLicense) -> bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match Self::reader(path) {
+            Ok(reader) => Self::for_each_line(reader, path, visit),
+            // Pruned or rotated away since it was listed
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn for_each_line(
+        reader: Box<dyn BufRead>,
+        path: &Path,
+        mut visit: impl FnMut(ArchivedLicense) -> bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<ArchivedLicense>(&line) {
+                Ok(record) => {
+                    if !visit(record) {
+                        break;
+                    }
+                }
+                Err(e) => warn!(
+                    segment = %path.display(),
+                    line = number + 1,
+                    error = %e,
+                    "Skipping unreadable archive record"
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    fn first_record_time(&self, path: &Path) -> Result<Option<u64>, Box<dyn std::error::Error>> {
+        let mut first = None;
+        Self::for_each_record(path, |record| {
+            first = Some(record.archived_at);
+            false
+        })?;
+        Ok(first)
+    }
+
+    /// Returns archived licenses matching `filter`, newest segment first, up to `limit`.
+    ///
+    /// Only the listing happens under the lock, so appends aren't held up by the scan.
+    /// The active segment is read through a handle opened under it, up to the length it
+    /// had then, which stays valid even if the segment is rotated in the meantime.
+    pub fn search(
+        &self,
+        filter: impl Fn(&ArchivedLicense) -> bool,
+        limit: usize,
+    ) -> Result<Vec<ArchivedLicense>, Box<dyn std::error::Error>> {
+        let (active, segments) = {
+            let _guard = self.segment_started.lock().unwrap();
+            let active = match File::open(&self.path) {
+                Ok(file) => {
+                    let len = file.metadata()?.len();
+                    Some(file.take(len))
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(e) => return Err(e.into()),
+            };
+            (active, self.segments()?)
+        };
+
+        // Newest records first within a segment too
+        let mut found = Vec::new();
+        if let Some(active) = active {
+            let mut matches = Vec::new();
+            Self::for_each_line(Box::new(BufReader::new(active)), &self.path, |record| {
+                if filter(&record) {
+                    matches.push(record);
+                }
+                true
+            })?;
+            found.extend(matches.into_iter().rev());
+        }
+        for (_, path) in segments.into_iter().rev() {
+            if found.len() >= limit {
+                break;
+            }
+            let mut matches = Vec::new();
+            Self::for_each_record(&path, |record| {
+                if filter(&record) {
+                    matches.push(record);
+                }
+                true
+            })?;
+            found.extend(matches.into_iter().rev());
+        }
+        found.truncate(limit);
+        Ok(found)
+    }
+
+    /// Removes every record of the license `key` from the archive, returning the newest.
+    ///
+    /// Segments are searched without the lock and only those holding the license are
+    /// rewritten under it.
+    pub fn take(&self, key: &str) -> Result<Option<ArchivedLicense>, Box<dyn std::error::Error>> {
+        let mut paths = vec![self.path.clone()];
+        paths.extend(self.segments()?.into_iter().rev().map(|(_, path)| path));
+        let mut holding = Vec::new();
+        for path in paths {
+            let mut found = false;
+            Self::for_each_record(&path, |record| {
+                found = record.license.key == key;
+                !found
+            })?;
+            if found {
+                holding.push(path);
+            }
+        }
+
+        let _guard = self.segment_started.lock().unwrap();
+        let mut newest = None;
+        for path in holding {
+            if let Some(record) = Self::remove_records(&path, key)? {
+                newest.get_or_insert(record);
+            }
+        }
+        Ok(newest)
+    }
+
+    /// Rewrites `path` without the records of `key`, returning the last of them.
+    fn remove_records(
+        path: &Path,
+        key: &str,
+    ) -> Result<Option<ArchivedLicense>, Box<dyn std::error::Error>> {
+        let reader = match Self::reader(path) {
+            Ok(reader) => reader,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut kept = Vec::new();
+        let mut removed = None;
+        for line in reader.lines() {
+            let line = line?;
+            match serde_json::from_str::<ArchivedLicense>(&line) {
+                Ok(record) if record.license.key == key => removed = Some(record),
+                // Unreadable lines are kept as they are, like everything else
+                _ => {
+                    kept.extend_from_slice(line.as_bytes());
+                    kept.push(b'\n');
+                }
+            }
+        }
+        if removed.is_none() {
+            return Ok(None);
+        }
+
+        let temp = PathBuf::from(format!("{}.tmp", path.display()));
+        if path.extension().is_some_and(|ext| ext == "gz") {
+            let mut encoder = GzEncoder::new(File::create(&temp)?, Compression::default());
+            encoder.write_all(&kept)?;
+            encoder.finish()?.sync_all()?;
+        } else {
+            let mut file = File::create(&temp)?;
+            file.write_all(&kept)?;
+            file.sync_all()?;
+        }
+        fs::rename(&temp, path)?;
+        Ok(removed)
+    }
+
+    /// Deletes rotated segments older than `retention_secs`, returning how many records
+    /// they held. The active segment is never pruned.
+    pub fn prune(&self, retention_secs: u64) -> Result<usize, Box<dyn std::error::Error>> {
+        // Rotation only ever adds segments, so the listing stays good without the lock
+        let segments = {
+            let _guard = self.segment_started.lock().unwrap();
+            self.segments()?
+        };
+        let cutoff = unix_now().saturating_sub(retention_secs);
+        let mut purged = 0;
+        for (rotated_at, path) in segments {
+            if rotated_at >= cutoff {
+                continue;
+            }
+            let mut records = 0;
+            Self::for_each_record(&path, |_| {
+                records += 1;
+                true
+            })?;
+            match fs::remove_file(&path) {
+                Ok(()) => {}
+                // Another prune got to it first
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            }
+            purged += records;
+            info!(segment = %path.display(), "Removed archive segment past retention");
+        }
+        Ok(purged)
+    }
+
+    /// Converts the JSON array written by older versions into the active segment.
+    ///
+    /// Looks at the active path itself and at the same name with a `.json` extension,
+    /// the legacy default. The legacy file is kept with a `.migrated` suffix.
+    fn migrate_legacy(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut candidates = vec![self.path.clone()];
+        let legacy = self.path.with_extension("json");
+        if legacy != self.path {
+            candidates.push(legacy);
+        }
+
+        for candidate in candidates {
+            if !candidate.is_file() {
+                continue;
+            }
+            let mut data = String::new();
+            File::open(&candidate)?.read_to_string(&mut data)?;
+            if !data.trim_start().starts_with('[') {
+                continue;
+            }
+            let licenses: Vec<License> = serde_json::from_str(&data).map_err(|e| {
+                format!(
+                    "legacy archive {} is not valid JSON, fix or move it aside: {}",
+                    candidate.display(),
+                    e
+                )
+            })?;
+            let migrated = PathBuf::from(format!("{}.migrated", candidate.display()));
+            fs::rename(&candidate, &migrated)?;
+            self.append(&licenses)?;
+            info!(
+                from = %candidate.display(),
+                records = licenses.len(),
+                "Migrated legacy license archive"
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,204 @@
+use actix_web::{HttpResponse, Responder, Result, error, post, web};
+use std::net::IpAddr;
+use tracing::{info, warn};
+
+use crate::{State, logging::Redacted, metrics::METRICS, storage::DataFile, types::*, webhooks};
+
+/// Most canary licenses minted by one request.
+const MAX_CANARIES_PER_REQUEST: usize = 1000;
+
+/// Records a use of a canary license and applies its action.
+///
+/// The caller still answers the client as if the license were genuine, so nothing
+/// tells a cracker the key was planted.
+pub fn record_hit(
+    state: &State,
+    license: &str,
+    canary: &Canary,
+    product_id: &str,
+    hwid: &str,
+    ip: Option<IpAddr>,
+) {
+    let now = unix_now();
+    {
+        let mut hits = state.canary_hits.write().unwrap();
+        let id = hits.last().map_or(1, |hit| hit.id + 1);
+        hits.push(CanaryHit {
+            id,
+            license: license.to_string(),
+            label: canary.label.clone(),
+            action: canary.action,
+            product_id: product_id.to_string(),
+            hwid: hwid.to_string(),
+            ip,
+            at: now,
+            reviewed: false,
+        });
+    }
+    state.schedule_save(DataFile::CanaryHits);
+    METRICS.record_canary_hit();
+    warn!(
+        license = %Redacted(license),
+        label = %canary.label,
+        action = ?canary.action,
+        hwid = %hwid,
+        ip = ?ip,
+        "Canary license used"
+    );
+
+    if canary.action == CanaryAction::Ban {
+        let details = BanDetails {
+            reason: Some(format!("Used canary license from {}", canary.label)),
+            created_by: Some("canary".to_string()),
+            ..BanDetails::permanent(now)
+        };
+        state.banned_hwids.write().unwrap().insert(
+            hwid.to_string(),
+            HwidBan {
+                hwid: hwid.to_string(),
+                details: details.clone(),
+            },
+        );
+        state.schedule_save(DataFile::BannedHwids);
+        webhooks::hwid_banned(state, hwid, &details);
+        if let Some(ip) = ip {
+            let network = ip.into();
+            let mut banned_ips = state.banned_ips.write().unwrap();
+            banned_ips.retain(|ban| ban.network != network);
+            banned_ips.push(IpBan { network, details });
+            drop(banned_ips);
+            state.schedule_save(DataFile::BannedIps);
+        }
+    }
+}
+
+#[post("/create-canaries")]
+async fn create_canaries(
+    req: web::Json<CanaryCreateRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    if req.key != state.config.security.api_key {
+        warn!(key = %Redacted(&req.key), "Rejected request with an invalid API key");
+        return Err(error::InternalError::from_response(
+            "Invalid API key.",
+            HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid API key.")),
+        )
+        .into());
+    }
+
+    if req.days > state.config.max_days() {
+        return Err(error::InternalError::from_response(
+            "Invalid number of days.",
+            HttpResponse::BadRequest().json(ErrorResponse::new("Invalid number of days.")),
+        )
+        .into());
+    }
+
+    if req.count == 0 || req.count > MAX_CANARIES_PER_REQUEST {
+        return Err(error::InternalError::from_response(
+            "Invalid number of canary licenses.",
+            HttpResponse::BadRequest()
+                .json(ErrorResponse::new("Invalid number of canary licenses.")),
+        )
+        .into());
+    }
+
+    let mut created = Vec::new();
+    {
+        let mut licenses = state.licenses.write().unwrap();
+        for _ in 0..req.count {
+            let Some(key) = state.generate_key(&licenses) else {
+                break;
+            };
+            let mut license = License::new(key.clone(), &req.product_ids).set_days(req.days);
+            license.canary = Some(Canary {
+                label: req.label.clone(),
+                action: req.action,
+            });
+            licenses.insert(license);
+            created.push(key);
+        }
+    }
+
+    // Save to file after modification
+    if !created.is_empty() {
+        state.schedule_save(DataFile::Licenses);
+    }
+    if created.len() < req.count {
+        return Err(error::InternalError::from_response(
+            "Failed to generate a unique license key.",
+            HttpResponse::InternalServerError().json(ErrorResponse::new(
+                "Failed to generate a unique license key.",
+            )),
+        )
+        .into());
+    }
+    info!(
+        count = created.len(),
+        label = %req.label,
+        action = ?req.action,
+        product_ids = ?req.product_ids,
+        "Canary licenses created"
+    );
+
+    Ok(HttpResponse::Created().json(CanaryCreateResponse { licenses: created }))
+}
+
+#[post("/canary-hits")]
+async fn canary_hits(
+    req: web::Json<CanaryHitsRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    if req.key != state.config.security.api_key {
+        warn!(key = %Redacted(&req.key), "Rejected request with an invalid API key");
+        return Err(error::InternalError::from_response(
+            "Invalid API key.",
+            HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid API key.")),
+        )
+        .into());
+    }
+
+    let hits = state
+        .canary_hits
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|hit| req.label.as_ref().is_none_or(|label| hit.label == *label))
+        .filter(|hit| !req.unreviewed_only || !hit.reviewed)
+        .cloned()
+        .collect();
+    Ok(HttpResponse::Ok().json(CanaryHitsResponse { hits }))
+}
+
+#[post("/canary-hits/review")]
+async fn review_canary_hit(
+    req: web::Json<CanaryReviewRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    if req.key != state.config.security.api_key {
+        warn!(key = %Redacted(&req.key), "Rejected request with an invalid API key");
+        return Err(error::InternalError::from_response(
+            "Invalid API key.",
+            HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid API key.")),
+        )
+        .into());
+    }
+
+    {
+        let mut hits = state.canary_hits.write().unwrap();
+        let Some(hit) = hits.iter_mut().find(|hit| hit.id == req.id) else {
+            return Err(error::InternalError::from_response(
+                "Canary hit not found.",
+                HttpResponse::NotFound().json(ErrorResponse::new("Canary hit not found.")),
+            )
+            .into());
+        };
+        hit.reviewed = true;
+    }
+
+    // Save to file after modification
+    state.schedule_save(DataFile::CanaryHits);
+    info!(id = req.id, "Canary hit reviewed");
+
+    Ok(HttpResponse::Ok().json(ErrorResponse::new("Canary hit marked as reviewed.")))
+}
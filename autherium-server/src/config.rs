@@ -0,0 +1,1442 @@
+use actix_web::http::header::HeaderName;
+use ipnet::IpNet;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use crate::{storage, types::WebhookEvent, versions::Version};
+
+const DEFAULT_CONFIG_FILE: &str = "./config/autherium.toml";
+const DEFAULT_API_KEY: &str = "super_secret_key";
+const LOG_LEVELS: [&str; 5] = ["trace", "debug", "info", "warn", "error"];
+
+const USAGE: &str = "Usage: autherium-server [--config <path>] [--print-default-config]
+
+Options:
+  -c, --config <path>         Path to the TOML config file (default: ./config/autherium.toml if present)
+      --print-default-config  Print the default configuration and exit
+  -h, --help                  Print this help and exit";
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub tls: TlsConfig,
+    pub storage: StorageConfig,
+    pub archive: ArchiveConfig,
+    pub limits: LimitsConfig,
+    pub keys: KeyPolicy,
+    pub maintenance: MaintenanceConfig,
+    pub logging: LoggingConfig,
+    pub security: SecurityConfig,
+    pub anomaly: AnomalyConfig,
+    pub webhooks: WebhooksConfig,
+    pub discord: DiscordConfig,
+    pub payments: PaymentsConfig,
+    /// Client version requirements by product ID.
+    pub versions: BTreeMap<String, VersionPolicy>,
+    pub sessions: SessionsConfig,
+    pub payloads: PayloadsConfig,
+    /// Further applications hosted by this server, by ID, each with the path of its
+    /// own config file. See [`AppConfig`].
+    pub apps: BTreeMap<String, String>,
+}
+
+/// Client versions of a product, as reported in `AuthRequest::client_version`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct VersionPolicy {
+    /// Older clients are refused with an update required error.
+    pub min_version: Option<String>,
+    /// Older clients are told an update is available.
+    pub latest_version: Option<String>,
+    /// Where to get the latest version.
+    pub download_url: Option<String>,
+    pub changelog: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionsConfig {
+    /// How long the session handed out by a successful auth stays valid.
+    pub ttl_secs: u64,
+}
+
+impl Default for SessionsConfig {
+    fn default() -> Self {
+        Self { ttl_secs: 60 * 60 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct PayloadsConfig {
+    /// Product downloads, laid out as `<dir>/<product id>/<version>/<file>`. Hosted
+    /// applications left on the default get `payloads` next to their licenses file.
+    pub dir: String,
+    /// Downloads one session may start. Resuming one it started with a range request is
+    /// free.
+    pub max_downloads_per_session: u32,
+    /// Refuse unencrypted downloads, so payloads never leave the server in the clear.
+    pub require_encryption: bool,
+}
+
+impl Default for PayloadsConfig {
+    fn default() -> Self {
+        Self {
+            dir: "./config/payloads".to_string(),
+            max_downloads_per_session: 3,
+            require_encryption: false,
+        }
+    }
+}
+
+/// Config file of a hosted application.
+///
+/// Holds only what an application keeps to itself: its admin key, data files, bans,
+/// webhooks and so on. Listeners, TLS and logging are shared by every application and
+/// come from the main config.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppConfig {
+    pub storage: StorageConfig,
+    pub archive: ArchiveConfig,
+    pub limits: LimitsConfig,
+    pub keys: KeyPolicy,
+    pub maintenance: MaintenanceConfig,
+    pub security: SecurityConfig,
+    pub anomaly: AnomalyConfig,
+    pub webhooks: WebhooksConfig,
+    pub discord: DiscordConfig,
+    pub payments: PaymentsConfig,
+    pub versions: BTreeMap<String, VersionPolicy>,
+    pub sessions: SessionsConfig,
+    pub payloads: PayloadsConfig,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Plain HTTP addresses to listen on, as `ip:port`. May be empty when TLS is enabled.
+    pub bind: Vec<String>,
+    /// Number of worker threads, defaults to the number of CPU cores.
+    pub workers: Option<usize>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: vec!["0.0.0.0:8080".to_string()],
+            workers: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlainHttp {
+    /// Answer plain HTTP requests as usual.
+    Serve,
+    /// Redirect plain HTTP requests to the first HTTPS listener.
+    Redirect,
+    /// Reject plain HTTP requests with 403, except the health endpoints.
+    Refuse,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    pub enabled: bool,
+    /// HTTPS addresses to listen on, as `ip:port`.
+    pub bind: Vec<String>,
+    /// PEM file holding the certificate chain.
+    pub cert_file: String,
+    /// PEM file holding the private key.
+    pub key_file: String,
+    /// How often to check the certificate files for changes.
+    pub reload_interval_secs: u64,
+    /// What to do with requests arriving on the plain `server.bind` listeners.
+    pub plain_http: PlainHttp,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: vec!["0.0.0.0:8443".to_string()],
+            cert_file: "./config/tls/cert.pem".to_string(),
+            key_file: "./config/tls/key.pem".to_string(),
+            reload_interval_secs: 60,
+            plain_http: PlainHttp::Serve,
+        }
+    }
+}
+
+impl TlsConfig {
+    /// Port of the first HTTPS listener, used as the redirect target.
+    pub fn https_port(&self) -> u16 {
+        self.bind
+            .first()
+            .and_then(|addr| addr.parse::<SocketAddr>().ok())
+            .map(|addr| addr.port())
+            .unwrap_or(443)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub licenses_file: String,
+    pub banned_hwids_file: String,
+    pub banned_ips_file: String,
+    pub canary_hits_file: String,
+    pub anomaly_flags_file: String,
+    /// Webhook deliveries still being retried and those that gave up.
+    pub webhook_outbox_file: String,
+    /// Orders received from payment providers, for idempotency and refunds.
+    pub orders_file: String,
+    pub resellers_file: String,
+    /// Remote variables of each product.
+    pub variables_file: String,
+    pub announcements_file: String,
+    /// Products switched off with `/products/disable`.
+    pub disabled_products_file: String,
+    pub archive_file: String,
+    /// Milliseconds to wait after a change before writing, so bursts are saved once.
+    pub save_debounce_ms: u64,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            licenses_file: "./config/licenses.json".to_string(),
+            banned_hwids_file: "./config/banned_hwids.json".to_string(),
+            banned_ips_file: "./config/banned_ips.json".to_string(),
+            canary_hits_file: "./config/canary_hits.json".to_string(),
+            anomaly_flags_file: "./config/anomaly_flags.json".to_string(),
+            webhook_outbox_file: "./config/webhook_outbox.json".to_string(),
+            orders_file: "./config/orders.json".to_string(),
+            resellers_file: "./config/resellers.json".to_string(),
+            variables_file: "./config/variables.json".to_string(),
+            announcements_file: "./config/announcements.json".to_string(),
+            disabled_products_file: "./config/disabled_products.json".to_string(),
+            archive_file: "./config/expired_licenses.jsonl".to_string(),
+            save_debounce_ms: 200,
+        }
+    }
+}
+
+impl StorageConfig {
+    /// Every data file the server reads and writes.
+    pub fn files(&self) -> [&str; 12] {
+        [
+            &self.licenses_file,
+            &self.banned_hwids_file,
+            &self.banned_ips_file,
+            &self.canary_hits_file,
+            &self.anomaly_flags_file,
+            &self.webhook_outbox_file,
+            &self.orders_file,
+            &self.resellers_file,
+            &self.variables_file,
+            &self.announcements_file,
+            &self.disabled_products_file,
+            &self.archive_file,
+        ]
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArchiveConfig {
+    /// Rotate the active archive segment once it reaches this many bytes.
+    pub rotate_max_bytes: u64,
+    /// Rotate the active archive segment once its first record is this many days old.
+    pub rotate_max_age_days: Option<u64>,
+    /// Gzip rotated segments.
+    pub compress: bool,
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            rotate_max_bytes: 64 * 1024 * 1024,
+            rotate_max_age_days: Some(30),
+            compress: true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Maximum size in bytes of a JSON request body.
+    pub json_payload_limit: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            json_payload_limit: 4096,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyPolicy {
+    /// How many times to retry generating a key that collides with an existing one.
+    pub regen_limit: u32,
+    /// Longest license that can be created, in days. Unlimited when unset.
+    pub max_days: Option<u64>,
+}
+
+impl Default for KeyPolicy {
+    fn default() -> Self {
+        Self {
+            regen_limit: 100,
+            max_days: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct MaintenanceConfig {
+    /// Seconds between background sweeps.
+    pub interval_secs: u64,
+    /// Days to keep rotated archive segments. Kept forever when unset.
+    pub archive_retention_days: Option<u64>,
+    /// Shown to clients of a product disabled without a message of its own.
+    pub disabled_product_message: String,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 300,
+            archive_retention_days: None,
+            disabled_product_message:
+                "This product is down for maintenance, please try again later.".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// One of `trace`, `debug`, `info`, `warn` or `error`.
+    pub level: String,
+    pub format: LogFormat,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines.
+    Text,
+    /// One JSON object per line, for log shippers.
+    Json,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecurityConfig {
+    /// Key required by the admin endpoints.
+    pub api_key: String,
+    /// Refuse to start while `api_key` is still the built-in default.
+    pub require_custom_api_key: bool,
+    /// Bearer token required to scrape `/metrics`. Open when unset.
+    pub metrics_token: Option<String>,
+    /// Header carrying the client address when behind a reverse proxy, e.g.
+    /// `X-Forwarded-For`. The connection address is used when unset.
+    pub trusted_proxy_header: Option<String>,
+    /// Proxies allowed to set `trusted_proxy_header`.
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        Self {
+            api_key: DEFAULT_API_KEY.to_string(),
+            require_custom_api_key: false,
+            metrics_token: None,
+            trusted_proxy_header: None,
+            trusted_proxies: vec!["127.0.0.1/32".parse().unwrap(), "::1/128".parse().unwrap()],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnomalyConfig {
+    /// Keep an auth history per license and check it against the rules.
+    pub enabled: bool,
+    pub rules: AnomalyRules,
+    /// Rules for specific product IDs, replacing `rules` for auths of that product.
+    pub products: HashMap<String, AnomalyRules>,
+}
+
+impl Default for AnomalyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            rules: AnomalyRules::default(),
+            products: HashMap::new(),
+        }
+    }
+}
+
+impl AnomalyConfig {
+    /// The rules that apply to auths for `product_id`.
+    pub fn rules_for(&self, product_id: &str) -> &AnomalyRules {
+        self.products.get(product_id).unwrap_or(&self.rules)
+    }
+
+    /// How long auth history has to be kept to evaluate every rule.
+    pub fn history_secs(&self) -> u64 {
+        std::iter::once(&self.rules)
+            .chain(self.products.values())
+            .map(AnomalyRules::history_secs)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Thresholds for flagging a license. A rule is off when its threshold is unset.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnomalyRules {
+    /// Flag a license used from more than this many distinct IP addresses within
+    /// `ips_window_secs`.
+    pub max_ips: Option<usize>,
+    pub ips_window_secs: u64,
+    /// Flag a license used on more than this many distinct HWIDs within
+    /// `hwids_window_secs`.
+    pub max_hwids: Option<usize>,
+    pub hwids_window_secs: u64,
+    /// Flag a license used from two different networks less than this many seconds
+    /// apart. Networks are compared by their first `travel_prefix_v4` or
+    /// `travel_prefix_v6` bits.
+    pub travel_window_secs: Option<u64>,
+    pub travel_prefix_v4: u8,
+    pub travel_prefix_v6: u8,
+    /// Ban a flagged license until an admin reviews the flag.
+    pub auto_suspend: bool,
+}
+
+impl Default for AnomalyRules {
+    fn default() -> Self {
+        Self {
+            max_ips: Some(5),
+            ips_window_secs: 60 * 60,
+            max_hwids: Some(3),
+            hwids_window_secs: 24 * 60 * 60,
+            travel_window_secs: Some(5 * 60),
+            travel_prefix_v4: 16,
+            travel_prefix_v6: 32,
+            auto_suspend: false,
+        }
+    }
+}
+
+impl AnomalyRules {
+    fn history_secs(&self) -> u64 {
+        let mut secs = 0;
+        if self.max_ips.is_some() {
+            secs = secs.max(self.ips_window_secs);
+        }
+        if self.max_hwids.is_some() {
+            secs = secs.max(self.hwids_window_secs);
+        }
+        secs.max(self.travel_window_secs.unwrap_or(0))
+    }
+
+    fn validate(&self, section: &str, errors: &mut Vec<String>) {
+        if self.max_ips == Some(0) {
+            errors.push(format!("{}.max_ips must be greater than 0", section));
+        }
+        if self.max_hwids == Some(0) {
+            errors.push(format!("{}.max_hwids must be greater than 0", section));
+        }
+        if self.ips_window_secs == 0 {
+            errors.push(format!(
+                "{}.ips_window_secs must be greater than 0",
+                section
+            ));
+        }
+        if self.hwids_window_secs == 0 {
+            errors.push(format!(
+                "{}.hwids_window_secs must be greater than 0",
+                section
+            ));
+        }
+        if self.travel_window_secs == Some(0) {
+            errors.push(format!(
+                "{}.travel_window_secs must be greater than 0",
+                section
+            ));
+        }
+        if self.travel_prefix_v4 > 32 {
+            errors.push(format!("{}.travel_prefix_v4 must be at most 32", section));
+        }
+        if self.travel_prefix_v6 > 128 {
+            errors.push(format!("{}.travel_prefix_v6 must be at most 128", section));
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhooksConfig {
+    pub endpoints: Vec<WebhookEndpoint>,
+    /// Delivery attempts before an event is moved to the dead-letter queue.
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled after every further failure.
+    pub initial_backoff_secs: u64,
+    pub max_backoff_secs: u64,
+    /// Seconds to wait for an endpoint to answer.
+    pub timeout_secs: u64,
+    /// Oldest dead letters are dropped beyond this many.
+    pub max_dead_letters: usize,
+    /// Send `auth.failure_spike` when more than this many auths fail within
+    /// `failure_spike_window_secs`.
+    pub failure_spike_threshold: Option<u64>,
+    pub failure_spike_window_secs: u64,
+}
+
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        Self {
+            endpoints: Vec::new(),
+            max_attempts: 8,
+            initial_backoff_secs: 5,
+            max_backoff_secs: 60 * 60,
+            timeout_secs: 10,
+            max_dead_letters: 1000,
+            failure_spike_threshold: Some(100),
+            failure_spike_window_secs: 60,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookEndpoint {
+    pub url: String,
+    /// Key for the `X-Autherium-Signature` HMAC.
+    pub secret: String,
+    /// Events sent to this endpoint, every event when empty.
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+}
+
+impl WebhookEndpoint {
+    pub fn wants(&self, event: WebhookEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+}
+
+/// Event notifications posted to Discord channel webhooks as embeds. Deliveries share
+/// the retry and dead-letter settings of `[webhooks]`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiscordConfig {
+    /// Channel for events without a product, and for products without a channel of
+    /// their own.
+    pub default_url: Option<String>,
+    /// Channel webhook URLs by product ID.
+    pub products: HashMap<String, String>,
+    /// Name the messages are posted under.
+    pub username: String,
+    pub events: Vec<WebhookEvent>,
+    /// Embed per event. `{name}` is replaced with the event field `name`, nested fields
+    /// are reached with dots, e.g. `{ban.reason}`. `{license_redacted}` and
+    /// `{licenses_redacted}` show keys masked like the logs do; `{license}` and
+    /// `{licenses}` post them in full to everyone who can read the channel.
+    pub templates: BTreeMap<WebhookEvent, DiscordTemplate>,
+}
+
+impl Default for DiscordConfig {
+    fn default() -> Self {
+        Self {
+            default_url: None,
+            products: HashMap::new(),
+            username: "Autherium".to_string(),
+            events: vec![
+                WebhookEvent::LicenseActivated,
+                WebhookEvent::LicenseExpired,
+                WebhookEvent::LicenseRevoked,
+                WebhookEvent::HwidBanned,
+                WebhookEvent::AnomalyFlagged,
+            ],
+            templates: DiscordTemplate::defaults(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DiscordTemplate {
+    pub title: String,
+    pub description: String,
+    /// Embed color as `0xRRGGBB`.
+    #[serde(default)]
+    pub color: u32,
+}
+
+impl DiscordTemplate {
+    fn new(title: &str, description: &str, color: u32) -> Self {
+        Self {
+            title: title.to_string(),
+            description: description.to_string(),
+            color,
+        }
+    }
+
+    /// Built-in templates, also used for events missing from `discord.templates`.
+    pub fn defaults() -> BTreeMap<WebhookEvent, DiscordTemplate> {
+        BTreeMap::from([
+            (
+                WebhookEvent::LicenseCreated,
+                Self::new(
+                    "License created",
+                    "`{license_redacted}` created for {product_ids}",
+                    0x3498db,
+                ),
+            ),
+            (
+                WebhookEvent::LicenseActivated,
+                Self::new(
+                    "License activated",
+                    "`{license_redacted}` activated for **{product_id}** on HWID `{hwid}`",
+                    0x2ecc71,
+                ),
+            ),
+            (
+                WebhookEvent::LicenseExpired,
+                Self::new(
+                    "License expired",
+                    "`{license_redacted}` for {product_ids} expired",
+                    0x95a5a6,
+                ),
+            ),
+            (
+                WebhookEvent::LicenseRevoked,
+                Self::new(
+                    "License banned",
+                    "`{license_redacted}` for {product_ids} was banned by {ban.created_by}: {ban.reason}",
+                    0xe74c3c,
+                ),
+            ),
+            (
+                WebhookEvent::HwidBanned,
+                Self::new(
+                    "HWID banned",
+                    "`{hwid}` was banned by {ban.created_by}: {ban.reason}",
+                    0xe74c3c,
+                ),
+            ),
+            (
+                WebhookEvent::AnomalyFlagged,
+                Self::new(
+                    "Suspicious activity",
+                    "`{license_redacted}` on **{product_id}**: {detail} ({rule})",
+                    0xe67e22,
+                ),
+            ),
+            (
+                WebhookEvent::AuthFailureSpike,
+                Self::new(
+                    "Auth failure spike",
+                    "{failures} failed auths since <t:{since}:T>",
+                    0xe67e22,
+                ),
+            ),
+            (
+                WebhookEvent::OrderCompleted,
+                Self::new(
+                    "Order completed",
+                    "Order `{order_id}` ({sku}) paid through {provider}: {licenses_redacted}",
+                    0x2ecc71,
+                ),
+            ),
+            (
+                WebhookEvent::OrderRefunded,
+                Self::new(
+                    "Order refunded",
+                    "Order `{order_id}` ({sku}) was refunded, banned {licenses_redacted}",
+                    0xe74c3c,
+                ),
+            ),
+        ])
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct PaymentsConfig {
+    /// Signing secret of the Stripe webhook endpoint (`whsec_...`). Stripe events are
+    /// refused when unset.
+    pub stripe_webhook_secret: Option<String>,
+    /// Secret for the generic purchase webhook, signed like outgoing webhooks. Refused
+    /// when unset.
+    pub generic_webhook_secret: Option<String>,
+    /// Reject signatures made longer ago than this, to stop replays.
+    pub signature_tolerance_secs: u64,
+    /// What each SKU buys. Stripe checkouts name theirs in the `sku` metadata field.
+    pub skus: BTreeMap<String, Sku>,
+}
+
+impl Default for PaymentsConfig {
+    fn default() -> Self {
+        Self {
+            stripe_webhook_secret: None,
+            generic_webhook_secret: None,
+            signature_tolerance_secs: 5 * 60,
+            skus: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Sku {
+    pub product_ids: Vec<String>,
+    pub days: u64,
+}
+
+pub enum Command {
+    /// Run with the main config and those of the hosted applications.
+    Run(Box<Config>, Vec<(String, Config)>),
+    Exit,
+}
+
+/// Keys of each section that [`Config::apply_env`] takes from the environment, for the
+/// header of the default config.
+const ENV_OVERRIDES: &[(&str, &[&str])] = &[
+    ("server", &["bind", "workers"]),
+    (
+        "tls",
+        &[
+            "enabled",
+            "bind",
+            "cert_file",
+            "key_file",
+            "reload_interval_secs",
+            "plain_http",
+        ],
+    ),
+    (
+        "storage",
+        &[
+            "licenses_file",
+            "banned_hwids_file",
+            "banned_ips_file",
+            "canary_hits_file",
+            "anomaly_flags_file",
+            "webhook_outbox_file",
+            "orders_file",
+            "resellers_file",
+            "variables_file",
+            "announcements_file",
+            "disabled_products_file",
+            "archive_file",
+            "save_debounce_ms",
+        ],
+    ),
+    (
+        "archive",
+        &["rotate_max_bytes", "rotate_max_age_days", "compress"],
+    ),
+    ("limits", &["json_payload_limit"]),
+    ("keys", &["regen_limit", "max_days"]),
+    (
+        "maintenance",
+        &[
+            "interval_secs",
+            "archive_retention_days",
+            "disabled_product_message",
+        ],
+    ),
+    ("logging", &["level", "format"]),
+    (
+        "security",
+        &[
+            "api_key",
+            "require_custom_api_key",
+            "metrics_token",
+            "trusted_proxy_header",
+            "trusted_proxies",
+        ],
+    ),
+    ("anomaly", &["enabled", "auto_suspend"]),
+    ("webhooks", &["max_attempts", "timeout_secs"]),
+    ("sessions", &["ttl_secs"]),
+    (
+        "payloads",
+        &["dir", "max_downloads_per_session", "require_encryption"],
+    ),
+    (
+        "payments",
+        &["stripe_webhook_secret", "generic_webhook_secret"],
+    ),
+];
+
+impl Config {
+    /// Parses the command line, then loads, overrides and validates the config it points to.
+    pub fn from_args() -> Result<Command, Box<dyn std::error::Error>> {
+        let mut args = std::env::args().skip(1);
+        let mut path = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-c" | "--config" => {
+                    path = Some(args.next().ok_or("--config requires a path")?);
+                }
+                "--print-default-config" => {
+                    print!("{}", Self::default_toml()?);
+                    return Ok(Command::Exit);
+                }
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    return Ok(Command::Exit);
+                }
+                other => return Err(format!("unknown argument `{}`\n\n{}", other, USAGE).into()),
+            }
+        }
+
+        let mut config = match path {
+            Some(path) => Self::load(&path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => Self::load(DEFAULT_CONFIG_FILE)?,
+            None => Self::default(),
+        };
+        config.apply_env()?;
+        config.validate()?;
+        let apps = config.load_apps()?;
+        Ok(Command::Run(Box::new(config), apps))
+    }
+
+    /// Loads and validates the config of every application in `apps`.
+    ///
+    /// Environment overrides only apply to the main config.
+    fn load_apps(&self) -> Result<Vec<(String, Config)>, Box<dyn std::error::Error>> {
+        let mut dirs = storage::data_dirs(self.storage.files())
+            .into_iter()
+            .map(|dir| (dir, "the main application"))
+            .collect::<HashMap<_, _>>();
+        let mut api_keys = HashMap::from([(self.security.api_key.clone(), "the main application")]);
+        let mut payload_dirs =
+            HashMap::from([(PathBuf::from(&self.payloads.dir), "the main application")]);
+        let mut apps = Vec::new();
+        for (id, path) in &self.apps {
+            let data = fs::read_to_string(path)
+                .map_err(|e| format!("apps.{}: failed to read {}: {}", id, path, e))?;
+            let app: AppConfig = toml::from_str(&data)
+                .map_err(|e| format!("apps.{}: failed to parse {}: {}", id, path, e))?;
+            let config = self.with_app(app);
+            config
+                .validate()
+                .map_err(|e| format!("apps.{} ({}): {}", id, path, e))?;
+
+            // Sharing data or an admin key would undo the separation
+            for dir in storage::data_dirs(config.storage.files()) {
+                if let Some(other) = dirs.insert(dir.clone(), id.as_str()) {
+                    return Err(format!(
+                        "apps.{}: data directory {} is also used by {}",
+                        id,
+                        dir.display(),
+                        other
+                    )
+                    .into());
+                }
+            }
+            let payload_dir = PathBuf::from(&config.payloads.dir);
+            if let Some(other) = payload_dirs.insert(payload_dir, id.as_str()) {
+                return Err(format!(
+                    "apps.{}: payloads.dir {} is also used by {}",
+                    id, config.payloads.dir, other
+                )
+                .into());
+            }
+            if let Some(other) = api_keys.insert(config.security.api_key.clone(), id.as_str()) {
+                return Err(
+                    format!("apps.{}: security.api_key is also used by {}", id, other).into(),
+                );
+            }
+            apps.push((id.clone(), config));
+        }
+        Ok(apps)
+    }
+
+    /// The config of a hosted application, sharing the process wide sections of this one.
+    fn with_app(&self, app: AppConfig) -> Config {
+        let mut payloads = app.payloads;
+        // The default is the main application's, whose products aren't this one's
+        if payloads.dir == PayloadsConfig::default().dir {
+            payloads.dir = storage::data_dirs([app.storage.licenses_file.as_str()])[0]
+                .join("payloads")
+                .display()
+                .to_string();
+        }
+        Config {
+            server: self.server.clone(),
+            tls: self.tls.clone(),
+            logging: self.logging.clone(),
+            storage: app.storage,
+            archive: app.archive,
+            limits: app.limits,
+            keys: app.keys,
+            maintenance: app.maintenance,
+            security: app.security,
+            anomaly: app.anomaly,
+            webhooks: app.webhooks,
+            discord: app.discord,
+            payments: app.payments,
+            versions: app.versions,
+            sessions: app.sessions,
+            payloads,
+            apps: BTreeMap::new(),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let data = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read config file {}: {}", path, e))?;
+        let config = toml::from_str(&data)
+            .map_err(|e| format!("failed to parse config file {}: {}", path, e))?;
+        Ok(config)
+    }
+
+    pub fn default_toml() -> Result<String, Box<dyn std::error::Error>> {
+        let overrides = ENV_OVERRIDES
+            .iter()
+            .map(|(section, keys)| format!("#   [{}] {}\n", section, keys.join(", ")))
+            .collect::<String>();
+        Ok(format!(
+            "# autherium-server configuration\n\
+             # These values can be overridden with an AUTHERIUM_<SECTION>_<KEY> environment variable,\n\
+             # e.g. AUTHERIUM_SECURITY_API_KEY or AUTHERIUM_SERVER_BIND=0.0.0.0:8080,[::]:8080\n\
+             {}\n{}",
+            overrides,
+            toml::to_string_pretty(&Self::default())?
+        ))
+    }
+
+    /// Applies environment variable overrides on top of the file values.
+    ///
+    /// The legacy `LICENSES_FILE`, `BANNED_HWIDS_FILE`, `ARCHIVE_FILE` and `API_KEY`
+    /// variables are still honored, the `AUTHERIUM_*` ones take precedence.
+    fn apply_env(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        fn var(names: &[&str]) -> Option<(String, String)> {
+            names
+                .iter()
+                .rev()
+                .find_map(|name| std::env::var(name).ok().map(|v| (name.to_string(), v)))
+        }
+        fn parse<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+            value.parse().map_err(|_| {
+                format!(
+                    "environment variable {} has an invalid value `{}`",
+                    name, value
+                )
+            })
+        }
+
+        if let Some((_, v)) = var(&["AUTHERIUM_SERVER_BIND"]) {
+            self.server.bind = v.split(',').map(|s| s.trim().to_string()).collect();
+        }
+        if let Some((n, v)) = var(&["AUTHERIUM_SERVER_WORKERS"]) {
+            self.server.workers = Some(parse(&n, &v)?);
+        }
+        if let Some((n, v)) = var(&["AUTHERIUM_TLS_ENABLED"]) {
+            self.tls.enabled = parse(&n, &v)?;
+        }
+        if let Some((_, v)) = var(&["AUTHERIUM_TLS_BIND"]) {
+            self.tls.bind = v.split(',').map(|s| s.trim().to_string()).collect();
+        }
+        if let Some((_, v)) = var(&["AUTHERIUM_TLS_CERT_FILE"]) {
+            self.tls.cert_file = v;
+        }
+        if let Some((_, v)) = var(&["AUTHERIUM_TLS_KEY_FILE"]) {
+            self.tls.key_file = v;
+        }
+        if let Some((n, v)) = var(&["AUTHERIUM_TLS_RELOAD_INTERVAL_SECS"]) {
+            self.tls.reload_interval_secs = parse(&n, &v)?;
+        }
+        if let Some((_, v)) = var(&["AUTHERIUM_TLS_PLAIN_HTTP"]) {
+            self.tls.plain_http = match v.to_lowercase().as_str() {
+                "serve" => PlainHttp::Serve,
+                "redirect" => PlainHttp::Redirect,
+                "refuse" => PlainHttp::Refuse,
+                _ => {
+                    return Err(format!(
+                        "environment variable AUTHERIUM_TLS_PLAIN_HTTP has an invalid value `{}`",
+                        v
+                    )
+                    .into());
+                }
+            };
+        }
+        if let Some((_, v)) = var(&["LICENSES_FILE", "AUTHERIUM_STORAGE_LICENSES_FILE"]) {
+            self.storage.licenses_file = v;
+        }
+        if let Some((_, v)) = var(&["BANNED_HWIDS_FILE", "AUTHERIUM_STORAGE_BANNED_HWIDS_FILE"]) {
+            self.storage.banned_hwids_file = v;
+        }
+        if let Some((_, v)) = var(&["AUTHERIUM_STORAGE_BANNED_IPS_FILE"]) {
+            self.storage.banned_ips_file = v;
+        }
+        if let Some((_, v)) = var(&["AUTHERIUM_STORAGE_CANARY_HITS_FILE"]) {
+            self.storage.canary_hits_file = v;
+        }
+        if let Some((_, v)) = var(&["AUTHERIUM_STORAGE_ANOMALY_FLAGS_FILE"]) {
+            self.storage.anomaly_flags_file = v;
+        }
+        if let Some((_, v)) = var(&["AUTHERIUM_STORAGE_WEBHOOK_OUTBOX_FILE"]) {
+            self.storage.webhook_outbox_file = v;
+        }
+        if let Some((_, v)) = var(&["AUTHERIUM_STORAGE_ORDERS_FILE"]) {
+            self.storage.orders_file = v;
+        }
+        if let Some((_, v)) = var(&["AUTHERIUM_STORAGE_RESELLERS_FILE"]) {
+            self.storage.resellers_file = v;
+        }
+        if let Some((_, v)) = var(&["AUTHERIUM_STORAGE_VARIABLES_FILE"]) {
+            self.storage.variables_file = v;
+        }
+        if let Some((_, v)) = var(&["AUTHERIUM_STORAGE_ANNOUNCEMENTS_FILE"]) {
+            self.storage.announcements_file = v;
+        }
+        if let Some((_, v)) = var(&["AUTHERIUM_STORAGE_DISABLED_PRODUCTS_FILE"]) {
+            self.storage.disabled_products_file = v;
+        }
+        if let Some((_, v)) = var(&["ARCHIVE_FILE", "AUTHERIUM_STORAGE_ARCHIVE_FILE"]) {
+            self.storage.archive_file = v;
+        }
+        if let Some((n, v)) = var(&["AUTHERIUM_STORAGE_SAVE_DEBOUNCE_MS"]) {
+            self.storage.save_debounce_ms = parse(&n, &v)?;
+        }
+        if let Some((n, v)) = var(&["AUTHERIUM_ARCHIVE_ROTATE_MAX_BYTES"]) {
+            self.archive.rotate_max_bytes = parse(&n, &v)?;
+        }
+        if let Some((n, v)) = var(&["AUTHERIUM_ARCHIVE_ROTATE_MAX_AGE_DAYS"]) {
+            self.archive.rotate_max_age_days = Some(parse(&n, &v)?);
+        }
+        if let Some((n, v)) = var(&["AUTHERIUM_ARCHIVE_COMPRESS"]) {
+            self.archive.compress = parse(&n, &v)?;
+        }
+        if let Some((n, v)) = var(&["AUTHERIUM_LIMITS_JSON_PAYLOAD_LIMIT"]) {
+            self.limits.json_payload_limit = parse(&n, &v)?;
+        }
+        if let Some((n, v)) = var(&["AUTHERIUM_KEYS_REGEN_LIMIT"]) {
+            self.keys.regen_limit = parse(&n, &v)?;
+        }
+        if let Some((n, v)) = var(&["AUTHERIUM_KEYS_MAX_DAYS"]) {
+            self.keys.max_days = Some(parse(&n, &v)?);
+        }
+        if let Some((n, v)) = var(&["AUTHERIUM_MAINTENANCE_INTERVAL_SECS"]) {
+            self.maintenance.interval_secs = parse(&n, &v)?;
+        }
+        if let Some((n, v)) = var(&["AUTHERIUM_MAINTENANCE_ARCHIVE_RETENTION_DAYS"]) {
+            self.maintenance.archive_retention_days = Some(parse(&n, &v)?);
+        }
+        if let Some((_, v)) = var(&["AUTHERIUM_MAINTENANCE_DISABLED_PRODUCT_MESSAGE"]) {
+            self.maintenance.disabled_product_message = v;
+        }
+        if let Some((_, v)) = var(&["AUTHERIUM_LOGGING_LEVEL"]) {
+            self.logging.level = v.to_lowercase();
+        }
+        if let Some((_, v)) = var(&["AUTHERIUM_LOGGING_FORMAT"]) {
+            self.logging.format = match v.to_lowercase().as_str() {
+                "text" => LogFormat::Text,
+                "json" => LogFormat::Json,
+                _ => {
+                    return Err(format!(
+                        "environment variable AUTHERIUM_LOGGING_FORMAT has an invalid value `{}`",
+                        v
+                    )
+                    .into());
+                }
+            };
+        }
+        if let Some((_, v)) = var(&["API_KEY", "AUTHERIUM_SECURITY_API_KEY"]) {
+            self.security.api_key = v;
+        }
+        if let Some((n, v)) = var(&["AUTHERIUM_SECURITY_REQUIRE_CUSTOM_API_KEY"]) {
+            self.security.require_custom_api_key = parse(&n, &v)?;
+        }
+        if let Some((_, v)) = var(&["AUTHERIUM_SECURITY_METRICS_TOKEN"]) {
+            self.security.metrics_token = Some(v);
+        }
+        if let Some((_, v)) = var(&["AUTHERIUM_SECURITY_TRUSTED_PROXY_HEADER"]) {
+            self.security.trusted_proxy_header = Some(v);
+        }
+        if let Some((n, v)) = var(&["AUTHERIUM_SECURITY_TRUSTED_PROXIES"]) {
+            self.security.trusted_proxies = v
+                .split(',')
+                .map(|s| parse(&n, s.trim()))
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some((n, v)) = var(&["AUTHERIUM_ANOMALY_ENABLED"]) {
+            self.anomaly.enabled = parse(&n, &v)?;
+        }
+        if let Some((n, v)) = var(&["AUTHERIUM_ANOMALY_AUTO_SUSPEND"]) {
+            self.anomaly.rules.auto_suspend = parse(&n, &v)?;
+        }
+        if let Some((n, v)) = var(&["AUTHERIUM_WEBHOOKS_MAX_ATTEMPTS"]) {
+            self.webhooks.max_attempts = parse(&n, &v)?;
+        }
+        if let Some((n, v)) = var(&["AUTHERIUM_WEBHOOKS_TIMEOUT_SECS"]) {
+            self.webhooks.timeout_secs = parse(&n, &v)?;
+        }
+        if let Some((n, v)) = var(&["AUTHERIUM_SESSIONS_TTL_SECS"]) {
+            self.sessions.ttl_secs = parse(&n, &v)?;
+        }
+        if let Some((_, v)) = var(&["AUTHERIUM_PAYLOADS_DIR"]) {
+            self.payloads.dir = v;
+        }
+        if let Some((n, v)) = var(&["AUTHERIUM_PAYLOADS_MAX_DOWNLOADS_PER_SESSION"]) {
+            self.payloads.max_downloads_per_session = parse(&n, &v)?;
+        }
+        if let Some((n, v)) = var(&["AUTHERIUM_PAYLOADS_REQUIRE_ENCRYPTION"]) {
+            self.payloads.require_encryption = parse(&n, &v)?;
+        }
+        if let Some((_, v)) = var(&["AUTHERIUM_PAYMENTS_STRIPE_WEBHOOK_SECRET"]) {
+            self.payments.stripe_webhook_secret = Some(v);
+        }
+        if let Some((_, v)) = var(&["AUTHERIUM_PAYMENTS_GENERIC_WEBHOOK_SECRET"]) {
+            self.payments.generic_webhook_secret = Some(v);
+        }
+        Ok(())
+    }
+
+    /// Checks the whole config and reports every problem at once.
+    pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut errors = Vec::new();
+
+        if self.server.bind.is_empty() && !self.tls.enabled {
+            errors.push("server.bind must list at least one address".to_string());
+        }
+        for addr in &self.server.bind {
+            if addr.parse::<SocketAddr>().is_err() {
+                errors.push(format!(
+                    "server.bind: `{}` is not a valid ip:port address",
+                    addr
+                ));
+            }
+        }
+        if self.tls.enabled {
+            if self.tls.bind.is_empty() {
+                errors.push("tls.bind must list at least one address".to_string());
+            }
+            for addr in &self.tls.bind {
+                if addr.parse::<SocketAddr>().is_err() {
+                    errors.push(format!(
+                        "tls.bind: `{}` is not a valid ip:port address",
+                        addr
+                    ));
+                }
+            }
+            for (name, path) in [
+                ("tls.cert_file", &self.tls.cert_file),
+                ("tls.key_file", &self.tls.key_file),
+            ] {
+                if !Path::new(path).is_file() {
+                    errors.push(format!("{}: `{}` does not exist", name, path));
+                }
+            }
+            if self.tls.reload_interval_secs == 0 {
+                errors.push("tls.reload_interval_secs must be greater than 0".to_string());
+            }
+        } else if self.tls.plain_http != PlainHttp::Serve {
+            errors.push(
+                "tls.plain_http can only redirect or refuse when tls.enabled is set".to_string(),
+            );
+        }
+        if self.server.workers == Some(0) {
+            errors.push("server.workers must be greater than 0".to_string());
+        }
+        for (name, path) in [
+            ("storage.licenses_file", &self.storage.licenses_file),
+            ("storage.banned_hwids_file", &self.storage.banned_hwids_file),
+            ("storage.banned_ips_file", &self.storage.banned_ips_file),
+            ("storage.canary_hits_file", &self.storage.canary_hits_file),
+            (
+                "storage.anomaly_flags_file",
+                &self.storage.anomaly_flags_file,
+            ),
+            (
+                "storage.webhook_outbox_file",
+                &self.storage.webhook_outbox_file,
+            ),
+            ("storage.orders_file", &self.storage.orders_file),
+            ("storage.resellers_file", &self.storage.resellers_file),
+            ("storage.variables_file", &self.storage.variables_file),
+            (
+                "storage.announcements_file",
+                &self.storage.announcements_file,
+            ),
+            (
+                "storage.disabled_products_file",
+                &self.storage.disabled_products_file,
+            ),
+            ("storage.archive_file", &self.storage.archive_file),
+        ] {
+            if path.trim().is_empty() {
+                errors.push(format!("{} must not be empty", name));
+            }
+        }
+        if self.archive.rotate_max_bytes == 0 {
+            errors.push("archive.rotate_max_bytes must be greater than 0".to_string());
+        }
+        if let Some(days) = self.archive.rotate_max_age_days
+            && (days == 0 || days > u64::MAX / (24 * 60 * 60))
+        {
+            errors.push(format!(
+                "archive.rotate_max_age_days: {} is out of range",
+                days
+            ));
+        }
+        if self.limits.json_payload_limit == 0 {
+            errors.push("limits.json_payload_limit must be greater than 0".to_string());
+        }
+        if self.keys.regen_limit == 0 {
+            errors.push("keys.regen_limit must be greater than 0".to_string());
+        }
+        if let Some(max_days) = self.keys.max_days
+            && (max_days == 0 || max_days > u64::MAX / (24 * 60 * 60))
+        {
+            errors.push(format!("keys.max_days: {} is out of range", max_days));
+        }
+        if self.maintenance.interval_secs == 0 {
+            errors.push("maintenance.interval_secs must be greater than 0".to_string());
+        }
+        if let Some(days) = self.maintenance.archive_retention_days
+            && days > u64::MAX / (24 * 60 * 60)
+        {
+            errors.push(format!(
+                "maintenance.archive_retention_days: {} is out of range",
+                days
+            ));
+        }
+        if !LOG_LEVELS.contains(&self.logging.level.as_str()) {
+            errors.push(format!(
+                "logging.level: `{}` must be one of {}",
+                self.logging.level,
+                LOG_LEVELS.join(", ")
+            ));
+        }
+        if self.security.api_key.is_empty() {
+            errors.push("security.api_key must not be empty".to_string());
+        }
+        if self.security.require_custom_api_key && self.security.api_key == DEFAULT_API_KEY {
+            errors.push("security.api_key is still the default value".to_string());
+        }
+        if self.security.metrics_token.as_deref() == Some("") {
+            errors.push("security.metrics_token must not be empty when set".to_string());
+        }
+        if let Some(header) = &self.security.trusted_proxy_header
+            && HeaderName::from_bytes(header.as_bytes()).is_err()
+        {
+            errors.push(format!(
+                "security.trusted_proxy_header: `{}` is not a valid header name",
+                header
+            ));
+        }
+        self.anomaly.rules.validate("anomaly.rules", &mut errors);
+        for (product_id, rules) in &self.anomaly.products {
+            rules.validate(&format!("anomaly.products.{}", product_id), &mut errors);
+        }
+        for endpoint in &self.webhooks.endpoints {
+            if !endpoint.url.starts_with("http://") && !endpoint.url.starts_with("https://") {
+                errors.push(format!(
+                    "webhooks.endpoints: `{}` is not an http or https URL",
+                    endpoint.url
+                ));
+            }
+            if endpoint.secret.is_empty() {
+                errors.push(format!(
+                    "webhooks.endpoints: `{}` needs a secret",
+                    endpoint.url
+                ));
+            }
+        }
+        if self.webhooks.max_attempts == 0 {
+            errors.push("webhooks.max_attempts must be greater than 0".to_string());
+        }
+        if self.webhooks.initial_backoff_secs == 0 {
+            errors.push("webhooks.initial_backoff_secs must be greater than 0".to_string());
+        }
+        if self.webhooks.max_backoff_secs < self.webhooks.initial_backoff_secs {
+            errors.push(
+                "webhooks.max_backoff_secs must be at least webhooks.initial_backoff_secs"
+                    .to_string(),
+            );
+        }
+        if self.webhooks.timeout_secs == 0 {
+            errors.push("webhooks.timeout_secs must be greater than 0".to_string());
+        }
+        for (name, url) in self
+            .discord
+            .default_url
+            .iter()
+            .map(|url| ("discord.default_url".to_string(), url))
+            .chain(
+                self.discord
+                    .products
+                    .iter()
+                    .map(|(product_id, url)| (format!("discord.products.{}", product_id), url)),
+            )
+        {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                errors.push(format!("{}: `{}` is not an http or https URL", name, url));
+            }
+        }
+        for (name, secret) in [
+            (
+                "payments.stripe_webhook_secret",
+                &self.payments.stripe_webhook_secret,
+            ),
+            (
+                "payments.generic_webhook_secret",
+                &self.payments.generic_webhook_secret,
+            ),
+        ] {
+            if secret.as_deref() == Some("") {
+                errors.push(format!("{} must not be empty when set", name));
+            }
+        }
+        for (name, sku) in &self.payments.skus {
+            if sku.product_ids.is_empty() {
+                errors.push(format!(
+                    "payments.skus.{}: product_ids must not be empty",
+                    name
+                ));
+            }
+            if sku.days == 0 || sku.days > self.max_days() {
+                errors.push(format!("payments.skus.{}: days is out of range", name));
+            }
+        }
+        if self.webhooks.failure_spike_window_secs == 0 {
+            errors.push("webhooks.failure_spike_window_secs must be greater than 0".to_string());
+        }
+
+        if self.sessions.ttl_secs == 0 {
+            errors.push("sessions.ttl_secs must be greater than 0".to_string());
+        }
+        if self.payloads.dir.is_empty() {
+            errors.push("payloads.dir must not be empty".to_string());
+        }
+
+        for (product_id, policy) in &self.versions {
+            let mut parsed = Vec::new();
+            for (name, version) in [
+                ("min_version", &policy.min_version),
+                ("latest_version", &policy.latest_version),
+            ] {
+                if let Some(version) = version {
+                    match version.parse::<Version>() {
+                        Ok(version) => parsed.push(version),
+                        Err(e) => errors.push(format!("versions.{}.{}: {}", product_id, name, e)),
+                    }
+                }
+            }
+            if let [min_version, latest_version] = parsed.as_slice()
+                && min_version > latest_version
+            {
+                errors.push(format!(
+                    "versions.{}: min_version must not be newer than latest_version",
+                    product_id
+                ));
+            }
+            if let Some(url) = &policy.download_url
+                && !url.starts_with("http://")
+                && !url.starts_with("https://")
+            {
+                errors.push(format!(
+                    "versions.{}.download_url: `{}` is not an http or https URL",
+                    product_id, url
+                ));
+            }
+        }
+
+        for (id, path) in &self.apps {
+            let valid_id = !id.is_empty()
+                && id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid_id {
+                errors.push(format!(
+                    "apps: `{}` is not a valid application ID, use letters, digits, `-` and `_`",
+                    id
+                ));
+            }
+            if path.is_empty() {
+                errors.push(format!("apps.{} must not be empty", id));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("invalid configuration:\n  - {}", errors.join("\n  - ")).into())
+        }
+    }
+
+    /// Largest license duration that may be created, in days.
+    pub fn max_days(&self) -> u64 {
+        self.keys.max_days.unwrap_or(u64::MAX / (24 * 60 * 60))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The `AUTHERIUM_*` names read by `apply_env`, taken from its source.
+    fn apply_env_vars() -> Vec<String> {
+        let source = include_str!("config.rs");
+        let start = source.find("fn apply_env(").unwrap();
+        let end = start + source[start..].find("fn validate(").unwrap();
+        source[start..end]
+            .split('"')
+            .filter(|part| part.starts_with("AUTHERIUM_"))
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn default_toml_lists_exactly_the_env_overrides() {
+        let mut listed = ENV_OVERRIDES
+            .iter()
+            .flat_map(|(section, keys)| {
+                keys.iter()
+                    .map(move |key| format!("AUTHERIUM_{}_{}", section, key).to_ascii_uppercase())
+            })
+            .collect::<Vec<_>>();
+        let mut handled = apply_env_vars();
+        listed.sort();
+        handled.sort();
+        assert_eq!(listed, handled);
+    }
+
+    #[test]
+    fn default_toml_parses() {
+        let toml = Config::default_toml().unwrap();
+        assert!(toml.contains("#   [webhooks] max_attempts, timeout_secs\n"));
+        toml::from_str::<Config>(&toml).unwrap();
+    }
+}
@@ -0,0 +1,353 @@
+use serde_json::{Value, json};
+
+use crate::{
+    config::{DiscordConfig, DiscordTemplate},
+    logging::Redacted,
+    types::{WebhookDelivery, WebhookEvent},
+};
+
+/// Discord rejects embeds with longer titles or descriptions.
+const MAX_TITLE_CHARS: usize = 256;
+const MAX_DESCRIPTION_CHARS: usize = 4096;
+
+/// Deliveries of `event` to the Discord channels that should see it.
+///
+/// Events about licenses go to the channel of each of their products, falling back to
+/// `default_url` for products without one. Everything else goes to `default_url`.
+pub fn deliveries(
+    config: &DiscordConfig,
+    id: &str,
+    event: WebhookEvent,
+    data: &Value,
+    now: u64,
+) -> Vec<WebhookDelivery> {
+    if !config.events.contains(&event) {
+        return Vec::new();
+    }
+    let mut urls = Vec::new();
+    for product_id in product_ids(data) {
+        let url = config
+            .products
+            .get(product_id)
+            .or(config.default_url.as_ref());
+        if let Some(url) = url
+            && !urls.contains(url)
+        {
+            urls.push(url.clone());
+        }
+    }
+    if urls.is_empty()
+        && let Some(url) = &config.default_url
+    {
+        urls.push(url.clone());
+    }
+    if urls.is_empty() {
+        return Vec::new();
+    }
+
+    let defaults;
+    let template = match config.templates.get(&event) {
+        Some(template) => template,
+        None => {
+            defaults = DiscordTemplate::defaults();
+            &defaults[&event]
+        }
+    };
+    let data = &with_redacted_keys(data);
+    let body = json!({
+        "username": config.username,
+        "embeds": [{
+            "title": truncate(render(&template.title, data), MAX_TITLE_CHARS),
+            "description": truncate(render(&template.description, data), MAX_DESCRIPTION_CHARS),
+            "color": template.color,
+            "footer": { "text": event.as_str() },
+        }],
+        // Event fields are user controlled, never let them ping anyone
+        "allowed_mentions": { "parse": [] },
+    })
+    .to_string();
+
+    urls.into_iter()
+        .map(|url| WebhookDelivery {
+            id: id.to_string(),
+            event,
+            url,
+            body: body.clone(),
+            attempts: 0,
+            last_error: None,
+            at: now,
+            discord: true,
+        })
+        .collect()
+}
+
+/// The products an event is about, from its `product_id` or `product_ids` field.
+fn product_ids(data: &Value) -> Vec<&str> {
+    if let Some(product_id) = data["product_id"].as_str() {
+        return vec![product_id];
+    }
+    data["product_ids"]
+        .as_array()
+        .map(|ids| ids.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default()
+}
+
+/// `data` with `license_redacted` and `licenses_redacted` added next to the license keys
+/// it carries, so templates can name a license without posting the key.
+fn with_redacted_keys(data: &Value) -> Value {
+    let mut data = data.clone();
+    if let Some(license) = data["license"].as_str() {
+        data["license_redacted"] = Redacted(license).to_string().into();
+    }
+    if let Some(licenses) = data["licenses"].as_array() {
+        data["licenses_redacted"] = licenses
+            .iter()
+            .filter_map(Value::as_str)
+            .map(|license| Redacted(license).to_string())
+            .collect::<Vec<_>>()
+            .into();
+    }
+    data
+}
+
+/// Replaces every `{field}` in `template` with that field of `data`. Unknown fields are
+/// left as they are.
+fn render(template: &str, data: &Value) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find('}') else {
+            rest = &rest[start..];
+            break;
+        };
+        let name = &rest[start + 1..start + len];
+        match name.split('.').try_fold(data, |value, key| value.get(key)) {
+            Some(value) => rendered.push_str(&display(value)),
+            None => rendered.push_str(&rest[start..=start + len]),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+fn display(value: &Value) -> String {
+    match value {
+        Value::Null => "-".to_string(),
+        Value::String(s) => s.clone(),
+        Value::Array(values) => values.iter().map(display).collect::<Vec<_>>().join(", "),
+        other => other.to_string(),
+    }
+}
+
+fn truncate(mut text: String, max_chars: usize) -> String {
+    if let Some((index, _)) = text.char_indices().nth(max_chars) {
+        text.truncate(index);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{BTreeMap, HashMap};
+
+    fn config() -> DiscordConfig {
+        DiscordConfig {
+            default_url: Some("https://discord.test/default".to_string()),
+            products: HashMap::from([
+                ("pro".to_string(), "https://discord.test/pro".to_string()),
+                ("lite".to_string(), "https://discord.test/lite".to_string()),
+            ]),
+            ..DiscordConfig::default()
+        }
+    }
+
+    fn embed(delivery: &WebhookDelivery) -> Value {
+        let body: Value = serde_json::from_str(&delivery.body).unwrap();
+        body["embeds"][0].clone()
+    }
+
+    #[test]
+    fn render_fills_in_fields() {
+        let data = json!({
+            "license": "ABC",
+            "product_ids": ["pro", "lite"],
+            "ban": { "created_by": "panel", "reason": null },
+            "count": 3,
+        });
+        assert_eq!(
+            render(
+                "`{license}` for {product_ids} by {ban.created_by}: {ban.reason} x{count}",
+                &data
+            ),
+            "`ABC` for pro, lite by panel: - x3"
+        );
+    }
+
+    #[test]
+    fn render_leaves_unknown_fields_and_stray_braces() {
+        let data = json!({ "license": "ABC", "ban": {} });
+        assert_eq!(
+            render("{missing} {ban.reason}", &data),
+            "{missing} {ban.reason}"
+        );
+        assert_eq!(render("{license} {unclosed", &data), "ABC {unclosed");
+        assert_eq!(render("no fields", &data), "no fields");
+    }
+
+    #[test]
+    fn truncate_counts_characters() {
+        assert_eq!(truncate("short".to_string(), 10), "short");
+        assert_eq!(truncate("exactly".to_string(), 7), "exactly");
+        assert_eq!(truncate("truncated".to_string(), 5), "trunc");
+        // Multi-byte characters are never split
+        assert_eq!(truncate("ééééé".to_string(), 3), "ééé");
+    }
+
+    #[test]
+    fn license_events_go_to_their_product_channels() {
+        let data = json!({ "license": "ABC", "product_ids": ["pro", "other", "lite"] });
+        let urls = deliveries(&config(), "evt_1", WebhookEvent::LicenseExpired, &data, 7)
+            .into_iter()
+            .map(|delivery| delivery.url)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://discord.test/pro",
+                "https://discord.test/default",
+                "https://discord.test/lite",
+            ]
+        );
+    }
+
+    #[test]
+    fn other_events_go_to_the_default_channel() {
+        let data = json!({ "hwid": "H1", "ban": { "created_by": "panel", "reason": "cheating" } });
+        let deliveries = deliveries(&config(), "evt_1", WebhookEvent::HwidBanned, &data, 7);
+        assert_eq!(deliveries.len(), 1);
+        let delivery = &deliveries[0];
+        assert_eq!(delivery.url, "https://discord.test/default");
+        assert!(delivery.discord);
+        assert_eq!(delivery.at, 7);
+        assert_eq!(embed(delivery)["title"], "HWID banned");
+        assert_eq!(
+            embed(delivery)["description"],
+            "`H1` was banned by panel: cheating"
+        );
+
+        let no_default = DiscordConfig {
+            default_url: None,
+            ..config()
+        };
+        assert!(deliveries_for(&no_default, WebhookEvent::HwidBanned, &data).is_empty());
+    }
+
+    #[test]
+    fn unsubscribed_events_are_not_sent() {
+        let data = json!({ "license": "ABC", "product_ids": ["pro"] });
+        assert!(deliveries_for(&config(), WebhookEvent::LicenseCreated, &data).is_empty());
+    }
+
+    #[test]
+    fn custom_templates_are_used_and_truncated() {
+        let config = DiscordConfig {
+            templates: BTreeMap::from([(
+                WebhookEvent::LicenseActivated,
+                DiscordTemplate {
+                    title: "{license}".repeat(100),
+                    description: "Hi <@everyone> from {product_id}".to_string(),
+                    color: 0x123456,
+                },
+            )]),
+            ..config()
+        };
+        let data = json!({ "license": "ABC", "product_id": "lite" });
+        let deliveries = deliveries_for(&config, WebhookEvent::LicenseActivated, &data);
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].url, "https://discord.test/lite");
+        let body: Value = serde_json::from_str(&deliveries[0].body).unwrap();
+        let embed = &body["embeds"][0];
+        assert_eq!(
+            embed["title"].as_str().unwrap().chars().count(),
+            MAX_TITLE_CHARS
+        );
+        assert_eq!(embed["description"], "Hi <@everyone> from lite");
+        assert_eq!(embed["color"], 0x123456);
+        assert_eq!(embed["footer"]["text"], "license.activated");
+        assert_eq!(body["username"], "Autherium");
+        assert_eq!(body["allowed_mentions"]["parse"], json!([]));
+    }
+
+    #[test]
+    fn missing_templates_fall_back_to_the_defaults() {
+        let config = DiscordConfig {
+            templates: BTreeMap::new(),
+            ..config()
+        };
+        let data = json!({ "license": "ABCD-EFGH", "product_ids": ["pro"] });
+        let deliveries = deliveries_for(&config, WebhookEvent::LicenseExpired, &data);
+        assert_eq!(embed(&deliveries[0])["title"], "License expired");
+        assert_eq!(
+            embed(&deliveries[0])["description"],
+            "`*****EFGH` for pro expired"
+        );
+    }
+
+    #[test]
+    fn default_templates_never_post_full_keys() {
+        let config = DiscordConfig {
+            events: vec![WebhookEvent::LicenseActivated, WebhookEvent::OrderCompleted],
+            ..config()
+        };
+        let data = json!({ "license": "ABCD-EFGH", "product_id": "pro", "hwid": "H1" });
+        let deliveries = deliveries_for(&config, WebhookEvent::LicenseActivated, &data);
+        assert_eq!(
+            embed(&deliveries[0])["description"],
+            "`*****EFGH` activated for **pro** on HWID `H1`"
+        );
+
+        let data = json!({
+            "order_id": "o1",
+            "sku": "pro-30",
+            "provider": "stripe",
+            "licenses": ["ABCD-EFGH", "IJKL-MNOP"],
+        });
+        let deliveries = deliveries_for(&config, WebhookEvent::OrderCompleted, &data);
+        assert_eq!(
+            embed(&deliveries[0])["description"],
+            "Order `o1` (pro-30) paid through stripe: *****EFGH, *****MNOP"
+        );
+    }
+
+    #[test]
+    fn full_keys_are_opt_in() {
+        let config = DiscordConfig {
+            templates: BTreeMap::from([(
+                WebhookEvent::LicenseExpired,
+                DiscordTemplate {
+                    title: "Expired".to_string(),
+                    description: "{license} / {license_redacted}".to_string(),
+                    color: 0,
+                },
+            )]),
+            ..config()
+        };
+        let data = json!({ "license": "ABCD-EFGH", "product_ids": ["pro"] });
+        let deliveries = deliveries_for(&config, WebhookEvent::LicenseExpired, &data);
+        assert_eq!(
+            embed(&deliveries[0])["description"],
+            "ABCD-EFGH / *****EFGH"
+        );
+    }
+
+    fn deliveries_for(
+        config: &DiscordConfig,
+        event: WebhookEvent,
+        data: &Value,
+    ) -> Vec<WebhookDelivery> {
+        deliveries(config, "evt_1", event, data, 0)
+    }
+}
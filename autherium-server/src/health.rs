@@ -0,0 +1,97 @@
+use actix_web::{HttpResponse, Responder, get, web};
+use std::{fs, path::Path};
+
+use crate::{Apps, State, storage, types::*};
+
+const PROBE_FILE: &str = ".autherium-readyz";
+
+/// Liveness: the process is up and serving requests.
+#[get("/healthz")]
+async fn healthz() -> impl Responder {
+    HttpResponse::Ok().json(HealthResponse {
+        status: "ok".to_string(),
+    })
+}
+
+/// Readiness of an application: its data directory can still be read and written.
+#[get("/readyz")]
+async fn readyz(state: web::Data<State>) -> impl Responder {
+    respond(checks(&state, ""))
+}
+
+/// Readiness of the server, which needs every application it hosts to be ready. Checks
+/// of hosted applications are prefixed with `apps.<id>.`.
+#[get("/readyz")]
+async fn server_readyz(apps: web::Data<Apps>) -> impl Responder {
+    let checks = apps
+        .0
+        .iter()
+        .flat_map(|(id, state)| {
+            let prefix = id
+                .as_ref()
+                .map(|id| format!("apps.{}.", id))
+                .unwrap_or_default();
+            checks(state, &prefix)
+        })
+        .collect();
+    respond(checks)
+}
+
+fn checks(state: &State, prefix: &str) -> Vec<ReadinessCheck> {
+    let data_files = state.config.storage.files();
+    vec![
+        check(&format!("{}data_dir_writable", prefix), || {
+            storage::data_dirs(data_files)
+                .iter()
+                .try_for_each(|dir| probe_write(dir))
+        }),
+        check(&format!("{}storage_reachable", prefix), || {
+            data_files.iter().try_for_each(|file| probe_read(file))?;
+            let failing_saves = state.failing_saves.lock().unwrap();
+            if failing_saves.is_empty() {
+                Ok(())
+            } else {
+                let mut files = failing_saves.iter().copied().collect::<Vec<_>>();
+                files.sort();
+                Err(format!("last save failed for: {}", files.join(", ")))
+            }
+        }),
+    ]
+}
+
+fn respond(checks: Vec<ReadinessCheck>) -> HttpResponse {
+    let ready = checks.iter().all(|check| check.ok);
+    let response = ReadinessResponse {
+        status: if ready { "ready" } else { "not ready" }.to_string(),
+        checks,
+    };
+    if ready {
+        HttpResponse::Ok().json(response)
+    } else {
+        HttpResponse::ServiceUnavailable().json(response)
+    }
+}
+
+fn check(name: &str, run: impl FnOnce() -> Result<(), String>) -> ReadinessCheck {
+    let result = run();
+    ReadinessCheck {
+        name: name.to_string(),
+        ok: result.is_ok(),
+        error: result.err(),
+    }
+}
+
+fn probe_write(dir: &Path) -> Result<(), String> {
+    let probe = dir.join(PROBE_FILE);
+    fs::write(&probe, b"ok").map_err(|e| format!("{}: {}", dir.display(), e))?;
+    fs::remove_file(&probe).map_err(|e| format!("{}: {}", dir.display(), e))
+}
+
+fn probe_read(file: &str) -> Result<(), String> {
+    if !Path::new(file).exists() {
+        return Ok(());
+    }
+    fs::File::open(file)
+        .map(|_| ())
+        .map_err(|e| format!("{}: {}", file, e))
+}
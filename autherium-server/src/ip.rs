@@ -0,0 +1,60 @@
+use actix_web::HttpRequest;
+use ipnet::IpNet;
+use std::net::IpAddr;
+
+use crate::config::SecurityConfig;
+
+/// Parses a single address or a CIDR range. Host bits of a range are cleared, so
+/// `10.1.2.3/8` bans `10.0.0.0/8`.
+pub fn parse_network(value: &str) -> Option<IpNet> {
+    let value = value.trim();
+    if let Ok(network) = value.parse::<IpNet>() {
+        return Some(network.trunc());
+    }
+    value.parse::<IpAddr>().ok().map(IpNet::from)
+}
+
+/// The address of the client behind `req`.
+///
+/// `security.trusted_proxy_header` is only honored when the connection comes from one of
+/// `security.trusted_proxies`. The header is read right to left, skipping further trusted
+/// proxies, so a client can't spoof its address by sending the header itself.
+pub fn client_ip(req: &HttpRequest, security: &SecurityConfig) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    let is_trusted = |ip: &IpAddr| security.trusted_proxies.iter().any(|net| net.contains(ip));
+    let Some(header) = &security.trusted_proxy_header else {
+        return Some(peer);
+    };
+    if !is_trusted(&peer) {
+        return Some(peer);
+    }
+
+    let mut client = peer;
+    for value in req.headers().get_all(header.as_str()).rev() {
+        let Ok(value) = value.to_str() else {
+            return Some(client);
+        };
+        for entry in value.rsplit(',') {
+            let Some(ip) = parse_forwarded_ip(entry) else {
+                return Some(client);
+            };
+            client = ip;
+            if !is_trusted(&ip) {
+                return Some(client);
+            }
+        }
+    }
+    Some(client)
+}
+
+/// Parses one entry of a forwarding header, which may carry a port or IPv6 brackets.
+fn parse_forwarded_ip(entry: &str) -> Option<IpAddr> {
+    let entry = entry.trim().trim_matches('"');
+    if let Ok(ip) = entry.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    if let Some(rest) = entry.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+    entry.rsplit_once(':')?.0.parse().ok()
+}
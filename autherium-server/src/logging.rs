@@ -0,0 +1,50 @@
+use std::fmt;
+
+use tracing_subscriber::{EnvFilter, fmt::format::FmtSpan};
+
+use crate::config::{LogFormat, LoggingConfig};
+
+/// Number of trailing characters of a secret left visible in logs.
+const VISIBLE_SUFFIX: usize = 4;
+
+/// Installs the global tracing subscriber. `RUST_LOG` takes precedence over `logging.level`.
+pub fn init(config: &LoggingConfig) {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(format!("{},actix_server=info", config.level)));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE);
+    match config.format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder
+            .json()
+            .flatten_event(true)
+            .with_span_list(false)
+            .init(),
+    }
+}
+
+/// Displays a secret such as a license or API key with everything but its last
+/// few characters masked, e.g. `************WXYZ`.
+pub struct Redacted<'a>(pub &'a str);
+
+impl fmt::Display for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let chars = self.0.chars().count();
+        if chars <= VISIBLE_SUFFIX {
+            return f.write_str(&"*".repeat(chars));
+        }
+        let suffix = self
+            .0
+            .chars()
+            .skip(chars - VISIBLE_SUFFIX)
+            .collect::<String>();
+        write!(f, "{}{}", "*".repeat(chars - VISIBLE_SUFFIX), suffix)
+    }
+}
+
+impl fmt::Debug for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{}\"", self)
+    }
+}
@@ -1,127 +1,598 @@
-use actix_web::{App, HttpResponse, HttpServer, Responder, Result, error, post, web};
+use actix_web::{
+    App, HttpRequest, HttpResponse, HttpServer, Responder, Result, Scope, error, guard,
+    http::StatusCode, middleware::from_fn, post, web,
+};
 use regex::Regex;
-use std::{fs, path::Path, sync::Mutex};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Mutex, RwLock},
+    time::Duration,
+};
 
 use rand::{Rng, distr::Alphanumeric};
+use tracing_actix_web::TracingLogger;
 
+mod announcements;
+mod anomaly;
+mod archive;
+mod canary;
+mod config;
+mod discord;
+mod health;
+mod ip;
+mod logging;
+mod maintenance;
+mod metrics;
+mod payloads;
+mod payments;
+mod products;
+mod resellers;
+mod sessions;
+mod storage;
+mod store;
+mod tls;
 mod types;
+mod variables;
+mod versions;
+mod webhooks;
+use anomaly::AuthHistory;
+use archive::Archive;
+use config::{Command, Config};
+use logging::Redacted;
+use metrics::{AuthOutcome, METRICS};
+use payloads::PayloadHashes;
+use sessions::Sessions;
+use storage::{DataDirLock, DataFile, SaveQueue};
+use store::LicenseStore;
+use tracing::{Instrument, Span, error, info, info_span, warn};
 use types::*;
+use versions::VersionCheck;
+use webhooks::Webhooks;
 
-const LICENSES_FILE: std::sync::LazyLock<String> = std::sync::LazyLock::new(|| {
-    std::env::var("LICENSES_FILE").unwrap_or_else(|_| "./config/licenses.json".to_string())
-});
-const BANNED_HWIDS_FILE: std::sync::LazyLock<String> = std::sync::LazyLock::new(|| {
-    std::env::var("BANNED_HWIDS_FILE").unwrap_or_else(|_| "./config/banned_hwids.json".to_string())
-});
-const ARCHIVE_FILE: std::sync::LazyLock<String> = std::sync::LazyLock::new(|| {
-    std::env::var("ARCHIVE_FILE").unwrap_or_else(|_| "./config/expired_licenses.json".to_string())
-});
-const LICENSE_REGEX: std::sync::LazyLock<Regex> =
+/// Names the hosted application a request to `/api/v1` is for. Requests without it go
+/// to the main application.
+const APP_HEADER: &str = "X-Autherium-App";
+
+/// Every application the server runs, the main one first under no ID, for the endpoints
+/// that report on the server as a whole.
+struct Apps(Vec<(Option<String>, web::Data<State>)>);
+
+static LICENSE_REGEX: std::sync::LazyLock<Regex> =
     std::sync::LazyLock::new(|| regex::Regex::new(r"^[A-Z0-9]{16}").unwrap());
-const API_KEY: std::sync::LazyLock<String> = std::sync::LazyLock::new(|| {
-    std::env::var("API_KEY").unwrap_or_else(|_| "super_secret_key".to_string())
-});
-const LICENSE_REGEN_LIMIT: u32 = 100;
 
 struct State {
-    pub licenses: Mutex<Vec<License>>,
-    pub banned_hwids: Mutex<Vec<String>>,
+    pub config: Config,
+    pub licenses: RwLock<LicenseStore>,
+    pub banned_hwids: RwLock<HashMap<String, HwidBan>>,
+    pub banned_ips: RwLock<Vec<IpBan>>,
+    pub canary_hits: RwLock<Vec<CanaryHit>>,
+    pub anomaly_flags: RwLock<Vec<AnomalyFlag>>,
+    pub auth_history: AuthHistory,
+    pub sessions: Sessions,
+    pub payload_hashes: PayloadHashes,
+    pub webhooks: Webhooks,
+    /// Orders from payment providers by `<provider>:<order id>`.
+    pub orders: RwLock<HashMap<String, Order>>,
+    pub resellers: RwLock<HashMap<String, Reseller>>,
+    /// Remote variables by product ID.
+    pub variables: RwLock<HashMap<String, ProductVariables>>,
+    pub announcements: RwLock<HashMap<String, Announcement>>,
+    /// Products switched off by an admin, by product ID.
+    pub disabled_products: RwLock<HashMap<String, DisabledProduct>>,
+    pub archive: Archive,
+    /// Data files whose most recent save failed.
+    pub failing_saves: Mutex<HashSet<&'static str>>,
+    pub save_queue: SaveQueue,
+    /// Serializes writes so two saves of a file never share a temp file.
+    save_lock: Mutex<()>,
+    /// Held for as long as the server runs.
+    _data_lock: DataDirLock,
 }
 
 impl State {
-    pub fn new() -> Self {
-        let licenses = Self::load_licenses().unwrap_or_else(|_| Vec::new());
-        let banned_hwids = Self::load_banned_hwids().unwrap_or_else(|_| Vec::new());
+    /// Locks the data directories and loads every data file.
+    ///
+    /// Fails rather than starting with empty state when a file can't be read, since the
+    /// next save would overwrite whatever it still holds.
+    pub fn new(config: Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let data_lock = DataDirLock::acquire(&storage::data_dirs(config.storage.files()))?;
+        let archive = Archive::new(&config.storage.archive_file, &config.archive);
+        archive.init()?;
+        let disabled_products =
+            storage::read_json(&config.storage.disabled_products_file)?.unwrap_or_default();
+        let licenses = Self::load_licenses(&config, &archive, &disabled_products)?;
+        let banned_hwids = Self::load_banned_hwids(&config)?;
+        let banned_ips = storage::read_json(&config.storage.banned_ips_file)?.unwrap_or_default();
+        let canary_hits = storage::read_json(&config.storage.canary_hits_file)?.unwrap_or_default();
+        let anomaly_flags =
+            storage::read_json(&config.storage.anomaly_flags_file)?.unwrap_or_default();
+        let webhooks = Webhooks::load(&config.storage.webhook_outbox_file)?;
+        let orders = storage::read_json(&config.storage.orders_file)?.unwrap_or_default();
+        let resellers = storage::read_json(&config.storage.resellers_file)?.unwrap_or_default();
+        let variables = storage::read_json(&config.storage.variables_file)?.unwrap_or_default();
+        let announcements =
+            storage::read_json(&config.storage.announcements_file)?.unwrap_or_default();
+        let save_queue = SaveQueue::new(Duration::from_millis(config.storage.save_debounce_ms));
+
+        Ok(Self {
+            config,
+            licenses: RwLock::new(LicenseStore::new(licenses)),
+            banned_hwids: RwLock::new(
+                banned_hwids
+                    .into_iter()
+                    .map(|ban| (ban.hwid.clone(), ban))
+                    .collect(),
+            ),
+            banned_ips: RwLock::new(banned_ips),
+            canary_hits: RwLock::new(canary_hits),
+            anomaly_flags: RwLock::new(anomaly_flags),
+            auth_history: AuthHistory::default(),
+            sessions: Sessions::default(),
+            payload_hashes: PayloadHashes::default(),
+            webhooks,
+            orders: RwLock::new(orders),
+            resellers: RwLock::new(resellers),
+            variables: RwLock::new(variables),
+            announcements: RwLock::new(announcements),
+            disabled_products: RwLock::new(disabled_products),
+            archive,
+            failing_saves: Mutex::new(HashSet::new()),
+            save_queue,
+            save_lock: Mutex::new(()),
+            _data_lock: data_lock,
+        })
+    }
 
-        Self {
-            licenses: Mutex::new(licenses),
-            banned_hwids: Mutex::new(banned_hwids),
+    /// Runs a save of `file`, recording its outcome for metrics and readiness checks.
+    fn track_save<T, E>(
+        &self,
+        file: &'static str,
+        save: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E> {
+        let result = METRICS.observe_save(file, save);
+        let mut failing_saves = self.failing_saves.lock().unwrap();
+        if result.is_ok() {
+            failing_saves.remove(file);
+        } else {
+            failing_saves.insert(file);
         }
+        result
     }
 
-    fn load_licenses() -> Result<Vec<License>, Box<dyn std::error::Error>> {
-        if !Path::new(LICENSES_FILE.as_str()).exists() {
+    fn load_licenses(
+        config: &Config,
+        archive: &Archive,
+        disabled_products: &HashMap<String, DisabledProduct>,
+    ) -> Result<Vec<License>, Box<dyn std::error::Error>> {
+        let licenses_file = &config.storage.licenses_file;
+        let Some(licenses) = storage::read_json::<Vec<License>>(licenses_file)? else {
             return Ok(Vec::new());
-        }
-        let data = fs::read_to_string(LICENSES_FILE.as_str())?;
-        let licenses: Vec<License> = serde_json::from_str(&data)?;
+        };
         // add expired licenses to an archive file
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-        let (expired, active): (Vec<License>, Vec<License>) = licenses
-            .into_iter()
-            .partition(|license| license.used && (license.start + license.duration) <= now);
+        let now = unix_now();
+        let (expired, active): (Vec<License>, Vec<License>) =
+            licenses.into_iter().partition(|license| {
+                license.is_expired(now) && !products::is_paused(disabled_products, license)
+            });
         if !expired.is_empty() {
-            let mut archive = if Path::new(&ARCHIVE_FILE.as_str()).exists() {
-                let archive_data = fs::read_to_string(ARCHIVE_FILE.as_str())?;
-                serde_json::from_str::<Vec<License>>(&archive_data)?
-            } else {
-                Vec::new()
-            };
-            archive.extend(expired);
-            let archive_data = serde_json::to_string_pretty(&archive)?;
-            fs::write(ARCHIVE_FILE.as_str(), archive_data)?;
+            archive.append(&expired)?;
+            METRICS.licenses_archived_by(expired.len() as u64);
         }
         let licenses = active;
         // Save the active licenses back to the licenses file
-        let data = serde_json::to_string_pretty(&licenses)?;
-        fs::write(LICENSES_FILE.as_str(), data)?;
+        storage::write_json(licenses_file, &licenses)?;
         Ok(licenses)
     }
 
-    fn load_banned_hwids() -> Result<Vec<String>, Box<dyn std::error::Error>> {
-        if !Path::new(&BANNED_HWIDS_FILE.as_str()).exists() {
+    fn load_banned_hwids(config: &Config) -> Result<Vec<HwidBan>, Box<dyn std::error::Error>> {
+        let banned_hwids_file = &config.storage.banned_hwids_file;
+        let Some(stored) = storage::read_json::<Vec<StoredBan>>(banned_hwids_file)? else {
             return Ok(Vec::new());
+        };
+        let now = unix_now();
+        let mut migrated = 0;
+        let bans = stored
+            .into_iter()
+            .map(|ban| match ban {
+                StoredBan::Ban(ban) => ban,
+                StoredBan::Legacy(hwid) => {
+                    migrated += 1;
+                    HwidBan {
+                        hwid,
+                        details: BanDetails::permanent(now),
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+        if migrated > 0 {
+            storage::write_json(banned_hwids_file, &bans)?;
+            info!(migrated, "Migrated banned HWIDs to ban records");
         }
-        let data = fs::read_to_string(&BANNED_HWIDS_FILE.as_str())?;
-        let banned_hwids: Vec<String> = serde_json::from_str(&data)?;
-        Ok(banned_hwids)
+        Ok(bans)
+    }
+
+    /// Queues `file` for the background writer.
+    pub fn schedule_save(&self, file: DataFile) {
+        self.save_queue.request(file);
     }
 
-    pub fn save_licenses(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let licenses = self.licenses.lock().unwrap();
-        let data = serde_json::to_string_pretty(&*licenses)?;
-        fs::write(&LICENSES_FILE.as_str(), data)?;
+    /// Writes `file` now. Handlers should use [`State::schedule_save`] instead.
+    pub fn save(&self, file: DataFile) -> Result<(), Box<dyn std::error::Error>> {
+        let _writing = self.save_lock.lock().unwrap();
+        self.track_save(file.as_str(), || match file {
+            DataFile::Licenses => {
+                // Snapshot first so the lock isn't held while serializing
+                let licenses = self.licenses.read().unwrap().to_vec();
+                storage::write_json(&self.config.storage.licenses_file, &licenses)
+            }
+            DataFile::BannedHwids => {
+                let mut banned_hwids = self
+                    .banned_hwids
+                    .read()
+                    .unwrap()
+                    .values()
+                    .cloned()
+                    .collect::<Vec<_>>();
+                banned_hwids.sort_by(|a, b| a.hwid.cmp(&b.hwid));
+                storage::write_json(&self.config.storage.banned_hwids_file, &banned_hwids)
+            }
+            DataFile::BannedIps => {
+                let banned_ips = self.banned_ips.read().unwrap().clone();
+                storage::write_json(&self.config.storage.banned_ips_file, &banned_ips)
+            }
+            DataFile::CanaryHits => {
+                let canary_hits = self.canary_hits.read().unwrap().clone();
+                storage::write_json(&self.config.storage.canary_hits_file, &canary_hits)
+            }
+            DataFile::AnomalyFlags => {
+                let anomaly_flags = self.anomaly_flags.read().unwrap().clone();
+                storage::write_json(&self.config.storage.anomaly_flags_file, &anomaly_flags)
+            }
+            DataFile::WebhookOutbox => {
+                let outbox = self.webhooks.snapshot();
+                storage::write_json(&self.config.storage.webhook_outbox_file, &outbox)
+            }
+            DataFile::Orders => {
+                let orders = self.orders.read().unwrap().clone();
+                storage::write_json(&self.config.storage.orders_file, &orders)
+            }
+            DataFile::Resellers => {
+                let resellers = self.resellers.read().unwrap().clone();
+                storage::write_json(&self.config.storage.resellers_file, &resellers)
+            }
+            DataFile::Variables => {
+                let variables = self.variables.read().unwrap().clone();
+                storage::write_json(&self.config.storage.variables_file, &variables)
+            }
+            DataFile::Announcements => {
+                let announcements = self.announcements.read().unwrap().clone();
+                storage::write_json(&self.config.storage.announcements_file, &announcements)
+            }
+            DataFile::DisabledProducts => {
+                let disabled_products = self.disabled_products.read().unwrap().clone();
+                storage::write_json(
+                    &self.config.storage.disabled_products_file,
+                    &disabled_products,
+                )
+            }
+        })
+    }
+
+    /// Writes every queued save now, e.g. on shutdown.
+    pub fn flush(&self) {
+        for file in self.save_queue.take() {
+            if let Err(e) = self.save(file) {
+                error!(error = %e, file = file.as_str(), "Failed to save data file");
+            }
+        }
+    }
+
+    /// Background writer loop, saving queued files as they come in.
+    fn write_queued(&self) {
+        loop {
+            for file in self.save_queue.next_batch() {
+                if let Err(e) = self.save(file) {
+                    error!(error = %e, file = file.as_str(), "Failed to save data file");
+                }
+            }
+            self.save_queue.batch_written();
+        }
+    }
+
+    fn append_to_archive(&self, licenses: &[License]) -> Result<(), Box<dyn std::error::Error>> {
+        self.track_save("archive", || self.archive.append(licenses))?;
+        METRICS.licenses_archived_by(licenses.len() as u64);
+        let now = unix_now();
+        for license in licenses.iter().filter(|license| license.canary.is_none()) {
+            webhooks::emit(
+                self,
+                WebhookEvent::LicenseExpired,
+                serde_json::json!({
+                    "license": license.key,
+                    "product_ids": license.product_ids,
+                    "expired_at": license.expires_at().min(now),
+                }),
+            );
+        }
         Ok(())
     }
 
-    pub fn save_banned_hwids(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let banned_hwids = self.banned_hwids.lock().unwrap();
-        let data = serde_json::to_string_pretty(&*banned_hwids)?;
-        fs::write(BANNED_HWIDS_FILE.as_str(), data)?;
+    /// Archives a license already taken out of the store, putting it back if that fails
+    /// so the next sweep retries instead of it being lost.
+    pub fn archive_license(&self, license: License) -> Result<(), Box<dyn std::error::Error>> {
+        if let Err(e) = self.append_to_archive(std::slice::from_ref(&license)) {
+            self.licenses.write().unwrap().insert(license);
+            return Err(e);
+        }
         Ok(())
     }
 
-    pub fn archive_license(&self, license: &License) {
-        let mut archive = if Path::new(ARCHIVE_FILE.as_str()).exists() {
-            let archive_data = fs::read_to_string(ARCHIVE_FILE.as_str()).unwrap_or_default();
-            serde_json::from_str::<Vec<License>>(&archive_data).unwrap_or_default()
-        } else {
-            Vec::new()
+    /// Moves every expired license into the archive, returning how many were moved.
+    pub fn archive_expired(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        // Holding it keeps a product from being enabled, and its licenses extended, while
+        // they are picked
+        let disabled_products = self.disabled_products.read().unwrap();
+        let expired = self
+            .licenses
+            .write()
+            .unwrap()
+            .take_expired(unix_now(), |license| {
+                products::is_paused(&disabled_products, license)
+            });
+        drop(disabled_products);
+        if expired.is_empty() {
+            return Ok(0);
+        }
+        if let Err(e) = self.append_to_archive(&expired) {
+            // Keep them active rather than losing them, the next sweep will retry
+            let mut licenses = self.licenses.write().unwrap();
+            for license in expired {
+                licenses.insert(license);
+            }
+            return Err(e);
+        }
+        self.save(DataFile::Licenses)?;
+        Ok(expired.len())
+    }
+
+    /// Removes temporary HWID and IP bans that have run out, returning how many were lifted.
+    pub fn lift_expired_bans(&self) -> usize {
+        let now = unix_now();
+        let hwids_lifted = {
+            let mut banned_hwids = self.banned_hwids.write().unwrap();
+            let before = banned_hwids.len();
+            banned_hwids.retain(|hwid, ban| {
+                let active = ban.details.is_active(now);
+                if !active {
+                    info!(hwid = %hwid, "HWID ban lifted");
+                }
+                active
+            });
+            before - banned_hwids.len()
         };
-        archive.push(license.clone());
-        let archive_data = serde_json::to_string_pretty(&archive).unwrap_or_default();
-        fs::write(ARCHIVE_FILE.as_str(), archive_data).unwrap_or_default();
+        if hwids_lifted > 0 {
+            self.schedule_save(DataFile::BannedHwids);
+        }
+        let ips_lifted = {
+            let mut banned_ips = self.banned_ips.write().unwrap();
+            let before = banned_ips.len();
+            banned_ips.retain(|ban| {
+                let active = ban.details.is_active(now);
+                if !active {
+                    info!(network = %ban.network, "IP ban lifted");
+                }
+                active
+            });
+            before - banned_ips.len()
+        };
+        if ips_lifted > 0 {
+            self.schedule_save(DataFile::BannedIps);
+        }
+        let licenses_lifted = self.licenses.write().unwrap().clear_expired_bans(now);
+        for key in &licenses_lifted {
+            info!(license = %Redacted(key), "License ban lifted");
+        }
+        if !licenses_lifted.is_empty() {
+            self.schedule_save(DataFile::Licenses);
+        }
+        hwids_lifted + ips_lifted + licenses_lifted.len()
+    }
+
+    /// Bans every license `hwid` has used and every other HWID those licenses were used
+    /// on. Existing bans are left as they are. Returns how many licenses and HWIDs were
+    /// newly banned.
+    pub fn cascade_ban(&self, hwid: &str, details: &BanDetails) -> (usize, usize) {
+        let now = details.created_at;
+        let mut related_hwids = HashSet::new();
+        let mut licenses_banned = Vec::new();
+        {
+            let mut licenses = self.licenses.write().unwrap();
+            let keys = licenses
+                .for_hwid(hwid)
+                .map(|license| license.key.clone())
+                .collect::<Vec<_>>();
+            for key in keys {
+                let Some(license) = licenses.get_mut(&key) else {
+                    continue;
+                };
+                related_hwids.extend(license.hwids.iter().cloned());
+                if license.active_ban(now).is_none() {
+                    license.ban = Some(details.clone());
+                    licenses_banned.push(key);
+                }
+            }
+        }
+        related_hwids.remove(hwid);
+
+        let mut hwids_banned = Vec::new();
+        {
+            let mut banned_hwids = self.banned_hwids.write().unwrap();
+            for related in related_hwids {
+                if banned_hwids
+                    .get(&related)
+                    .is_some_and(|ban| ban.details.is_active(now))
+                {
+                    continue;
+                }
+                banned_hwids.insert(
+                    related.clone(),
+                    HwidBan {
+                        hwid: related.clone(),
+                        details: details.clone(),
+                    },
+                );
+                hwids_banned.push(related);
+            }
+        }
+
+        if !licenses_banned.is_empty() {
+            self.schedule_save(DataFile::Licenses);
+        }
+        if !hwids_banned.is_empty() {
+            self.schedule_save(DataFile::BannedHwids);
+        }
+        for key in &licenses_banned {
+            webhooks::license_revoked(self, key, details);
+        }
+        for related in &hwids_banned {
+            webhooks::hwid_banned(self, related, details);
+        }
+        (licenses_banned.len(), hwids_banned.len())
+    }
+
+    /// Generates a key not used by any license in `licenses`, or `None` after
+    /// `keys.regen_limit` collisions.
+    fn generate_key(&self, licenses: &LicenseStore) -> Option<String> {
+        for _ in 0..=self.config.keys.regen_limit {
+            let key = rand::rng()
+                .sample_iter(&Alphanumeric)
+                .take(16)
+                .map(char::from)
+                .collect::<String>()
+                .to_uppercase();
+            assert!(
+                LICENSE_REGEX.is_match(&key),
+                "Generated license key does not match regex"
+            );
+            //regenerate license if it already exists
+            if !licenses.contains(&key) {
+                return Some(key);
+            }
+        }
+        None
+    }
+
+    /// Creates a license for `product_ids` lasting `days` once activated, returning its key.
+    pub fn mint_license(
+        &self,
+        product_ids: &[String],
+        days: u64,
+        reseller: Option<&str>,
+    ) -> Option<String> {
+        let mut licenses = self.licenses.write().unwrap();
+        let license = self.new_license(&licenses, product_ids, days, reseller)?;
+        licenses.insert(license.clone());
+
+        // Save to file after modification
+        drop(licenses); // Release the lock before saving
+        self.schedule_save(DataFile::Licenses);
+        self.license_created(&license, days);
+        Some(license.key)
+    }
+
+    /// A license with a key unused by `licenses`, left for the caller to insert.
+    pub fn new_license(
+        &self,
+        licenses: &LicenseStore,
+        product_ids: &[String],
+        days: u64,
+        reseller: Option<&str>,
+    ) -> Option<License> {
+        let key = self.generate_key(licenses)?;
+        let mut license = License::new(key, product_ids).set_days(days);
+        license.reseller = reseller.map(str::to_string);
+        Some(license)
+    }
+
+    /// Counts and announces a license once it is in the store. Call without holding
+    /// any lock, webhook receivers may already be told about it.
+    pub fn license_created(&self, license: &License, days: u64) {
+        METRICS.record_license_created();
+        webhooks::emit(
+            self,
+            WebhookEvent::LicenseCreated,
+            serde_json::json!({
+                "license": license.key,
+                "product_ids": license.product_ids,
+                "duration": license.duration,
+                "reseller": license.reseller,
+            }),
+        );
+        info!(
+            license = %Redacted(&license.key),
+            days,
+            product_ids = ?license.product_ids,
+            reseller = ?license.reseller,
+            "License created"
+        );
+    }
+
+    /// Drops rotated archive segments older than `retention_secs`, returning how many
+    /// licenses they held.
+    pub fn prune_archive(&self, retention_secs: u64) -> Result<usize, Box<dyn std::error::Error>> {
+        self.archive.prune(retention_secs)
     }
 }
 
+/// Rejects admin requests whose `key` is not the API key.
+pub(crate) fn check_key(key: &str, state: &State) -> Result<()> {
+    if key != state.config.security.api_key {
+        warn!(key = %Redacted(key), "Rejected request with an invalid API key");
+        return Err(error::InternalError::from_response(
+            "Invalid API key.",
+            HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid API key.")),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+pub(crate) fn bad_request(message: &str) -> error::Error {
+    error::InternalError::from_response(
+        message.to_string(),
+        HttpResponse::BadRequest().json(ErrorResponse::new(message)),
+    )
+    .into()
+}
+
+pub(crate) fn not_found(message: &str) -> error::Error {
+    error::InternalError::from_response(
+        message.to_string(),
+        HttpResponse::NotFound().json(ErrorResponse::new(message)),
+    )
+    .into()
+}
+
 #[post("/create-license")]
 async fn create_license(
     req: web::Json<CreateRequest>,
     state: web::Data<State>,
 ) -> Result<impl Responder> {
-    if req.key != API_KEY.as_str() {
-        //fixme
+    // Resellers create licenses with their own key, within their quota
+    let reseller = if req.key == state.config.security.api_key {
+        None
+    } else if let Some(id) = resellers::find(&state, &req.key) {
+        Some(id)
+    } else {
+        warn!(key = %Redacted(&req.key), "Rejected request with an invalid API key");
         return Err(error::InternalError::from_response(
             "Invalid API key.",
             HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid API key.")),
         )
         .into());
-    }
+    };
 
-    if req.days > std::u64::MAX / (24 * 60 * 60) {
+    if req.days > state.config.max_days() {
         return Err(error::InternalError::from_response(
             "Invalid number of days.",
             HttpResponse::BadRequest().json(ErrorResponse::new("Invalid number of days.")),
@@ -129,64 +600,123 @@ async fn create_license(
         .into());
     }
 
-    let mut licenses = state.licenses.lock().unwrap();
-    let mut s;
-    let mut regen_counter = 0;
-    loop {
-        if regen_counter > LICENSE_REGEN_LIMIT {
-            return Err(error::InternalError::from_response(
+    let minted = match &reseller {
+        Some(id) => resellers::mint(&state, id, &req.product_ids, req.days)?,
+        None => state.mint_license(&req.product_ids, req.days, None),
+    };
+    let Some(s) = minted else {
+        return Err(error::InternalError::from_response(
+            "Failed to generate a unique license key.",
+            HttpResponse::InternalServerError().json(ErrorResponse::new(
                 "Failed to generate a unique license key.",
-                HttpResponse::InternalServerError().json(ErrorResponse::new(
-                    "Failed to generate a unique license key.",
-                )),
-            )
-            .into());
-        }
-        s = rand::rng()
-            .sample_iter(&Alphanumeric)
-            .take(16)
-            .map(char::from)
-            .collect::<String>()
-            .to_uppercase();
-        assert!(
-            LICENSE_REGEX.is_match(&s),
-            "Generated license key does not match regex"
-        );
-        if licenses.iter().any(|license| license.key == s) {
-            //regenerate license if it already exists, and add 1 to the regen counter
-            regen_counter += 1;
-        } else {
-            break;
-        }
-    }
-    let license = License::new(s.clone(), &req.product_ids).set_days(req.days);
-    licenses.push(license);
-
-    // Save to file after modification
-    drop(licenses); // Release the lock before saving
-    if let Err(e) = state.save_licenses() {
-        eprintln!("Failed to save licenses: {}", e);
-    }
+            )),
+        )
+        .into());
+    };
 
     Ok(HttpResponse::Created().json(CreateResponse { license: s }))
 }
 
 #[post("/auth")]
-async fn auth(req: web::Json<AuthRequest>, state: web::Data<State>) -> Result<impl Responder> {
-    dbg!(&req);
+async fn auth(
+    http: HttpRequest,
+    req: web::Json<AuthRequest>,
+    state: web::Data<State>,
+) -> Result<HttpResponse> {
+    let result = authenticate(http, req, &state);
+    let status = match &result {
+        Ok(response) => response.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    // Invalid, expired and banned licenses, not a product in maintenance or an
+    // outdated client, which would make every planned downtime look like an attack
+    if status == StatusCode::UNAUTHORIZED {
+        webhooks::record_auth_failure(&state);
+    }
+    result
+}
 
-    if state
+fn authenticate(
+    http: HttpRequest,
+    req: web::Json<AuthRequest>,
+    state: &State,
+) -> Result<HttpResponse> {
+    tracing::debug!(request = ?req, "Auth request");
+
+    let session_key = match req.client_public_key.as_deref() {
+        Some(client_public_key) => match sessions::agree_key(client_public_key) {
+            Some(agreed) => Some(agreed),
+            None => {
+                return Ok(HttpResponse::BadRequest()
+                    .json(ErrorResponse::new("Invalid client public key.")));
+            }
+        },
+        None => None,
+    };
+
+    let now = unix_now();
+    let client_ip = ip::client_ip(&http, &state.config.security);
+    let ip_ban = client_ip.and_then(|client_ip| {
+        state
+            .banned_ips
+            .read()
+            .unwrap()
+            .iter()
+            .find(|ban| ban.network.contains(&client_ip) && ban.details.is_active(now))
+            .map(|ban| ban.details.message("IP address"))
+    });
+    if let Some(message) = ip_ban {
+        METRICS.record_auth(AuthOutcome::Banned);
+        info!(ip = ?client_ip, hwid = %req.hwid, outcome = "banned", "Auth rejected");
+        return Ok(HttpResponse::Unauthorized().json(ErrorResponse::new(&message)));
+    }
+
+    let ban = state
         .banned_hwids
-        .lock()
+        .read()
         .unwrap()
-        .iter()
-        .find(|entry| **entry == req.hwid)
-        .is_some()
-    {
-        return Ok(HttpResponse::Unauthorized().json(ErrorResponse::new("Your HWID is banned.")));
+        .get(&req.hwid)
+        .filter(|ban| ban.details.is_active(now))
+        .map(|ban| ban.details.message("HWID"));
+    if let Some(message) = ban {
+        METRICS.record_auth(AuthOutcome::Banned);
+        info!(hwid = %req.hwid, outcome = "banned", "Auth rejected");
+        return Ok(HttpResponse::Unauthorized().json(ErrorResponse::new(&message)));
+    }
+
+    if let Some(message) = products::disabled_message(state, &req.product_id) {
+        METRICS.record_auth(AuthOutcome::Disabled);
+        info!(product_id = %req.product_id, outcome = "disabled", "Auth rejected");
+        return Ok(HttpResponse::ServiceUnavailable().json(ErrorResponse::new(&message)));
     }
 
+    // Refuse outdated clients before they learn anything about the license
+    let update = match versions::check(
+        state.config.versions.get(&req.product_id),
+        req.client_version.as_deref(),
+    ) {
+        VersionCheck::Current => None,
+        VersionCheck::UpdateAvailable(update) => Some(update),
+        VersionCheck::UpdateRequired(update) => {
+            METRICS.record_auth(AuthOutcome::Outdated);
+            info!(
+                product_id = %req.product_id,
+                client_version = ?req.client_version,
+                outcome = "outdated",
+                "Auth rejected"
+            );
+            return Ok(HttpResponse::build(StatusCode::UPGRADE_REQUIRED).json(
+                UpdateRequiredResponse {
+                    error: "Update required.".to_string(),
+                    update,
+                },
+            ));
+        }
+    };
+
     if !LICENSE_REGEX.is_match(&req.license) {
+        METRICS.record_auth(AuthOutcome::Invalid);
+        info!(license = %Redacted(&req.license), outcome = "invalid", "Auth rejected");
         return Err(error::InternalError::from_response(
             "Not a valid license.",
             HttpResponse::Unauthorized().json(ErrorResponse::new("Not a valid license.")),
@@ -194,64 +724,187 @@ async fn auth(req: web::Json<AuthRequest>, state: web::Data<State>) -> Result<im
         .into());
     }
 
+    let matches = |license: &License| license.product_ids.contains(&req.product_id);
+    // An activated license already seen on this HWID only needs the read lock
+    let known = state
+        .licenses
+        .read()
+        .unwrap()
+        .get(&req.license)
+        .filter(|license| {
+            matches(license)
+                && license.used
+                && !license.is_expired(now)
+                && license.active_ban(now).is_none()
+                && license.hwids.contains(&req.hwid)
+        })
+        .map(|license| (license.start, license.duration, license.canary.clone()));
+
     let mut save_needed = false;
-    let result = {
-        let mut licenses = state.licenses.lock().unwrap();
-        if let Some(license) = licenses
-            .iter_mut()
-            .find(|entry| entry.key == req.license && entry.product_ids.contains(&req.product_id))
-        {
-            if !license.used {
-                license.start();
-                save_needed = true;
+    let mut expired = false;
+    let mut archive = None;
+    let mut banned = None;
+    let mut activated = None;
+    let found = match known {
+        Some(found) => Some(found),
+        None => {
+            // Taken before the licenses, in the same order as the expiry sweep
+            let disabled_products = state.disabled_products.read().unwrap();
+            let mut licenses = state.licenses.write().unwrap();
+            match licenses
+                .get_mut(&req.license)
+                .filter(|license| matches(license))
+            {
+                None => None,
+                Some(license) if license.active_ban(now).is_some() => {
+                    banned = license.active_ban(now).map(|ban| ban.message("license"));
+                    None
+                }
+                Some(license) => {
+                    if !license.used {
+                        license.start();
+                        save_needed = true;
+                        if license.canary.is_none() {
+                            activated = Some((license.start, license.duration));
+                        }
+                    }
+                    if license.is_expired(now) {
+                        expired = true;
+                        // A paused license gets its time back once its product is enabled,
+                        // so like in the sweep it stays until then
+                        if !products::is_paused(&disabled_products, license) {
+                            //remove expired license from db to save on search time, add it to archive file
+                            archive = licenses.remove(&req.license);
+                            save_needed = true;
+                        }
+                        None
+                    } else {
+                        let found = (license.start, license.duration, license.canary.clone());
+                        save_needed |= licenses.record_hwid(&req.license, &req.hwid);
+                        Some(found)
+                    }
+                }
             }
-            let time_remaining = (license.start + license.duration) as i64
-                - std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap_or_default()
-                    .as_secs() as i64;
-            if time_remaining <= 0 {
-                //remove expired license from db to save on search time, add it to archive file
-                let key = license.key.clone(); //borrow checker
-                state.archive_license(&license);
-                licenses.retain(|l| l.key != key);
-                save_needed = true;
-                Err(error::InternalError::from_response(
-                    "Your license has expired.",
-                    HttpResponse::Unauthorized()
-                        .json(ErrorResponse::new("Your license has expired.")),
-                )
-                .into())
-            } else {
-                Ok(HttpResponse::Ok().json(AuthResponse {
-                    license_start: license.start,
-                    license_duration: license.duration,
-                    time_remaining,
-                }))
-            }
-        } else {
-            Err(error::InternalError::from_response(
-                "Not a valid license.",
-                HttpResponse::Unauthorized().json(ErrorResponse::new("Not a valid license.")),
-            )
-            .into())
         }
     };
 
-    // Save if license was activated
+    // Save if license was activated or changed
     if save_needed {
-        if let Err(e) = state.save_licenses() {
-            eprintln!("Failed to save licenses: {}", e);
+        state.schedule_save(DataFile::Licenses);
+    }
+    if let Some((start, duration)) = activated {
+        webhooks::emit(
+            state,
+            WebhookEvent::LicenseActivated,
+            serde_json::json!({
+                "license": req.license,
+                "product_id": req.product_id,
+                "hwid": req.hwid,
+                "start": start,
+                "duration": duration,
+            }),
+        );
+    }
+
+    if let Some(message) = banned {
+        METRICS.record_auth(AuthOutcome::Banned);
+        info!(license = %Redacted(&req.license), outcome = "banned", "Auth rejected");
+        return Err(error::InternalError::from_response(
+            "License is banned.",
+            HttpResponse::Unauthorized().json(ErrorResponse::new(&message)),
+        )
+        .into());
+    }
+
+    if expired {
+        if let Some(license) = archive
+            && let Err(e) = state.archive_license(license)
+        {
+            error!(error = %e, license = %Redacted(&req.license), "Failed to archive license");
         }
+        METRICS.record_auth(AuthOutcome::Expired);
+        info!(license = %Redacted(&req.license), outcome = "expired", "Auth rejected");
+        return Err(error::InternalError::from_response(
+            "Your license has expired.",
+            HttpResponse::Unauthorized().json(ErrorResponse::new("Your license has expired.")),
+        )
+        .into());
     }
 
-    result
+    let Some((license_start, license_duration, canary)) = found else {
+        METRICS.record_auth(AuthOutcome::Invalid);
+        info!(
+            license = %Redacted(&req.license),
+            product_id = %req.product_id,
+            outcome = "invalid",
+            "Auth rejected"
+        );
+        return Err(error::InternalError::from_response(
+            "Not a valid license.",
+            HttpResponse::Unauthorized().json(ErrorResponse::new("Not a valid license.")),
+        )
+        .into());
+    };
+
+    // Canary licenses get the same answer as real ones
+    if let Some(canary) = &canary {
+        canary::record_hit(
+            state,
+            &req.license,
+            canary,
+            &req.product_id,
+            &req.hwid,
+            client_ip,
+        );
+    } else if let Some(message) = anomaly::observe(
+        state,
+        &req.license,
+        &req.product_id,
+        &req.hwid,
+        client_ip,
+        now,
+    ) {
+        METRICS.record_auth(AuthOutcome::Banned);
+        info!(license = %Redacted(&req.license), outcome = "suspended", "Auth rejected");
+        return Err(error::InternalError::from_response(
+            "License is banned.",
+            HttpResponse::Unauthorized().json(ErrorResponse::new(&message)),
+        )
+        .into());
+    }
+
+    let time_remaining = (license_start + license_duration) as i64 - now as i64;
+    let session = state.sessions.issue(
+        &req.license,
+        &req.product_id,
+        &req.hwid,
+        session_key.as_ref().map(|(_, key)| *key),
+        state.config.sessions.ttl_secs,
+        now,
+    );
+    METRICS.record_auth(AuthOutcome::Ok);
+    info!(
+        license = %Redacted(&req.license),
+        product_id = %req.product_id,
+        hwid = %req.hwid,
+        time_remaining,
+        "Auth succeeded"
+    );
+    Ok(HttpResponse::Ok().json(AuthResponse {
+        license_start,
+        license_duration,
+        time_remaining,
+        session,
+        server_public_key: session_key.map(|(public_key, _)| public_key),
+        update,
+    }))
 }
 
 #[post("/ban-hwid")]
-async fn ban_hwid(req: web::Json<HwidRequest>, state: web::Data<State>) -> Result<impl Responder> {
-    if req.key != API_KEY.as_str() {
+async fn ban_hwid(req: web::Json<BanRequest>, state: web::Data<State>) -> Result<impl Responder> {
+    if req.key != state.config.security.api_key {
         //fixme
+        warn!(key = %Redacted(&req.key), "Rejected request with an invalid API key");
         return Err(error::InternalError::from_response(
             "Invalid API key.",
             HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid API key.")),
@@ -259,18 +912,54 @@ async fn ban_hwid(req: web::Json<HwidRequest>, state: web::Data<State>) -> Resul
         .into());
     }
 
+    if req.options.duration_secs == Some(0) {
+        return Err(error::InternalError::from_response(
+            "Invalid ban duration.",
+            HttpResponse::BadRequest().json(ErrorResponse::new("Invalid ban duration.")),
+        )
+        .into());
+    }
+
+    let details = req.options.details(unix_now());
     {
-        let mut banned_hwids = state.banned_hwids.lock().unwrap();
-        if !banned_hwids.contains(&req.hwid) {
-            banned_hwids.push(req.hwid.clone());
-        }
+        let ban = HwidBan {
+            hwid: req.hwid.clone(),
+            details: details.clone(),
+        };
+        state
+            .banned_hwids
+            .write()
+            .unwrap()
+            .insert(req.hwid.clone(), ban);
     }
 
     // Save to file after modification
-    if let Err(e) = state.save_banned_hwids() {
-        eprintln!("Failed to save banned HWIDs: {}", e);
-    }
+    state.schedule_save(DataFile::BannedHwids);
+    webhooks::hwid_banned(&state, &req.hwid, &details);
+    let (licenses_banned, hwids_banned) = if req.cascade {
+        state.cascade_ban(&req.hwid, &details)
+    } else {
+        (0, 0)
+    };
+    let licenses = state.licenses.read().unwrap().for_hwid(&req.hwid).count();
+    info!(
+        hwid = %req.hwid,
+        licenses,
+        reason = ?req.options.reason,
+        created_by = ?req.options.created_by,
+        duration_secs = ?req.options.duration_secs,
+        cascade = req.cascade,
+        licenses_banned,
+        hwids_banned,
+        "HWID banned"
+    );
 
+    if req.cascade {
+        return Ok(HttpResponse::Ok().json(ErrorResponse::new(&format!(
+            "HWID banned successfully, along with {} license(s) and {} other HWID(s).",
+            licenses_banned, hwids_banned
+        ))));
+    }
     Ok(HttpResponse::Ok().json(ErrorResponse::new("HWID banned successfully.")))
 }
 
@@ -279,8 +968,9 @@ async fn unban_hwid(
     req: web::Json<HwidRequest>,
     state: web::Data<State>,
 ) -> Result<impl Responder> {
-    if req.key != API_KEY.as_str() {
+    if req.key != state.config.security.api_key {
         //fixme
+        warn!(key = %Redacted(&req.key), "Rejected request with an invalid API key");
         return Err(error::InternalError::from_response(
             "Invalid API key.",
             HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid API key.")),
@@ -289,56 +979,536 @@ async fn unban_hwid(
     }
 
     {
-        let mut banned_hwids = state.banned_hwids.lock().unwrap();
-        dbg!(&banned_hwids);
-        dbg!(&req.hwid);
-        if let Some(pos) = banned_hwids.iter().position(|x| *x == req.hwid) {
-            banned_hwids.remove(pos);
-        }
-        dbg!(&banned_hwids);
+        state.banned_hwids.write().unwrap().remove(&req.hwid);
     }
 
     // Save to file after modification
-    if let Err(e) = state.save_banned_hwids() {
-        eprintln!("Failed to save banned HWIDs: {}", e);
-    }
+    state.schedule_save(DataFile::BannedHwids);
+    info!(hwid = %req.hwid, unbanned_by = ?req.unbanned_by, "HWID unbanned");
 
     Ok(HttpResponse::Ok().json(ErrorResponse::new("HWID unbanned successfully.")))
 }
 
+#[post("/ban-license")]
+async fn ban_license(
+    req: web::Json<LicenseBanRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    if req.key != state.config.security.api_key {
+        warn!(key = %Redacted(&req.key), "Rejected request with an invalid API key");
+        return Err(error::InternalError::from_response(
+            "Invalid API key.",
+            HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid API key.")),
+        )
+        .into());
+    }
+
+    if req.options.duration_secs == Some(0) {
+        return Err(error::InternalError::from_response(
+            "Invalid ban duration.",
+            HttpResponse::BadRequest().json(ErrorResponse::new("Invalid ban duration.")),
+        )
+        .into());
+    }
+
+    let details = req.options.details(unix_now());
+    {
+        let mut licenses = state.licenses.write().unwrap();
+        let Some(license) = licenses.get_mut(&req.license) else {
+            return Err(error::InternalError::from_response(
+                "Not a valid license.",
+                HttpResponse::NotFound().json(ErrorResponse::new("Not a valid license.")),
+            )
+            .into());
+        };
+        license.ban = Some(details.clone());
+    }
+
+    // Save to file after modification
+    state.schedule_save(DataFile::Licenses);
+    webhooks::license_revoked(&state, &req.license, &details);
+    info!(
+        license = %Redacted(&req.license),
+        reason = ?req.options.reason,
+        created_by = ?req.options.created_by,
+        duration_secs = ?req.options.duration_secs,
+        "License banned"
+    );
+
+    Ok(HttpResponse::Ok().json(ErrorResponse::new("License banned successfully.")))
+}
+
+#[post("/unban-license")]
+async fn unban_license(
+    req: web::Json<LicenseUnbanRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    if req.key != state.config.security.api_key {
+        warn!(key = %Redacted(&req.key), "Rejected request with an invalid API key");
+        return Err(error::InternalError::from_response(
+            "Invalid API key.",
+            HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid API key.")),
+        )
+        .into());
+    }
+
+    {
+        let mut licenses = state.licenses.write().unwrap();
+        let Some(license) = licenses.get_mut(&req.license) else {
+            return Err(error::InternalError::from_response(
+                "Not a valid license.",
+                HttpResponse::NotFound().json(ErrorResponse::new("Not a valid license.")),
+            )
+            .into());
+        };
+        license.ban = None;
+    }
+
+    // Save to file after modification
+    state.schedule_save(DataFile::Licenses);
+    info!(license = %Redacted(&req.license), unbanned_by = ?req.unbanned_by, "License unbanned");
+
+    Ok(HttpResponse::Ok().json(ErrorResponse::new("License unbanned successfully.")))
+}
+
+#[post("/ban-ip")]
+async fn ban_ip(req: web::Json<IpBanRequest>, state: web::Data<State>) -> Result<impl Responder> {
+    if req.key != state.config.security.api_key {
+        warn!(key = %Redacted(&req.key), "Rejected request with an invalid API key");
+        return Err(error::InternalError::from_response(
+            "Invalid API key.",
+            HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid API key.")),
+        )
+        .into());
+    }
+
+    let Some(network) = ip::parse_network(&req.ip) else {
+        return Err(error::InternalError::from_response(
+            "Invalid IP address or CIDR range.",
+            HttpResponse::BadRequest()
+                .json(ErrorResponse::new("Invalid IP address or CIDR range.")),
+        )
+        .into());
+    };
+
+    if req.options.duration_secs == Some(0) {
+        return Err(error::InternalError::from_response(
+            "Invalid ban duration.",
+            HttpResponse::BadRequest().json(ErrorResponse::new("Invalid ban duration.")),
+        )
+        .into());
+    }
+
+    {
+        let mut banned_ips = state.banned_ips.write().unwrap();
+        banned_ips.retain(|ban| ban.network != network);
+        banned_ips.push(IpBan {
+            network,
+            details: req.options.details(unix_now()),
+        });
+    }
+
+    // Save to file after modification
+    state.schedule_save(DataFile::BannedIps);
+    info!(
+        network = %network,
+        reason = ?req.options.reason,
+        created_by = ?req.options.created_by,
+        duration_secs = ?req.options.duration_secs,
+        "IP banned"
+    );
+
+    Ok(HttpResponse::Ok().json(ErrorResponse::new("IP banned successfully.")))
+}
+
+#[post("/unban-ip")]
+async fn unban_ip(
+    req: web::Json<IpUnbanRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    if req.key != state.config.security.api_key {
+        warn!(key = %Redacted(&req.key), "Rejected request with an invalid API key");
+        return Err(error::InternalError::from_response(
+            "Invalid API key.",
+            HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid API key.")),
+        )
+        .into());
+    }
+
+    let Some(network) = ip::parse_network(&req.ip) else {
+        return Err(error::InternalError::from_response(
+            "Invalid IP address or CIDR range.",
+            HttpResponse::BadRequest()
+                .json(ErrorResponse::new("Invalid IP address or CIDR range.")),
+        )
+        .into());
+    };
+
+    let removed = {
+        let mut banned_ips = state.banned_ips.write().unwrap();
+        let before = banned_ips.len();
+        banned_ips.retain(|ban| ban.network != network);
+        before != banned_ips.len()
+    };
+    if !removed {
+        return Err(error::InternalError::from_response(
+            "IP ban not found.",
+            HttpResponse::NotFound().json(ErrorResponse::new("IP ban not found.")),
+        )
+        .into());
+    }
+
+    // Save to file after modification
+    state.schedule_save(DataFile::BannedIps);
+    info!(network = %network, unbanned_by = ?req.unbanned_by, "IP unbanned");
+
+    Ok(HttpResponse::Ok().json(ErrorResponse::new("IP unbanned successfully.")))
+}
+
+#[post("/ip-bans")]
+async fn ip_bans(req: web::Json<AdminRequest>, state: web::Data<State>) -> Result<impl Responder> {
+    if req.key != state.config.security.api_key {
+        warn!(key = %Redacted(&req.key), "Rejected request with an invalid API key");
+        return Err(error::InternalError::from_response(
+            "Invalid API key.",
+            HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid API key.")),
+        )
+        .into());
+    }
+
+    let now = unix_now();
+    let bans = state
+        .banned_ips
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|ban| ban.details.is_active(now))
+        .cloned()
+        .collect();
+    Ok(HttpResponse::Ok().json(IpBansResponse { bans }))
+}
+
+/// Default and maximum number of results returned by an archive search.
+const ARCHIVE_SEARCH_LIMIT: usize = 100;
+const ARCHIVE_SEARCH_MAX_LIMIT: usize = 1000;
+
+#[post("/archive/search")]
+async fn archive_search(
+    req: web::Json<ArchiveSearchRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    if req.key != state.config.security.api_key {
+        warn!(key = %Redacted(&req.key), "Rejected request with an invalid API key");
+        return Err(error::InternalError::from_response(
+            "Invalid API key.",
+            HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid API key.")),
+        )
+        .into());
+    }
+
+    let limit = req
+        .limit
+        .unwrap_or(ARCHIVE_SEARCH_LIMIT)
+        .min(ARCHIVE_SEARCH_MAX_LIMIT);
+    let req = req.into_inner();
+    let state = state.clone();
+    let result = web::block(move || {
+        state
+            .archive
+            .search(
+                |record| {
+                    req.license
+                        .as_ref()
+                        .is_none_or(|prefix| record.license.key.starts_with(prefix.as_str()))
+                        && req.product_id.as_ref().is_none_or(|product_id| {
+                            record.license.product_ids.contains(product_id)
+                        })
+                },
+                limit,
+            )
+            .map_err(|e| e.to_string())
+    })
+    .await?;
+
+    match result {
+        Ok(licenses) => Ok(HttpResponse::Ok().json(ArchiveSearchResponse { licenses })),
+        Err(e) => {
+            error!(error = %e, "Failed to search the license archive");
+            Err(error::InternalError::from_response(
+                "Failed to search the archive.",
+                HttpResponse::InternalServerError()
+                    .json(ErrorResponse::new("Failed to search the archive.")),
+            )
+            .into())
+        }
+    }
+}
+
+#[post("/archive/restore")]
+async fn archive_restore(
+    req: web::Json<ArchiveRestoreRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    if req.key != state.config.security.api_key {
+        warn!(key = %Redacted(&req.key), "Rejected request with an invalid API key");
+        return Err(error::InternalError::from_response(
+            "Invalid API key.",
+            HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid API key.")),
+        )
+        .into());
+    }
+
+    if req.days.is_some_and(|days| days > state.config.max_days()) {
+        return Err(error::InternalError::from_response(
+            "Invalid number of days.",
+            HttpResponse::BadRequest().json(ErrorResponse::new("Invalid number of days.")),
+        )
+        .into());
+    }
+
+    if state.licenses.read().unwrap().contains(&req.license) {
+        return Err(error::InternalError::from_response(
+            "License is already active.",
+            HttpResponse::Conflict().json(ErrorResponse::new("License is already active.")),
+        )
+        .into());
+    }
+
+    let license = req.license.clone();
+    let archive_state = state.clone();
+    let taken = web::block(move || {
+        archive_state
+            .archive
+            .take(&license)
+            .map_err(|e| e.to_string())
+    })
+    .await?;
+    let archived = match taken {
+        Ok(archived) => archived,
+        Err(e) => {
+            error!(error = %e, "Failed to take the license out of the archive");
+            return Err(error::InternalError::from_response(
+                "Failed to search the archive.",
+                HttpResponse::InternalServerError()
+                    .json(ErrorResponse::new("Failed to search the archive.")),
+            )
+            .into());
+        }
+    };
+    let Some(archived) = archived else {
+        return Err(error::InternalError::from_response(
+            "License not found in the archive.",
+            HttpResponse::NotFound().json(ErrorResponse::new("License not found in the archive.")),
+        )
+        .into());
+    };
+
+    // Restored licenses start over unused, the clock starts again on the next auth. The
+    // rest, bans and reseller included, is kept as it was
+    let mut license = archived.license;
+    license.used = false;
+    license.start = 0;
+    if let Some(days) = req.days {
+        license.duration = days * 24 * 60 * 60;
+    }
+    state.licenses.write().unwrap().insert(license);
+
+    state.schedule_save(DataFile::Licenses);
+    info!(license = %Redacted(&req.license), days = ?req.days, "License restored from archive");
+
+    Ok(HttpResponse::Ok().json(ErrorResponse::new("License restored successfully.")))
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    // if let Err(e) = std::env::var("API_KEY") {
-    //     eprintln!("API_KEY environment variable is not set: {}", e);
-    //     eprintln!("Please set it before running the server.");
-    //     std::process::exit(1);
-    // }
-    let state = web::Data::new(State::new());
-
-    HttpServer::new(move || {
+    let (config, app_configs) = match Config::from_args() {
+        Ok(Command::Run(config, apps)) => (config, apps),
+        Ok(Command::Exit) => return Ok(()),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    logging::init(&config.logging);
+
+    let bind = config.server.bind.clone();
+    let workers = config.server.workers;
+    let json_payload_limit = config.limits.json_payload_limit;
+    let tls_config = config.tls.clone();
+    let plain_http = tls_config.plain_http;
+    let https_port = tls_config.https_port();
+    let state = load_state(*config, None);
+    let apps = app_configs
+        .into_iter()
+        .map(|(id, config)| {
+            let state = load_state(config, Some(&id));
+            (id, state)
+        })
+        .collect::<Vec<_>>();
+    start_workers(&state, Span::none());
+    for (id, app_state) in &apps {
+        start_workers(app_state, info_span!("app", id = %id));
+    }
+    let shutdown_states = std::iter::once(state.clone())
+        .chain(apps.iter().map(|(_, app_state)| app_state.clone()))
+        .collect::<Vec<_>>();
+    let all_apps = web::Data::new(Apps(
+        std::iter::once((None, state.clone()))
+            .chain(
+                apps.iter()
+                    .map(|(id, app_state)| (Some(id.clone()), app_state.clone())),
+            )
+            .collect(),
+    ));
+
+    let mut server = HttpServer::new(move || {
         let auth_json_config = web::JsonConfig::default()
-            .limit(4096)
-            .error_handler(|err, _| {
+            .limit(json_payload_limit)
+            .error_handler(|err, req| {
+                if req.path().ends_with("/auth") {
+                    METRICS.record_auth(AuthOutcome::Malformed);
+                }
                 error::InternalError::from_response(
                     err,
                     HttpResponse::BadRequest()
-                        .json(ErrorResponse::new("The auth payload was malformed."))
-                        .into(),
+                        .json(ErrorResponse::new("The auth payload was malformed.")),
                 )
                 .into()
             });
 
-        App::new().service(
-            web::scope("/api/v1")
-                .app_data(auth_json_config)
-                .app_data(state.clone())
-                .service(auth)
-                .service(create_license)
-                .service(ban_hwid)
-                .service(unban_hwid),
+        let mut app = App::new()
+            .wrap(TracingLogger::default())
+            .wrap(from_fn(move |req, next| {
+                tls::enforce_https(plain_http, https_port, req, next)
+            }))
+            .app_data(state.clone())
+            .app_data(all_apps.clone())
+            .service(metrics::metrics)
+            .service(health::healthz)
+            .service(health::server_readyz);
+        // Scope level data takes precedence, so handlers see the state of their app
+        for (id, app_state) in &apps {
+            let id = id.clone();
+            app = app
+                .service(
+                    web::scope(&format!("/apps/{}", id))
+                        .app_data(app_state.clone())
+                        .service(health::readyz)
+                        .service(api_scope().app_data(auth_json_config.clone())),
+                )
+                .service(
+                    api_scope()
+                        .guard(guard::fn_guard(move |ctx| {
+                            ctx.head()
+                                .headers()
+                                .get(APP_HEADER)
+                                .is_some_and(|value| value.as_bytes() == id.as_bytes())
+                        }))
+                        .app_data(app_state.clone())
+                        .app_data(auth_json_config.clone()),
+                );
+        }
+        // An unknown application must not fall through to the main one
+        app.service(
+            api_scope()
+                .guard(guard::fn_guard(|ctx| {
+                    !ctx.head().headers().contains_key(APP_HEADER)
+                }))
+                .app_data(auth_json_config),
         )
-    })
-    .bind(("0.0.0.0", 8080))?
-    .run()
-    .await
+    });
+    if let Some(workers) = workers {
+        server = server.workers(workers);
+    }
+    for addr in &bind {
+        server = server.bind(addr)?;
+    }
+    if tls_config.enabled {
+        let rustls_config = match tls::server_config(&tls_config) {
+            Ok(rustls_config) => rustls_config,
+            Err(e) => {
+                error!(error = %e, "Failed to set up TLS");
+                std::process::exit(1);
+            }
+        };
+        for addr in &tls_config.bind {
+            server = server.bind_rustls_0_23(addr, rustls_config.clone())?;
+        }
+    }
+    let result = server.run().await;
+    for state in shutdown_states {
+        state.flush();
+    }
+    result
+}
+
+/// Loads the state of the main application, or of hosted application `app`, exiting
+/// when it can't be.
+fn load_state(config: Config, app: Option<&str>) -> web::Data<State> {
+    let state = match State::new(config) {
+        Ok(state) => web::Data::new(state),
+        Err(e) => {
+            error!(app, error = %e, "Failed to load state");
+            std::process::exit(1);
+        }
+    };
+    info!(
+        app,
+        licenses = state.licenses.read().unwrap().len(),
+        banned_hwids = state.banned_hwids.read().unwrap().len(),
+        "State loaded"
+    );
+    state
+}
+
+/// Starts the maintenance task, save writer and webhook sender of one application.
+fn start_workers(state: &web::Data<State>, span: Span) {
+    actix_web::rt::spawn(maintenance::run(state.clone()).instrument(span.clone()));
+    let writer = state.clone();
+    let writer_span = span.clone();
+    std::thread::spawn(move || writer_span.in_scope(|| writer.write_queued()));
+    let sender = state.clone();
+    std::thread::spawn(move || span.in_scope(|| webhooks::run(&sender)));
+}
+
+/// The admin and client API of an application.
+fn api_scope() -> Scope {
+    web::scope("/api/v1")
+        .service(auth)
+        .service(create_license)
+        .service(ban_hwid)
+        .service(unban_hwid)
+        .service(canary::create_canaries)
+        .service(canary::canary_hits)
+        .service(canary::review_canary_hit)
+        .service(anomaly::anomaly_flags)
+        .service(anomaly::review_anomaly_flag)
+        .service(webhooks::dead_letters)
+        .service(webhooks::redeliver)
+        .service(payloads::payload)
+        .service(variables::session_variables)
+        .service(variables::set_variable)
+        .service(variables::delete_variable)
+        .service(variables::list_variables)
+        .service(announcements::live_announcements)
+        .service(announcements::create_announcement)
+        .service(announcements::update_announcement)
+        .service(announcements::delete_announcement)
+        .service(announcements::list_announcements)
+        .service(products::disable_product)
+        .service(products::enable_product)
+        .service(products::list_disabled_products)
+        .service(payments::stripe)
+        .service(payments::generic)
+        .service(resellers::create_reseller)
+        .service(resellers::list_resellers)
+        .service(resellers::update_reseller)
+        .service(resellers::revoke_reseller)
+        .service(ban_license)
+        .service(unban_license)
+        .service(ban_ip)
+        .service(unban_ip)
+        .service(ip_bans)
+        .service(archive_search)
+        .service(archive_restore)
 }
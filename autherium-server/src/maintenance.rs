@@ -0,0 +1,70 @@
+use actix_web::{rt, web};
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+
+use crate::{State, metrics::METRICS, types::unix_now};
+
+#[derive(Default, Debug)]
+struct SweepReport {
+    archived: usize,
+    archive_purged: usize,
+    bans_lifted: usize,
+}
+
+/// Periodically archives expired licenses, lifts temporary bans and applies the archive
+/// retention policy.
+pub async fn run(state: web::Data<State>) {
+    let period = Duration::from_secs(state.config.maintenance.interval_secs);
+    let mut interval = rt::time::interval(period);
+    // The first tick completes immediately, and startup has just archived everything
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+        let started = Instant::now();
+        let sweep_state = state.clone();
+        let result = web::block(move || sweep(&sweep_state))
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()));
+        let elapsed = started.elapsed();
+
+        match result {
+            Ok(report) => {
+                METRICS.record_maintenance(
+                    true,
+                    elapsed.as_secs_f64(),
+                    report.archive_purged as u64,
+                );
+                info!(
+                    archived = report.archived,
+                    archive_purged = report.archive_purged,
+                    bans_lifted = report.bans_lifted,
+                    elapsed_ms = elapsed.as_millis() as u64,
+                    "Maintenance sweep finished"
+                );
+            }
+            Err(e) => {
+                METRICS.record_maintenance(false, elapsed.as_secs_f64(), 0);
+                error!(error = %e, "Maintenance sweep failed");
+            }
+        }
+    }
+}
+
+fn sweep(state: &State) -> Result<SweepReport, String> {
+    let mut report = SweepReport {
+        archived: state.archive_expired().map_err(|e| e.to_string())?,
+        bans_lifted: state.lift_expired_bans(),
+        ..Default::default()
+    };
+    state
+        .auth_history
+        .prune(unix_now(), state.config.anomaly.history_secs());
+    state.sessions.prune(unix_now());
+    if let Some(days) = state.config.maintenance.archive_retention_days {
+        report.archive_purged = state
+            .prune_archive(days * 24 * 60 * 60)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(report)
+}
@@ -0,0 +1,252 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, get, http::header, web};
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, Opts,
+    Registry, TextEncoder, core::Collector,
+};
+use std::sync::LazyLock;
+
+use crate::{Apps, types::AnomalyRule};
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub enum AuthOutcome {
+    Ok,
+    Expired,
+    Banned,
+    Invalid,
+    Malformed,
+    Outdated,
+    Disabled,
+}
+
+impl AuthOutcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AuthOutcome::Ok => "ok",
+            AuthOutcome::Expired => "expired",
+            AuthOutcome::Banned => "banned",
+            AuthOutcome::Invalid => "invalid",
+            AuthOutcome::Malformed => "malformed",
+            AuthOutcome::Outdated => "outdated",
+            AuthOutcome::Disabled => "disabled",
+        }
+    }
+}
+
+pub struct Metrics {
+    registry: Registry,
+    auth_requests: IntCounterVec,
+    licenses_created: IntCounter,
+    licenses_archived: IntCounter,
+    licenses: IntGaugeVec,
+    storage_save_seconds: HistogramVec,
+    storage_save_failures: IntCounterVec,
+    maintenance_runs: IntCounterVec,
+    maintenance_seconds: Histogram,
+    archive_purged: IntCounter,
+    canary_hits: IntCounter,
+    anomaly_flags: IntCounterVec,
+    webhook_deliveries: IntCounterVec,
+    payload_downloads: IntCounter,
+}
+
+fn register<C: Collector + Clone + 'static>(registry: &Registry, collector: C) -> C {
+    registry.register(Box::new(collector.clone())).unwrap();
+    collector
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("autherium".into()), None).unwrap();
+        let counter =
+            |name: &str, help: &str| register(&registry, IntCounter::new(name, help).unwrap());
+        let counter_vec = |name: &str, help: &str, labels: &[&str]| {
+            register(
+                &registry,
+                IntCounterVec::new(Opts::new(name, help), labels).unwrap(),
+            )
+        };
+
+        Self {
+            auth_requests: counter_vec(
+                "auth_requests_total",
+                "Auth requests by outcome.",
+                &["outcome"],
+            ),
+            licenses_created: counter("licenses_created_total", "Licenses created."),
+            licenses_archived: counter(
+                "licenses_archived_total",
+                "Expired licenses moved to the archive.",
+            ),
+            licenses: register(
+                &registry,
+                IntGaugeVec::new(
+                    Opts::new(
+                        "licenses",
+                        "Licenses currently held, by application (empty for the main one), product and status.",
+                    ),
+                    &["app", "product", "status"],
+                )
+                .unwrap(),
+            ),
+            storage_save_seconds: register(
+                &registry,
+                HistogramVec::new(
+                    HistogramOpts::new(
+                        "storage_save_seconds",
+                        "Time taken to persist a data file.",
+                    ),
+                    &["file"],
+                )
+                .unwrap(),
+            ),
+            storage_save_failures: counter_vec(
+                "storage_save_failures_total",
+                "Failed attempts to persist a data file.",
+                &["file"],
+            ),
+            maintenance_runs: counter_vec(
+                "maintenance_runs_total",
+                "Background maintenance sweeps by result.",
+                &["result"],
+            ),
+            maintenance_seconds: register(
+                &registry,
+                Histogram::with_opts(HistogramOpts::new(
+                    "maintenance_seconds",
+                    "Time taken by a background maintenance sweep.",
+                ))
+                .unwrap(),
+            ),
+            archive_purged: counter(
+                "archive_purged_total",
+                "Archived licenses dropped by the retention policy.",
+            ),
+            canary_hits: counter("canary_hits_total", "Uses of canary licenses."),
+            payload_downloads: counter(
+                "payload_downloads_total",
+                "Payload downloads started, not counting resumed ones.",
+            ),
+            anomaly_flags: counter_vec(
+                "anomaly_flags_total",
+                "Licenses flagged for suspicious activity, by rule.",
+                &["rule"],
+            ),
+            webhook_deliveries: counter_vec(
+                "webhook_deliveries_total",
+                "Webhook delivery attempts by result.",
+                &["result"],
+            ),
+            registry,
+        }
+    }
+
+    pub fn record_auth(&self, outcome: AuthOutcome) {
+        self.auth_requests
+            .with_label_values(&[outcome.as_str()])
+            .inc();
+    }
+
+    pub fn record_license_created(&self) {
+        self.licenses_created.inc();
+    }
+
+    pub fn licenses_archived_by(&self, count: u64) {
+        self.licenses_archived.inc_by(count);
+    }
+
+    pub fn record_canary_hit(&self) {
+        self.canary_hits.inc();
+    }
+
+    pub fn record_payload_download(&self) {
+        self.payload_downloads.inc();
+    }
+
+    pub fn record_webhook_delivery(&self, result: &str) {
+        self.webhook_deliveries.with_label_values(&[result]).inc();
+    }
+
+    pub fn record_anomaly_flag(&self, rule: AnomalyRule) {
+        self.anomaly_flags.with_label_values(&[rule.as_str()]).inc();
+    }
+
+    pub fn record_maintenance(&self, ok: bool, seconds: f64, archive_purged: u64) {
+        let result = if ok { "ok" } else { "error" };
+        self.maintenance_runs.with_label_values(&[result]).inc();
+        self.maintenance_seconds.observe(seconds);
+        self.archive_purged.inc_by(archive_purged);
+    }
+
+    /// Times a save of `file` and counts it as a failure if it returns an error.
+    pub fn observe_save<T, E>(
+        &self,
+        file: &str,
+        save: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E> {
+        let timer = self
+            .storage_save_seconds
+            .with_label_values(&[file])
+            .start_timer();
+        let result = save();
+        timer.observe_duration();
+        if result.is_err() {
+            self.storage_save_failures.with_label_values(&[file]).inc();
+        }
+        result
+    }
+
+    /// Counts the licenses of every application, each under its own `app` label.
+    fn update_license_gauges(&self, apps: &Apps) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.licenses.reset();
+        for (id, state) in &apps.0 {
+            let app = id.as_deref().unwrap_or_default();
+            for license in state.licenses.read().unwrap().iter() {
+                let status = if !license.used {
+                    "unused"
+                } else if license.start + license.duration <= now {
+                    "expired"
+                } else {
+                    "active"
+                };
+                for product_id in &license.product_ids {
+                    self.licenses
+                        .with_label_values(&[app, product_id.as_str(), status])
+                        .inc();
+                }
+            }
+        }
+    }
+}
+
+#[get("/metrics")]
+async fn metrics(req: HttpRequest, apps: web::Data<Apps>) -> impl Responder {
+    // The main application, whose settings cover the whole server
+    let state = &apps.0[0].1;
+    if let Some(token) = &state.config.security.metrics_token {
+        let authorized = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|value| value == token);
+        if !authorized {
+            return HttpResponse::Unauthorized().finish();
+        }
+    }
+
+    METRICS.update_license_gauges(&apps);
+
+    let mut buffer = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&METRICS.registry.gather(), &mut buffer) {
+        tracing::error!(error = %e, "Failed to encode metrics");
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Ok()
+        .content_type(prometheus::TEXT_FORMAT)
+        .body(buffer)
+}
@@ -0,0 +1,398 @@
+use actix_files::NamedFile;
+use actix_web::{
+    HttpRequest, HttpResponse, Result, error, get,
+    http::header::{self, ContentDisposition, DispositionParam, DispositionType},
+    mime, web,
+};
+use futures_util::stream;
+use ring::{aead, digest, hkdf, rand::SecureRandom};
+use serde_derive::Deserialize;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+use tracing::{info, warn};
+
+use crate::{State, logging::Redacted, metrics::METRICS, sessions, types::*, versions::Version};
+
+/// Plaintext bytes in each sealed chunk of an encrypted download.
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+/// HKDF info of the key of a single encrypted download.
+const DOWNLOAD_KEY_INFO: &[u8] = b"autherium payload key v1";
+
+/// SHA-256 of payload files, recomputed only when a file changes.
+#[derive(Default)]
+pub struct PayloadHashes {
+    hashes: Mutex<HashMap<PathBuf, (u64, SystemTime, String)>>,
+}
+
+impl PayloadHashes {
+    fn sha256(&self, path: &Path) -> io::Result<String> {
+        let metadata = fs::metadata(path)?;
+        let (len, modified) = (metadata.len(), metadata.modified()?);
+        if let Some((cached_len, cached_modified, hash)) = self.hashes.lock().unwrap().get(path)
+            && (*cached_len, *cached_modified) == (len, modified)
+        {
+            return Ok(hash.clone());
+        }
+
+        let mut file = fs::File::open(path)?;
+        let mut context = digest::Context::new(&digest::SHA256);
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            context.update(&buffer[..read]);
+        }
+        let hash = context
+            .finish()
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+        self.hashes
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), (len, modified, hash.clone()));
+        Ok(hash)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PayloadQuery {
+    /// Version to download, the newest one when unset.
+    pub version: Option<String>,
+    /// Seal the payload with a key derived from the session key.
+    #[serde(default)]
+    pub encrypted: bool,
+}
+
+struct Payload {
+    version: String,
+    path: PathBuf,
+    sha256: String,
+}
+
+/// Finds the payload of `product_id` in `dir`, laid out as
+/// `<dir>/<product id>/<version>/<file>` with one file per version directory.
+///
+/// Only names read from the directory are ever joined onto it, so a request can't
+/// reach outside of it.
+fn find(
+    dir: &str,
+    product_id: &str,
+    version: Option<&str>,
+    hashes: &PayloadHashes,
+) -> io::Result<Option<Payload>> {
+    let Some(product_dir) = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .find(|entry| entry.file_name().to_str() == Some(product_id))
+        .map(|entry| entry.path())
+    else {
+        return Ok(None);
+    };
+
+    let mut versions = fs::read_dir(&product_dir)?
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let parsed = name.parse::<Version>().ok()?;
+            Some((parsed, name, entry.path()))
+        })
+        .collect::<Vec<_>>();
+    versions.sort_by(|a, b| a.0.cmp(&b.0));
+    let chosen = match version {
+        Some(version) => versions.into_iter().find(|(_, name, _)| name == version),
+        None => versions.pop(),
+    };
+    let Some((_, version, version_dir)) = chosen else {
+        return Ok(None);
+    };
+
+    let files = fs::read_dir(&version_dir)?
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_file()))
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    let [path] = files.as_slice() else {
+        warn!(
+            dir = %version_dir.display(),
+            files = files.len(),
+            "Payload version directory must hold exactly one file"
+        );
+        return Ok(None);
+    };
+    let sha256 = hashes.sha256(path)?;
+    Ok(Some(Payload {
+        version,
+        path: path.clone(),
+        sha256,
+    }))
+}
+
+/// Key for one download, derived from the session key and a random salt sent along
+/// with it, so chunk nonces never repeat under a key when a session downloads again.
+fn download_key(session_key: &[u8; 32], salt: &[u8]) -> Option<aead::LessSafeKey> {
+    let mut key = [0; 32];
+    hkdf::Salt::new(hkdf::HKDF_SHA256, salt)
+        .extract(session_key)
+        .expand(&[DOWNLOAD_KEY_INFO], hkdf::HKDF_SHA256)
+        .ok()?
+        .fill(&mut key)
+        .ok()?;
+    let key = aead::UnboundKey::new(&aead::CHACHA20_POLY1305, &key).ok()?;
+    Some(aead::LessSafeKey::new(key))
+}
+
+/// Nonce of chunk `index`. The first byte marks the last chunk, so a download cut
+/// short at a chunk boundary fails to decrypt instead of passing for a shorter file.
+fn chunk_nonce(index: u64, last: bool) -> aead::Nonce {
+    let mut nonce = [0; aead::NONCE_LEN];
+    nonce[0] = u8::from(last);
+    nonce[4..].copy_from_slice(&index.to_be_bytes());
+    aead::Nonce::assume_unique_for_key(nonce)
+}
+
+/// Length of a payload of `len` bytes once sealed. An empty payload is still sent as
+/// one empty chunk.
+fn sealed_len(len: u64) -> u64 {
+    let chunks = len.div_ceil(CHUNK_SIZE).max(1);
+    len + chunks * aead::CHACHA20_POLY1305.tag_len() as u64
+}
+
+/// Reads a payload and seals it chunk by chunk with ChaCha20-Poly1305.
+struct Encryptor {
+    file: fs::File,
+    key: aead::LessSafeKey,
+    index: u64,
+    remaining: u64,
+    done: bool,
+}
+
+impl Encryptor {
+    fn next_chunk(&mut self) -> io::Result<Option<web::Bytes>> {
+        if self.done {
+            return Ok(None);
+        }
+        // Stop after a failed read, rather than carry on from the wrong offset
+        self.done = true;
+        let mut chunk = vec![0; self.remaining.min(CHUNK_SIZE) as usize];
+        self.file.read_exact(&mut chunk)?;
+        self.remaining -= chunk.len() as u64;
+        let last = self.remaining == 0;
+        self.key
+            .seal_in_place_append_tag(
+                chunk_nonce(self.index, last),
+                aead::Aad::empty(),
+                &mut chunk,
+            )
+            .map_err(|_| io::Error::other("failed to seal payload chunk"))?;
+        self.index += 1;
+        self.done = last;
+        Ok(Some(chunk.into()))
+    }
+}
+
+/// Streams `download` sealed under a key derived from `session_key`.
+async fn encrypted_response(download: &Payload, session_key: &[u8; 32]) -> Result<HttpResponse> {
+    let mut salt = [0; 32];
+    ring::rand::SystemRandom::new()
+        .fill(&mut salt)
+        .map_err(|_| error::ErrorInternalServerError("Failed to generate a download key."))?;
+    let key = download_key(session_key, &salt)
+        .ok_or_else(|| error::ErrorInternalServerError("Failed to generate a download key."))?;
+    let path = download.path.clone();
+    let (file, len) = web::block(move || {
+        let file = fs::File::open(path)?;
+        let len = file.metadata()?.len();
+        Ok::<_, io::Error>((file, len))
+    })
+    .await??;
+
+    let encryptor = Encryptor {
+        file,
+        key,
+        index: 0,
+        remaining: len,
+        done: false,
+    };
+    let body = stream::unfold(encryptor, |mut encryptor| async move {
+        match web::block(move || (encryptor.next_chunk(), encryptor)).await {
+            Ok((Ok(Some(chunk)), encryptor)) => Some((Ok(chunk), encryptor)),
+            Ok((Ok(None), _)) => None,
+            Ok((Err(e), encryptor)) => {
+                warn!(error = %e, "Failed to read payload");
+                Some((Err(e), encryptor))
+            }
+            Err(e) => {
+                warn!(error = %e, "Failed to read payload");
+                None
+            }
+        }
+    });
+
+    let salt = salt
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    Ok(HttpResponse::Ok()
+        .content_type(mime::APPLICATION_OCTET_STREAM)
+        .insert_header(("x-autherium-encryption", "chacha20-poly1305"))
+        .insert_header(("x-autherium-salt", salt))
+        .insert_header(("x-autherium-chunk-size", CHUNK_SIZE))
+        .insert_header(("x-autherium-size", len))
+        .no_chunking(sealed_len(len))
+        .streaming(body))
+}
+
+/// Whether a request asks to continue an earlier download rather than start one.
+fn is_resume(http: &HttpRequest) -> bool {
+    http.headers()
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|range| range.strip_prefix("bytes="))
+        .and_then(|range| range.split('-').next())
+        .and_then(|start| start.trim().parse::<u64>().ok())
+        .is_some_and(|start| start > 0)
+}
+
+/// Streams the product payload to a client holding a session from `/auth`.
+///
+/// Supports range requests so interrupted downloads can resume, and sends the SHA-256
+/// of the whole file in `X-Autherium-SHA256` for the client to check.
+///
+/// With `encrypted`, the payload is sealed in chunks of `CHUNK_SIZE` with
+/// ChaCha20-Poly1305 instead, under a key derived from the session key and the salt in
+/// `X-Autherium-Salt`. Encrypted downloads always send the whole file.
+#[get("/payload")]
+async fn payload(
+    http: HttpRequest,
+    query: web::Query<PayloadQuery>,
+    state: web::Data<State>,
+) -> Result<HttpResponse> {
+    let (token, session) = sessions::authorize(&http, &state)?;
+
+    let dir = state.config.payloads.dir.clone();
+    let product_id = session.product_id.clone();
+    let version = query.version.clone();
+    let lookup_state = state.clone();
+    let found = web::block(move || {
+        find(
+            &dir,
+            &product_id,
+            version.as_deref(),
+            &lookup_state.payload_hashes,
+        )
+    })
+    .await?;
+    let payload = match found {
+        Ok(Some(payload)) => payload,
+        Ok(None) => {
+            return Err(error::InternalError::from_response(
+                "Payload not found.",
+                HttpResponse::NotFound().json(ErrorResponse::new("Payload not found.")),
+            )
+            .into());
+        }
+        Err(e) => {
+            warn!(error = %e, product_id = %session.product_id, "Failed to read payload");
+            return Err(error::InternalError::from_response(
+                "Payload not found.",
+                HttpResponse::NotFound().json(ErrorResponse::new("Payload not found.")),
+            )
+            .into());
+        }
+    };
+
+    let key = match (query.encrypted, &session.key) {
+        (true, Some(key)) => Some(key),
+        (true, None) => {
+            return Err(error::InternalError::from_response(
+                "Session has no key.",
+                HttpResponse::BadRequest().json(ErrorResponse::new("Session has no key.")),
+            )
+            .into());
+        }
+        (false, _) if state.config.payloads.require_encryption => {
+            return Err(error::InternalError::from_response(
+                "Encrypted download required.",
+                HttpResponse::Forbidden().json(ErrorResponse::new("Encrypted download required.")),
+            )
+            .into());
+        }
+        (false, _) => None,
+    };
+
+    let max_downloads = state.config.payloads.max_downloads_per_session;
+    let started = state.sessions.start_download(
+        &token,
+        &format!("{}:{}", payload.version, payload.sha256),
+        key.is_none() && is_resume(&http),
+        max_downloads,
+    );
+    let resume = started == sessions::Download::Resumed;
+    if started == sessions::Download::LimitReached {
+        warn!(
+            license = %Redacted(&session.license),
+            product_id = %session.product_id,
+            "Payload download limit reached"
+        );
+        return Err(error::InternalError::from_response(
+            "Download limit reached.",
+            HttpResponse::TooManyRequests().json(ErrorResponse::new("Download limit reached.")),
+        )
+        .into());
+    }
+
+    let mut response = match key {
+        Some(key) => encrypted_response(&payload, key).await?,
+        None => plain_response(&payload, &http).await?,
+    };
+    for (name, value) in [
+        ("x-autherium-sha256", &payload.sha256),
+        ("x-autherium-version", &payload.version),
+    ] {
+        if let Ok(value) = header::HeaderValue::from_str(value) {
+            response
+                .headers_mut()
+                .insert(header::HeaderName::from_static(name), value);
+        }
+    }
+    if !resume {
+        METRICS.record_payload_download();
+    }
+    info!(
+        license = %Redacted(&session.license),
+        product_id = %session.product_id,
+        version = %payload.version,
+        resume,
+        encrypted = key.is_some(),
+        "Payload download"
+    );
+    Ok(response)
+}
+
+async fn plain_response(download: &Payload, http: &HttpRequest) -> Result<HttpResponse> {
+    let file_name = download
+        .path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("payload")
+        .to_string();
+    let file = NamedFile::open_async(&download.path)
+        .await?
+        .set_content_type(mime::APPLICATION_OCTET_STREAM)
+        .set_content_disposition(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(file_name)],
+        });
+    Ok(file.into_response(http))
+}
@@ -0,0 +1,564 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, Result, error, post, web};
+use serde_json::{Value, json};
+use tracing::{error, info, warn};
+
+use crate::{State, bad_request, storage::DataFile, types::*, webhooks};
+
+/// Most licenses one generic purchase may ask for.
+const MAX_QUANTITY: u32 = 100;
+
+/// `created_by` of the bans put on refunded licenses.
+const REFUNDED_BY: &str = "payments";
+
+/// Stripe checkout events that mean the customer paid.
+const STRIPE_PAID_EVENTS: [&str; 2] = [
+    "checkout.session.completed",
+    "checkout.session.async_payment_succeeded",
+];
+
+fn order_key(provider: &str, order_id: &str) -> String {
+    format!("{}:{}", provider, order_id)
+}
+
+/// Checks the signature of a provider webhook, answering 404 when the provider has no
+/// secret configured so an unused endpoint looks like it doesn't exist.
+fn verify(
+    http: &HttpRequest,
+    body: &[u8],
+    secret: Option<&str>,
+    header: &str,
+    state: &State,
+) -> Result<()> {
+    let Some(secret) = secret else {
+        return Err(error::InternalError::from_response(
+            "Not found.",
+            HttpResponse::NotFound().json(ErrorResponse::new("Not found.")),
+        )
+        .into());
+    };
+    let signature = http
+        .headers()
+        .get(header)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let tolerance = state.config.payments.signature_tolerance_secs;
+    if !webhooks::verify_signature(secret, signature, body, tolerance, unix_now()) {
+        warn!(header, "Rejected payment webhook with an invalid signature");
+        return Err(bad_request("Invalid signature."));
+    }
+    Ok(())
+}
+
+struct Purchase<'a> {
+    provider: &'a str,
+    order_id: &'a str,
+    sku: &'a str,
+    email: Option<&'a str>,
+    payment_ref: Option<&'a str>,
+    quantity: u32,
+}
+
+/// Creates the licenses of a purchase, or returns the ones already created when the
+/// provider sends the same order again.
+fn fulfil(state: &State, purchase: Purchase) -> Result<Vec<String>> {
+    let key = order_key(purchase.provider, purchase.order_id);
+    let mut orders = state.orders.write().unwrap();
+    if let Some(order) = orders.get(&key) {
+        info!(
+            provider = purchase.provider,
+            order_id = %purchase.order_id,
+            "Order already fulfilled"
+        );
+        return Ok(order.licenses.clone());
+    }
+    let Some(sku) = state.config.payments.skus.get(purchase.sku) else {
+        warn!(
+            provider = purchase.provider,
+            order_id = %purchase.order_id,
+            sku = %purchase.sku,
+            "Order for an unknown SKU"
+        );
+        return Err(bad_request("Unknown SKU."));
+    };
+
+    // Mint every license before storing any, so a failed order leaves nothing behind and
+    // nobody hears about licenses that never made it in
+    let mut store = state.licenses.write().unwrap();
+    let mut minted: Vec<License> = Vec::new();
+    while minted.len() < purchase.quantity as usize {
+        let Some(license) = state.new_license(&store, &sku.product_ids, sku.days, None) else {
+            error!(order_id = %purchase.order_id, "Failed to generate licenses for an order");
+            return Err(error::InternalError::from_response(
+                "Failed to generate a unique license key.",
+                HttpResponse::InternalServerError().json(ErrorResponse::new(
+                    "Failed to generate a unique license key.",
+                )),
+            )
+            .into());
+        };
+        if !minted.iter().any(|other| other.key == license.key) {
+            minted.push(license);
+        }
+    }
+    let licenses = minted
+        .iter()
+        .map(|license| license.key.clone())
+        .collect::<Vec<_>>();
+    for license in &minted {
+        store.insert(license.clone());
+    }
+    drop(store);
+
+    let order = Order {
+        provider: purchase.provider.to_string(),
+        order_id: purchase.order_id.to_string(),
+        sku: purchase.sku.to_string(),
+        licenses: licenses.clone(),
+        email: purchase.email.map(str::to_string),
+        payment_ref: purchase.payment_ref.map(str::to_string),
+        created_at: unix_now(),
+        refunded_at: None,
+    };
+    orders.insert(key, order.clone());
+    drop(orders);
+    state.schedule_save(DataFile::Licenses);
+    state.schedule_save(DataFile::Orders);
+    for license in &minted {
+        state.license_created(license, sku.days);
+    }
+    info!(
+        provider = %order.provider,
+        order_id = %order.order_id,
+        sku = %order.sku,
+        licenses = licenses.len(),
+        "Order fulfilled"
+    );
+    // Carries the keys, so a mailer subscribed to it can deliver them to the customer
+    webhooks::emit(
+        state,
+        WebhookEvent::OrderCompleted,
+        json!({
+            "order_id": order.order_id,
+            "provider": order.provider,
+            "sku": order.sku,
+            "email": order.email,
+            "licenses": licenses,
+            "product_ids": sku.product_ids,
+            "days": sku.days,
+        }),
+    );
+    Ok(licenses)
+}
+
+/// Bans the licenses of a refunded order. Refunding an order twice changes nothing.
+fn refund(state: &State, key: &str) -> Result<Order> {
+    let now = unix_now();
+    let details = BanDetails {
+        reason: Some("Order refunded".to_string()),
+        created_by: Some(REFUNDED_BY.to_string()),
+        ..BanDetails::permanent(now)
+    };
+    let order = {
+        let mut orders = state.orders.write().unwrap();
+        let Some(order) = orders.get_mut(key) else {
+            return Err(error::InternalError::from_response(
+                "Order not found.",
+                HttpResponse::NotFound().json(ErrorResponse::new("Order not found.")),
+            )
+            .into());
+        };
+        if order.refunded_at.is_some() {
+            return Ok(order.clone());
+        }
+        order.refunded_at = Some(now);
+        let mut licenses = state.licenses.write().unwrap();
+        for license in &order.licenses {
+            if let Some(license) = licenses.get_mut(license) {
+                license.ban = Some(details.clone());
+            }
+        }
+        order.clone()
+    };
+    state.schedule_save(DataFile::Orders);
+    state.schedule_save(DataFile::Licenses);
+    for license in &order.licenses {
+        webhooks::license_revoked(state, license, &details);
+    }
+    webhooks::emit(
+        state,
+        WebhookEvent::OrderRefunded,
+        json!({
+            "order_id": order.order_id,
+            "provider": order.provider,
+            "sku": order.sku,
+            "email": order.email,
+            "licenses": order.licenses,
+        }),
+    );
+    info!(
+        provider = %order.provider,
+        order_id = %order.order_id,
+        licenses = order.licenses.len(),
+        "Order refunded, licenses banned"
+    );
+    Ok(order)
+}
+
+/// What a Stripe event asks of us.
+#[derive(Debug, PartialEq)]
+enum StripeAction<'a> {
+    Purchase {
+        order_id: &'a str,
+        sku: Option<&'a str>,
+        email: Option<&'a str>,
+        payment_intent: Option<&'a str>,
+    },
+    Refund {
+        payment_intent: &'a str,
+    },
+    Ignore,
+}
+
+fn stripe_action(event: &Value) -> StripeAction<'_> {
+    let kind = event["type"].as_str().unwrap_or_default();
+    let object = &event["data"]["object"];
+
+    if STRIPE_PAID_EVENTS.contains(&kind) {
+        // Asynchronous payment methods complete the session before the money arrives
+        let paid = matches!(
+            object["payment_status"].as_str(),
+            Some("paid" | "no_payment_required")
+        );
+        return match object["id"].as_str().filter(|_| paid) {
+            Some(order_id) => StripeAction::Purchase {
+                order_id,
+                sku: object["metadata"]["sku"].as_str(),
+                email: object["customer_details"]["email"].as_str(),
+                payment_intent: object["payment_intent"].as_str(),
+            },
+            None => StripeAction::Ignore,
+        };
+    }
+
+    // Only full refunds revoke the licenses
+    if kind == "charge.refunded" && object["refunded"].as_bool() == Some(true) {
+        return match object["payment_intent"].as_str() {
+            Some(payment_intent) => StripeAction::Refund { payment_intent },
+            None => StripeAction::Ignore,
+        };
+    }
+
+    StripeAction::Ignore
+}
+
+#[post("/payments/stripe")]
+async fn stripe(
+    http: HttpRequest,
+    body: web::Bytes,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    let secret = state.config.payments.stripe_webhook_secret.as_deref();
+    verify(&http, &body, secret, "Stripe-Signature", &state)?;
+    let event: Value =
+        serde_json::from_slice(&body).map_err(|_| bad_request("Invalid event body."))?;
+
+    match stripe_action(&event) {
+        StripeAction::Purchase {
+            order_id,
+            sku,
+            email,
+            payment_intent,
+        } => {
+            let Some(sku) = sku else {
+                warn!(order_id = %order_id, "Stripe checkout without a sku in its metadata");
+                return Err(bad_request("Missing SKU."));
+            };
+            let licenses = fulfil(
+                &state,
+                Purchase {
+                    provider: "stripe",
+                    order_id,
+                    sku,
+                    email,
+                    payment_ref: payment_intent,
+                    quantity: 1,
+                },
+            )?;
+            Ok(HttpResponse::Ok().json(OrderResponse {
+                order_id: order_id.to_string(),
+                licenses,
+            }))
+        }
+        StripeAction::Refund { payment_intent } => {
+            let key = state
+                .orders
+                .read()
+                .unwrap()
+                .iter()
+                .find(|(_, order)| {
+                    order.provider == "stripe"
+                        && order.payment_ref.as_deref() == Some(payment_intent)
+                })
+                .map(|(key, _)| key.clone());
+            let Some(key) = key else {
+                warn!(payment_intent = %payment_intent, "Stripe refund for an unknown order");
+                return Ok(HttpResponse::Ok().json(ErrorResponse::new("Event ignored.")));
+            };
+            let order = refund(&state, &key)?;
+            Ok(HttpResponse::Ok().json(OrderResponse {
+                order_id: order.order_id,
+                licenses: order.licenses,
+            }))
+        }
+        StripeAction::Ignore => Ok(HttpResponse::Ok().json(ErrorResponse::new("Event ignored."))),
+    }
+}
+
+/// Purchases and refunds from stores without a dedicated integration, signed the way
+/// outgoing webhooks are.
+#[post("/payments/generic")]
+async fn generic(
+    http: HttpRequest,
+    body: web::Bytes,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    let secret = state.config.payments.generic_webhook_secret.as_deref();
+    verify(&http, &body, secret, "X-Autherium-Signature", &state)?;
+    let event: PaymentEvent =
+        serde_json::from_slice(&body).map_err(|_| bad_request("Invalid event body."))?;
+
+    match event {
+        PaymentEvent::Purchase {
+            order_id,
+            sku,
+            email,
+            quantity,
+        } => {
+            if quantity == 0 || quantity > MAX_QUANTITY {
+                return Err(bad_request("Invalid quantity."));
+            }
+            let licenses = fulfil(
+                &state,
+                Purchase {
+                    provider: "generic",
+                    order_id: &order_id,
+                    sku: &sku,
+                    email: email.as_deref(),
+                    payment_ref: None,
+                    quantity,
+                },
+            )?;
+            Ok(HttpResponse::Ok().json(OrderResponse { order_id, licenses }))
+        }
+        PaymentEvent::Refund { order_id } => {
+            let order = refund(&state, &order_key("generic", &order_id))?;
+            Ok(HttpResponse::Ok().json(OrderResponse {
+                order_id: order.order_id,
+                licenses: order.licenses,
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STRIPE_CHECKOUT_PAID: &str = r#"{
+        "id": "evt_1",
+        "type": "checkout.session.completed",
+        "data": {
+            "object": {
+                "id": "cs_test_123",
+                "payment_status": "paid",
+                "payment_intent": "pi_123",
+                "customer_details": { "email": "buyer@example.com" },
+                "metadata": { "sku": "pro-30" }
+            }
+        }
+    }"#;
+
+    const STRIPE_CHECKOUT_UNPAID: &str = r#"{
+        "id": "evt_2",
+        "type": "checkout.session.completed",
+        "data": {
+            "object": {
+                "id": "cs_test_456",
+                "payment_status": "unpaid",
+                "payment_intent": "pi_456",
+                "metadata": { "sku": "pro-30" }
+            }
+        }
+    }"#;
+
+    const STRIPE_CHECKOUT_NO_SKU: &str = r#"{
+        "id": "evt_3",
+        "type": "checkout.session.async_payment_succeeded",
+        "data": {
+            "object": {
+                "id": "cs_test_789",
+                "payment_status": "paid",
+                "metadata": {}
+            }
+        }
+    }"#;
+
+    const STRIPE_FULL_REFUND: &str = r#"{
+        "id": "evt_4",
+        "type": "charge.refunded",
+        "data": { "object": { "id": "ch_1", "refunded": true, "payment_intent": "pi_123" } }
+    }"#;
+
+    const STRIPE_PARTIAL_REFUND: &str = r#"{
+        "id": "evt_5",
+        "type": "charge.refunded",
+        "data": { "object": { "id": "ch_2", "refunded": false, "payment_intent": "pi_123" } }
+    }"#;
+
+    /// Signed with `whsec_fixture` at 1700000000, and with `whsec_old` for the second tag,
+    /// the way Stripe signs while a secret is being rolled.
+    const STRIPE_SIGNED_BODY: &str = r#"{"id":"evt_1","type":"checkout.session.completed","data":{"object":{"id":"cs_test_123","payment_status":"paid","metadata":{"sku":"pro-30"}}}}"#;
+    const STRIPE_SIGNATURE: &str = "t=1700000000,v1=c2a958b7df63fe642441c1435019194ca5ff567d43386d2abe4d79dc2a87ecf3,v1=72403f3720f0d9a6559dfde87539054f8396708bfb8b51732e4aec9a8a0155ca";
+
+    fn event(body: &str) -> Value {
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[test]
+    fn stripe_fixture_signature_verifies() {
+        let body = STRIPE_SIGNED_BODY.as_bytes();
+        let verify =
+            |secret, now| webhooks::verify_signature(secret, STRIPE_SIGNATURE, body, 300, now);
+        assert!(verify("whsec_fixture", 1_700_000_000));
+        assert!(verify("whsec_old", 1_700_000_120));
+        assert!(!verify("whsec_other", 1_700_000_000));
+        assert!(!verify("whsec_fixture", 1_700_000_301));
+        assert!(!webhooks::verify_signature(
+            "whsec_fixture",
+            STRIPE_SIGNATURE,
+            STRIPE_CHECKOUT_PAID.as_bytes(),
+            300,
+            1_700_000_000
+        ));
+        assert_eq!(
+            stripe_action(&event(STRIPE_SIGNED_BODY)),
+            StripeAction::Purchase {
+                order_id: "cs_test_123",
+                sku: Some("pro-30"),
+                email: None,
+                payment_intent: None,
+            }
+        );
+    }
+
+    #[test]
+    fn stripe_paid_checkout_is_a_purchase() {
+        assert_eq!(
+            stripe_action(&event(STRIPE_CHECKOUT_PAID)),
+            StripeAction::Purchase {
+                order_id: "cs_test_123",
+                sku: Some("pro-30"),
+                email: Some("buyer@example.com"),
+                payment_intent: Some("pi_123"),
+            }
+        );
+        assert_eq!(
+            stripe_action(&event(STRIPE_CHECKOUT_NO_SKU)),
+            StripeAction::Purchase {
+                order_id: "cs_test_789",
+                sku: None,
+                email: None,
+                payment_intent: None,
+            }
+        );
+    }
+
+    #[test]
+    fn stripe_unpaid_checkout_is_ignored() {
+        assert_eq!(
+            stripe_action(&event(STRIPE_CHECKOUT_UNPAID)),
+            StripeAction::Ignore
+        );
+    }
+
+    #[test]
+    fn stripe_only_full_refunds_revoke() {
+        assert_eq!(
+            stripe_action(&event(STRIPE_FULL_REFUND)),
+            StripeAction::Refund {
+                payment_intent: "pi_123"
+            }
+        );
+        assert_eq!(
+            stripe_action(&event(STRIPE_PARTIAL_REFUND)),
+            StripeAction::Ignore
+        );
+    }
+
+    #[test]
+    fn stripe_other_events_are_ignored() {
+        assert_eq!(
+            stripe_action(&event(
+                r#"{ "type": "customer.created", "data": { "object": { "id": "cus_1" } } }"#
+            )),
+            StripeAction::Ignore
+        );
+        assert_eq!(stripe_action(&event("{}")), StripeAction::Ignore);
+    }
+
+    #[test]
+    fn generic_purchase_parses() {
+        let event: PaymentEvent = serde_json::from_str(
+            r#"{ "type": "purchase", "order_id": "1001", "sku": "pro-30", "email": "buyer@example.com", "quantity": 3 }"#,
+        )
+        .unwrap();
+        let PaymentEvent::Purchase {
+            order_id,
+            sku,
+            email,
+            quantity,
+        } = event
+        else {
+            panic!("expected a purchase, got {:?}", event);
+        };
+        assert_eq!(order_id, "1001");
+        assert_eq!(sku, "pro-30");
+        assert_eq!(email.as_deref(), Some("buyer@example.com"));
+        assert_eq!(quantity, 3);
+
+        let event: PaymentEvent =
+            serde_json::from_str(r#"{ "type": "purchase", "order_id": "1002", "sku": "pro-30" }"#)
+                .unwrap();
+        assert!(matches!(
+            event,
+            PaymentEvent::Purchase {
+                email: None,
+                quantity: 1,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn generic_refund_parses() {
+        let event: PaymentEvent =
+            serde_json::from_str(r#"{ "type": "refund", "order_id": "1001" }"#).unwrap();
+        assert!(matches!(event, PaymentEvent::Refund { order_id } if order_id == "1001"));
+    }
+
+    #[test]
+    fn generic_rejects_malformed_events() {
+        for body in [
+            r#"{ "type": "chargeback", "order_id": "1001" }"#,
+            r#"{ "type": "purchase", "order_id": "1001" }"#,
+            r#"{ "type": "refund", "order_id": "1001", "amount": 5 }"#,
+            r#"{ "order_id": "1001", "sku": "pro-30" }"#,
+        ] {
+            assert!(
+                serde_json::from_str::<PaymentEvent>(body).is_err(),
+                "{}",
+                body
+            );
+        }
+    }
+}
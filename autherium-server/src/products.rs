@@ -0,0 +1,280 @@
+use actix_web::{HttpResponse, Responder, Result, error, post, web};
+use std::collections::HashMap;
+use tracing::{info, warn};
+
+use crate::{State, check_key, storage::DataFile, types::*};
+
+/// The message for clients of `product_id`, when it is disabled.
+pub fn disabled_message(state: &State, product_id: &str) -> Option<String> {
+    state
+        .disabled_products
+        .read()
+        .unwrap()
+        .get(product_id)
+        .map(|disabled| disabled.message.clone())
+}
+
+/// Whether the time of `license` stands still because one of its products is disabled
+/// with its time paused.
+pub fn is_paused(disabled_products: &HashMap<String, DisabledProduct>, license: &License) -> bool {
+    license.product_ids.iter().any(|product_id| {
+        disabled_products
+            .get(product_id)
+            .is_some_and(|disabled| disabled.pause_time)
+    })
+}
+
+/// Time to give back to `license` when `product_id`, paused since `since`, is enabled at
+/// `now`: the part of the downtime the license was running for. `None` when it wasn't
+/// running, or stayed usable through one of its products that was never disabled.
+fn pause_credit(
+    disabled_products: &HashMap<String, DisabledProduct>,
+    license: &License,
+    product_id: &str,
+    since: u64,
+    now: u64,
+) -> Option<u64> {
+    if !license.used
+        || !license.product_ids.iter().any(|id| id == product_id)
+        || license.expires_at() <= since
+    {
+        return None;
+    }
+    let usable_elsewhere = license
+        .product_ids
+        .iter()
+        .any(|id| id != product_id && !disabled_products.contains_key(id));
+    if usable_elsewhere {
+        return None;
+    }
+    Some(now.saturating_sub(since.max(license.start)))
+}
+
+/// Turns away clients of a product at once, both on `/auth` and on the endpoints that
+/// take a session, until it is enabled again.
+///
+/// Disabling a product that is already disabled replaces its message, but it stays
+/// disabled since the first time. Whether time is paused can't change until it is
+/// enabled, as that would credit or take back the downtime so far.
+#[post("/products/disable")]
+async fn disable_product(
+    req: web::Json<ProductDisableRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    check_key(&req.key, &state)?;
+
+    if req.product_id.is_empty() {
+        return Err(error::InternalError::from_response(
+            "Invalid product.",
+            HttpResponse::BadRequest().json(ErrorResponse::new("Invalid product.")),
+        )
+        .into());
+    }
+
+    let message = req
+        .message
+        .clone()
+        .filter(|message| !message.trim().is_empty())
+        .unwrap_or_else(|| state.config.maintenance.disabled_product_message.clone());
+    {
+        let mut disabled_products = state.disabled_products.write().unwrap();
+        let since = match disabled_products.get(&req.product_id) {
+            Some(disabled) if disabled.pause_time != req.pause_time => {
+                return Err(error::InternalError::from_response(
+                    "Product is already disabled with a different pause_time.",
+                    HttpResponse::Conflict().json(ErrorResponse::new(
+                        "Product is already disabled with a different pause_time.",
+                    )),
+                )
+                .into());
+            }
+            Some(disabled) => disabled.since,
+            None => unix_now(),
+        };
+        disabled_products.insert(
+            req.product_id.clone(),
+            DisabledProduct {
+                message,
+                since,
+                pause_time: req.pause_time,
+                created_by: req.created_by.clone(),
+            },
+        );
+    }
+    state.schedule_save(DataFile::DisabledProducts);
+    warn!(
+        product_id = %req.product_id,
+        pause_time = req.pause_time,
+        created_by = ?req.created_by,
+        "Product disabled"
+    );
+
+    Ok(HttpResponse::Ok().json(ErrorResponse::new("Product disabled successfully.")))
+}
+
+/// Lets clients of a product back in. When its time was paused, every license of the
+/// product that ran during the downtime gets back the part it ran for, unless it could
+/// still be used through another product.
+#[post("/products/enable")]
+async fn enable_product(
+    req: web::Json<ProductEnableRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    check_key(&req.key, &state)?;
+
+    let now = unix_now();
+    // Held until the licenses are extended, so the sweep can't archive them in between
+    let mut disabled_products = state.disabled_products.write().unwrap();
+    let Some(disabled) = disabled_products.remove(&req.product_id) else {
+        return Err(error::InternalError::from_response(
+            "Product is not disabled.",
+            HttpResponse::NotFound().json(ErrorResponse::new("Product is not disabled.")),
+        )
+        .into());
+    };
+
+    let mut paused_secs = 0;
+    let mut licenses_extended = 0;
+    if disabled.pause_time {
+        paused_secs = now.saturating_sub(disabled.since);
+        let mut licenses = state.licenses.write().unwrap();
+        let credits = licenses
+            .iter()
+            .filter_map(|license| {
+                pause_credit(
+                    &disabled_products,
+                    license,
+                    &req.product_id,
+                    disabled.since,
+                    now,
+                )
+                .map(|credit| (license.key.clone(), credit))
+            })
+            .collect::<Vec<_>>();
+        for (key, credit) in credits {
+            if let Some(license) = licenses.get_mut(&key) {
+                license.duration = license.duration.saturating_add(credit);
+                licenses_extended += 1;
+            }
+        }
+    }
+    drop(disabled_products);
+    state.schedule_save(DataFile::DisabledProducts);
+    if licenses_extended > 0 {
+        state.schedule_save(DataFile::Licenses);
+    }
+    info!(
+        product_id = %req.product_id,
+        paused_secs,
+        licenses_extended,
+        "Product enabled"
+    );
+
+    Ok(HttpResponse::Ok().json(ProductEnableResponse {
+        product_id: req.product_id.clone(),
+        paused_secs,
+        licenses_extended,
+    }))
+}
+
+#[post("/products/disabled")]
+async fn list_disabled_products(
+    req: web::Json<AdminRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    check_key(&req.key, &state)?;
+
+    let products = state
+        .disabled_products
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(product_id, disabled)| (product_id.clone(), disabled.clone()))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(DisabledProductsResponse { products }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SINCE: u64 = 1_700_000_000;
+    const NOW: u64 = SINCE + 3600;
+
+    fn license(product_ids: &[&str], start: u64) -> License {
+        let product_ids = product_ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>();
+        let mut license = License::new("ABCDEFGHIJKLMNOP".to_string(), &product_ids).set_days(30);
+        license.used = true;
+        license.start = start;
+        license
+    }
+
+    fn disabled(product_ids: &[&str]) -> HashMap<String, DisabledProduct> {
+        product_ids
+            .iter()
+            .map(|id| {
+                (
+                    id.to_string(),
+                    DisabledProduct {
+                        message: "Down for maintenance".to_string(),
+                        since: SINCE,
+                        pause_time: true,
+                        created_by: None,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn running_licenses_get_the_whole_downtime() {
+        let license = license(&["pro"], SINCE - 60);
+        assert_eq!(
+            pause_credit(&disabled(&[]), &license, "pro", SINCE, NOW),
+            Some(3600)
+        );
+    }
+
+    #[test]
+    fn licenses_activated_during_the_downtime_get_only_their_share() {
+        let license = license(&["pro"], SINCE + 600);
+        assert_eq!(
+            pause_credit(&disabled(&[]), &license, "pro", SINCE, NOW),
+            Some(3000)
+        );
+    }
+
+    #[test]
+    fn licenses_usable_through_another_product_get_nothing() {
+        let license = license(&["pro", "lite"], SINCE - 60);
+        assert_eq!(
+            pause_credit(&disabled(&[]), &license, "pro", SINCE, NOW),
+            None
+        );
+        // Unless that product is down as well
+        assert_eq!(
+            pause_credit(&disabled(&["lite"]), &license, "pro", SINCE, NOW),
+            Some(3600)
+        );
+    }
+
+    #[test]
+    fn licenses_not_running_during_the_downtime_get_nothing() {
+        let unused = License {
+            used: false,
+            ..license(&["pro"], 0)
+        };
+        let expired = license(&["pro"], SINCE - 31 * 24 * 60 * 60);
+        let other = license(&["lite"], SINCE - 60);
+        for license in [unused, expired, other] {
+            assert_eq!(
+                pause_credit(&disabled(&[]), &license, "pro", SINCE, NOW),
+                None
+            );
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use actix_web::{HttpResponse, Responder, Result, error, post, web};
+use rand::{Rng, distr::Alphanumeric};
+use ring::digest;
+use tracing::{info, warn};
+
+use crate::{State, logging::Redacted, not_found, storage::DataFile, types::*, webhooks};
+
+fn hash_key(key: &str) -> String {
+    digest::digest(&digest::SHA256, key.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn forbidden(message: &str) -> error::Error {
+    error::InternalError::from_response(
+        message.to_string(),
+        HttpResponse::Forbidden().json(ErrorResponse::new(message)),
+    )
+    .into()
+}
+
+/// The ID of the reseller whose key is `key`.
+pub fn find(state: &State, key: &str) -> Option<String> {
+    let hash = hash_key(key);
+    state
+        .resellers
+        .read()
+        .unwrap()
+        .values()
+        .find(|reseller| reseller.key_hash == hash)
+        .map(|reseller| reseller.id.clone())
+}
+
+/// Creates a license for reseller `id`, if it is within the reseller's allotment.
+///
+/// The quota is held for the whole mint, so concurrent requests can't overdraw it.
+pub fn mint(state: &State, id: &str, product_ids: &[String], days: u64) -> Result<Option<String>> {
+    let mut resellers = state.resellers.write().unwrap();
+    let reseller = resellers
+        .get_mut(id)
+        .ok_or_else(|| not_found("Reseller not found."))?;
+    if reseller.disabled {
+        warn!(reseller = %id, "Rejected license creation by a disabled reseller");
+        return Err(forbidden("Reseller account is disabled."));
+    }
+    if product_ids.is_empty()
+        || !product_ids
+            .iter()
+            .all(|product_id| reseller.product_ids.contains(product_id))
+    {
+        warn!(
+            reseller = %id,
+            product_ids = ?product_ids,
+            "Rejected license for products the reseller can't sell"
+        );
+        return Err(forbidden("Product not allowed for this reseller."));
+    }
+    if reseller
+        .max_keys
+        .is_some_and(|max_keys| reseller.keys_created >= max_keys)
+    {
+        warn!(reseller = %id, "Reseller license quota exhausted");
+        return Err(forbidden("License quota exhausted."));
+    }
+    if reseller
+        .max_days
+        .is_some_and(|max_days| reseller.days_created.saturating_add(days) > max_days)
+    {
+        warn!(reseller = %id, days, "Reseller day quota exhausted");
+        return Err(forbidden("Day quota exhausted."));
+    }
+
+    let Some(license) = state.mint_license(product_ids, days, Some(id)) else {
+        return Ok(None);
+    };
+    reseller.keys_created += 1;
+    reseller.days_created = reseller.days_created.saturating_add(days);
+    drop(resellers);
+    state.schedule_save(DataFile::Resellers);
+    Ok(Some(license))
+}
+
+#[post("/resellers/create")]
+async fn create_reseller(
+    req: web::Json<ResellerCreateRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    if req.key != state.config.security.api_key {
+        warn!(key = %Redacted(&req.key), "Rejected request with an invalid API key");
+        return Err(error::InternalError::from_response(
+            "Invalid API key.",
+            HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid API key.")),
+        )
+        .into());
+    }
+
+    let valid_id = !req.id.is_empty()
+        && req.id.len() <= 64
+        && req
+            .id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid_id || req.product_ids.is_empty() {
+        return Err(error::InternalError::from_response(
+            "Invalid reseller.",
+            HttpResponse::BadRequest().json(ErrorResponse::new("Invalid reseller.")),
+        )
+        .into());
+    }
+
+    let reseller_key = format!(
+        "rsl_{}",
+        rand::rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .map(char::from)
+            .collect::<String>()
+    );
+    {
+        let mut resellers = state.resellers.write().unwrap();
+        if resellers.contains_key(&req.id) {
+            return Err(error::InternalError::from_response(
+                "Reseller already exists.",
+                HttpResponse::Conflict().json(ErrorResponse::new("Reseller already exists.")),
+            )
+            .into());
+        }
+        resellers.insert(
+            req.id.clone(),
+            Reseller {
+                id: req.id.clone(),
+                key_hash: hash_key(&reseller_key),
+                product_ids: req.product_ids.clone(),
+                max_keys: req.max_keys,
+                max_days: req.max_days,
+                keys_created: 0,
+                days_created: 0,
+                created_at: unix_now(),
+                disabled: false,
+            },
+        );
+    }
+    state.schedule_save(DataFile::Resellers);
+    info!(
+        reseller = %req.id,
+        product_ids = ?req.product_ids,
+        max_keys = ?req.max_keys,
+        max_days = ?req.max_days,
+        "Reseller created"
+    );
+
+    Ok(HttpResponse::Created().json(ResellerCreateResponse {
+        id: req.id.clone(),
+        reseller_key,
+    }))
+}
+
+#[post("/resellers")]
+async fn list_resellers(
+    req: web::Json<AdminRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    if req.key != state.config.security.api_key {
+        warn!(key = %Redacted(&req.key), "Rejected request with an invalid API key");
+        return Err(error::InternalError::from_response(
+            "Invalid API key.",
+            HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid API key.")),
+        )
+        .into());
+    }
+
+    let mut resellers = state
+        .resellers
+        .read()
+        .unwrap()
+        .values()
+        .map(|reseller| ResellerUsage {
+            id: reseller.id.clone(),
+            product_ids: reseller.product_ids.clone(),
+            max_keys: reseller.max_keys,
+            max_days: reseller.max_days,
+            keys_created: reseller.keys_created,
+            days_created: reseller.days_created,
+            created_at: reseller.created_at,
+            disabled: reseller.disabled,
+            live_licenses: 0,
+            unused_licenses: 0,
+            banned_licenses: 0,
+        })
+        .collect::<Vec<_>>();
+    resellers.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let now = unix_now();
+    for license in state.licenses.read().unwrap().iter() {
+        let Some(id) = &license.reseller else {
+            continue;
+        };
+        let Ok(index) = resellers.binary_search_by(|usage| usage.id.as_str().cmp(id)) else {
+            continue;
+        };
+        let usage = &mut resellers[index];
+        usage.live_licenses += 1;
+        if !license.used {
+            usage.unused_licenses += 1;
+        }
+        if license.ban.as_ref().is_some_and(|ban| ban.is_active(now)) {
+            usage.banned_licenses += 1;
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(ResellersResponse { resellers }))
+}
+
+#[post("/resellers/update")]
+async fn update_reseller(
+    req: web::Json<ResellerUpdateRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    if req.key != state.config.security.api_key {
+        warn!(key = %Redacted(&req.key), "Rejected request with an invalid API key");
+        return Err(error::InternalError::from_response(
+            "Invalid API key.",
+            HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid API key.")),
+        )
+        .into());
+    }
+
+    if req.product_ids.as_ref().is_some_and(Vec::is_empty) {
+        return Err(error::InternalError::from_response(
+            "Invalid reseller.",
+            HttpResponse::BadRequest().json(ErrorResponse::new("Invalid reseller.")),
+        )
+        .into());
+    }
+
+    {
+        let mut resellers = state.resellers.write().unwrap();
+        let reseller = resellers
+            .get_mut(&req.id)
+            .ok_or_else(|| not_found("Reseller not found."))?;
+        if let Some(product_ids) = &req.product_ids {
+            reseller.product_ids = product_ids.clone();
+        }
+        if let Some(max_keys) = req.max_keys {
+            reseller.max_keys = Some(max_keys);
+        }
+        if let Some(max_days) = req.max_days {
+            reseller.max_days = Some(max_days);
+        }
+        if let Some(disabled) = req.disabled {
+            reseller.disabled = disabled;
+        }
+    }
+    state.schedule_save(DataFile::Resellers);
+    info!(
+        reseller = %req.id,
+        product_ids = ?req.product_ids,
+        max_keys = ?req.max_keys,
+        max_days = ?req.max_days,
+        disabled = ?req.disabled,
+        "Reseller updated"
+    );
+
+    Ok(HttpResponse::Ok().json(ErrorResponse::new("Reseller updated successfully.")))
+}
+
+#[post("/resellers/revoke")]
+async fn revoke_reseller(
+    req: web::Json<ResellerRevokeRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    if req.key != state.config.security.api_key {
+        warn!(key = %Redacted(&req.key), "Rejected request with an invalid API key");
+        return Err(error::InternalError::from_response(
+            "Invalid API key.",
+            HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid API key.")),
+        )
+        .into());
+    }
+
+    if req.options.duration_secs == Some(0) {
+        return Err(error::InternalError::from_response(
+            "Invalid ban duration.",
+            HttpResponse::BadRequest().json(ErrorResponse::new("Invalid ban duration.")),
+        )
+        .into());
+    }
+
+    {
+        let mut resellers = state.resellers.write().unwrap();
+        let reseller = resellers
+            .get_mut(&req.id)
+            .ok_or_else(|| not_found("Reseller not found."))?;
+        if req.disable {
+            reseller.disabled = true;
+        }
+    }
+    if req.disable {
+        state.schedule_save(DataFile::Resellers);
+    }
+
+    let details = req.options.details(unix_now());
+    let mut banned = Vec::new();
+    {
+        let mut licenses = state.licenses.write().unwrap();
+        let keys = licenses
+            .iter()
+            .filter(|license| license.reseller.as_deref() == Some(req.id.as_str()))
+            .map(|license| license.key.clone())
+            .collect::<Vec<_>>();
+        for key in keys {
+            if let Some(license) = licenses.get_mut(&key) {
+                license.ban = Some(details.clone());
+                banned.push(key);
+            }
+        }
+    }
+    if !banned.is_empty() {
+        state.schedule_save(DataFile::Licenses);
+    }
+    for license in &banned {
+        webhooks::license_revoked(&state, license, &details);
+    }
+    info!(
+        reseller = %req.id,
+        banned = banned.len(),
+        disabled = req.disable,
+        reason = ?req.options.reason,
+        "Reseller licenses revoked"
+    );
+
+    Ok(HttpResponse::Ok().json(ErrorResponse::new(&format!(
+        "Banned {} license(s) of reseller {}.",
+        banned.len(),
+        req.id
+    ))))
+}
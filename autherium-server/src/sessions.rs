@@ -0,0 +1,223 @@
+use actix_web::{HttpRequest, HttpResponse, Result, error, http::header};
+use rand::{Rng, distr::Alphanumeric};
+use ring::{agreement, hkdf, rand::SystemRandom};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+use crate::{State, products, types::*, webhooks::decode_hex};
+
+/// HKDF info of the key agreed on with a client for encrypting its payloads.
+const SESSION_KEY_INFO: &[u8] = b"autherium session key v1";
+
+/// What a session was issued for by `/auth`.
+#[derive(Clone)]
+pub struct Session {
+    pub license: String,
+    pub product_id: String,
+    pub hwid: String,
+    pub expires_at: u64,
+    /// Payload downloads started with this session.
+    pub downloads: u32,
+    /// Payloads whose download was counted, as `<version>:<sha256>`. Only these can be
+    /// resumed for free.
+    pub started: HashSet<String>,
+    /// Key agreed on with the client, when it sent a public key to `/auth`.
+    pub key: Option<[u8; 32]>,
+}
+
+/// Agrees on a session key with a client from the hex X25519 public key it sent, and
+/// returns the hex public key of the server for the client to do the same.
+///
+/// Both sides take HKDF-SHA256 of the shared secret, salted with the client's public
+/// key followed by the server's.
+pub fn agree_key(client_public_key: &str) -> Option<(String, [u8; 32])> {
+    let client_public_key = decode_hex(client_public_key).filter(|key| key.len() == 32)?;
+    let private_key =
+        agreement::EphemeralPrivateKey::generate(&agreement::X25519, &SystemRandom::new()).ok()?;
+    let public_key = private_key.compute_public_key().ok()?;
+    let mut salt = client_public_key.clone();
+    salt.extend_from_slice(public_key.as_ref());
+
+    let peer = agreement::UnparsedPublicKey::new(&agreement::X25519, client_public_key);
+    let key = agreement::agree_ephemeral(private_key, &peer, |secret| {
+        let mut key = [0; 32];
+        hkdf::Salt::new(hkdf::HKDF_SHA256, &salt)
+            .extract(secret)
+            .expand(&[SESSION_KEY_INFO], hkdf::HKDF_SHA256)?
+            .fill(&mut key)?;
+        Ok::<_, ring::error::Unspecified>(key)
+    })
+    .ok()?
+    .ok()?;
+    let public_key = public_key
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    Some((public_key, key))
+}
+
+/// What [`Sessions::start_download`] made of a download.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Download {
+    Started,
+    Resumed,
+    LimitReached,
+}
+
+#[derive(Default)]
+struct SessionTable {
+    by_token: HashMap<String, Session>,
+    /// Token of the live session of each license, product and HWID.
+    by_client: HashMap<(String, String, String), String>,
+}
+
+/// Sessions handed out by successful auths, kept in memory only.
+#[derive(Default)]
+pub struct Sessions {
+    table: Mutex<SessionTable>,
+}
+
+impl Sessions {
+    /// Returns the token of the live session of this client, or starts a new one
+    /// lasting `ttl_secs`.
+    ///
+    /// Authenticating again doesn't start over, so it can't reset the download limit,
+    /// but the session takes the new `key` since the client only knows the latest one.
+    pub fn issue(
+        &self,
+        license: &str,
+        product_id: &str,
+        hwid: &str,
+        key: Option<[u8; 32]>,
+        ttl_secs: u64,
+        now: u64,
+    ) -> String {
+        let mut table = self.table.lock().unwrap();
+        let client = (
+            license.to_string(),
+            product_id.to_string(),
+            hwid.to_string(),
+        );
+        if let Some(token) = table.by_client.get(&client).cloned()
+            && let Some(session) = table
+                .by_token
+                .get_mut(&token)
+                .filter(|session| session.expires_at > now)
+        {
+            session.key = key;
+            return token;
+        }
+
+        let token = rand::rng()
+            .sample_iter(&Alphanumeric)
+            .take(40)
+            .map(char::from)
+            .collect::<String>();
+        table.by_token.insert(
+            token.clone(),
+            Session {
+                license: client.0.clone(),
+                product_id: client.1.clone(),
+                hwid: client.2.clone(),
+                expires_at: now.saturating_add(ttl_secs),
+                downloads: 0,
+                started: HashSet::new(),
+                key,
+            },
+        );
+        if let Some(old) = table.by_client.insert(client, token.clone()) {
+            table.by_token.remove(&old);
+        }
+        token
+    }
+
+    pub fn get(&self, token: &str, now: u64) -> Option<Session> {
+        self.table
+            .lock()
+            .unwrap()
+            .by_token
+            .get(token)
+            .filter(|session| session.expires_at > now)
+            .cloned()
+    }
+
+    /// Counts a download of `payload` against the session, unless it already made `max`
+    /// of them. A `resume` is free, but only of a payload the session started before.
+    pub fn start_download(&self, token: &str, payload: &str, resume: bool, max: u32) -> Download {
+        let mut table = self.table.lock().unwrap();
+        let Some(session) = table.by_token.get_mut(token) else {
+            return Download::LimitReached;
+        };
+        if resume && session.started.contains(payload) {
+            return Download::Resumed;
+        }
+        if session.downloads >= max {
+            return Download::LimitReached;
+        }
+        session.downloads += 1;
+        session.started.insert(payload.to_string());
+        Download::Started
+    }
+
+    /// Forgets expired sessions.
+    pub fn prune(&self, now: u64) {
+        let mut table = self.table.lock().unwrap();
+        table.by_token.retain(|_, session| session.expires_at > now);
+        let SessionTable {
+            by_token,
+            by_client,
+        } = &mut *table;
+        by_client.retain(|_, token| by_token.contains_key(token));
+    }
+}
+
+/// The session of the `Authorization: Bearer` token of a request, with the token.
+///
+/// The license or HWID may have been banned, or the license run out, since the session
+/// started, so they are checked again.
+pub fn authorize(http: &HttpRequest, state: &State) -> Result<(String, Session)> {
+    let now = unix_now();
+    let token = http
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    let session = state.sessions.get(token, now).filter(|session| {
+        let hwid_banned = state
+            .banned_hwids
+            .read()
+            .unwrap()
+            .get(&session.hwid)
+            .is_some_and(|ban| ban.details.is_active(now));
+        !hwid_banned
+            && state
+                .licenses
+                .read()
+                .unwrap()
+                .get(&session.license)
+                .is_some_and(|license| {
+                    license.product_ids.contains(&session.product_id)
+                        && !license.is_expired(now)
+                        && license.active_ban(now).is_none()
+                })
+    });
+    let Some(session) = session else {
+        return Err(error::InternalError::from_response(
+            "Invalid session.",
+            HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid session.")),
+        )
+        .into());
+    };
+    if let Some(message) = products::disabled_message(state, &session.product_id) {
+        return Err(error::InternalError::from_response(
+            message.clone(),
+            HttpResponse::ServiceUnavailable().json(ErrorResponse::new(&message)),
+        )
+        .into());
+    }
+    Ok((token.to_string(), session))
+}
@@ -0,0 +1,234 @@
+use serde::{Serialize, de::DeserializeOwned};
+use std::{
+    collections::HashSet,
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Condvar, Mutex},
+    time::Duration,
+};
+
+/// Name of the lock file held in every data directory while the server runs.
+const LOCK_FILE: &str = ".autherium.lock";
+
+/// Replaces `path` with `data` without ever leaving a truncated file behind.
+///
+/// The data goes to a temporary file next to `path`, is fsynced, then renamed over the
+/// original, so a crash or full disk leaves either the old or the new contents.
+pub fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let mut tmp_name = file_name.to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+
+    let result = (|| {
+        let mut file = File::create(&tmp)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result?;
+    sync_dir(&parent_dir(path))
+}
+
+/// Serializes `value` as pretty JSON and writes it with [`write_atomic`].
+pub fn write_json<T: Serialize + ?Sized>(
+    path: &str,
+    value: &T,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let data = serde_json::to_vec_pretty(value)?;
+    write_atomic(Path::new(path), &data)?;
+    Ok(())
+}
+
+/// Reads a JSON data file, returning `None` if it does not exist yet.
+///
+/// A file that exists but can't be parsed is an error rather than an empty list, so a
+/// damaged file is never silently replaced on the next save.
+pub fn read_json<T: DeserializeOwned>(path: &str) -> Result<Option<T>, Box<dyn std::error::Error>> {
+    let data = match fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("failed to read {}: {}", path, e).into()),
+    };
+    serde_json::from_str(&data).map(Some).map_err(|e| {
+        format!(
+            "{} is not valid JSON ({}), fix it or restore it from a backup before starting",
+            path, e
+        )
+        .into()
+    })
+}
+
+/// The distinct directories holding `files`.
+pub fn data_dirs<'a>(files: impl IntoIterator<Item = &'a str>) -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+    files
+        .into_iter()
+        .map(|file| parent_dir(Path::new(file)))
+        .filter(|dir| seen.insert(dir.clone()))
+        .collect()
+}
+
+fn parent_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    // Makes the rename itself durable
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Exclusive locks on the data directories, released when dropped.
+///
+/// Stops a second server instance from using the same data and overwriting the first
+/// one's saves.
+pub struct DataDirLock {
+    _files: Vec<File>,
+}
+
+impl DataDirLock {
+    pub fn acquire(dirs: &[PathBuf]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut files = Vec::new();
+        for dir in dirs {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+            let path = dir.join(LOCK_FILE);
+            let mut file = OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&path)
+                .map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+            match file.try_lock() {
+                Ok(()) => {}
+                Err(TryLockError::WouldBlock) => {
+                    return Err(format!(
+                        "{} is in use by another autherium-server instance (locked {})",
+                        dir.display(),
+                        path.display()
+                    )
+                    .into());
+                }
+                Err(TryLockError::Error(e)) => {
+                    return Err(format!("failed to lock {}: {}", path.display(), e).into());
+                }
+            }
+            // Leave the holder's pid behind to make a stuck lock easier to track down
+            file.set_len(0)?;
+            writeln!(file, "{}", std::process::id())?;
+            files.push(file);
+        }
+        Ok(Self { _files: files })
+    }
+}
+
+/// Data files written by the background writer.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum DataFile {
+    Licenses,
+    BannedHwids,
+    BannedIps,
+    CanaryHits,
+    AnomalyFlags,
+    WebhookOutbox,
+    Orders,
+    Resellers,
+    Variables,
+    Announcements,
+    DisabledProducts,
+}
+
+impl DataFile {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DataFile::Licenses => "licenses",
+            DataFile::BannedHwids => "banned_hwids",
+            DataFile::BannedIps => "banned_ips",
+            DataFile::CanaryHits => "canary_hits",
+            DataFile::AnomalyFlags => "anomaly_flags",
+            DataFile::WebhookOutbox => "webhook_outbox",
+            DataFile::Orders => "orders",
+            DataFile::Resellers => "resellers",
+            DataFile::Variables => "variables",
+            DataFile::Announcements => "announcements",
+            DataFile::DisabledProducts => "disabled_products",
+        }
+    }
+}
+
+/// Coalesces save requests so request handlers never wait on the disk.
+pub struct SaveQueue {
+    pending: Mutex<Pending>,
+    wake: Condvar,
+    /// Signalled when the writer is done with a batch.
+    written: Condvar,
+    debounce: Duration,
+}
+
+#[derive(Default)]
+struct Pending {
+    files: HashSet<DataFile>,
+    /// Whether the writer is saving a batch it took.
+    writing: bool,
+}
+
+impl SaveQueue {
+    pub fn new(debounce: Duration) -> Self {
+        Self {
+            pending: Mutex::new(Pending::default()),
+            wake: Condvar::new(),
+            written: Condvar::new(),
+            debounce,
+        }
+    }
+
+    pub fn request(&self, file: DataFile) {
+        self.pending.lock().unwrap().files.insert(file);
+        self.wake.notify_one();
+    }
+
+    /// Blocks until a save is requested, then waits out the debounce so a burst of
+    /// changes is written once. [`SaveQueue::batch_written`] must follow once it is saved.
+    pub fn next_batch(&self) -> Vec<DataFile> {
+        let mut pending = self.pending.lock().unwrap();
+        while pending.files.is_empty() {
+            pending = self.wake.wait(pending).unwrap();
+        }
+        drop(pending);
+        std::thread::sleep(self.debounce);
+        let mut pending = self.pending.lock().unwrap();
+        let batch = pending.files.drain().collect::<Vec<_>>();
+        pending.writing = !batch.is_empty();
+        batch
+    }
+
+    pub fn batch_written(&self) {
+        self.pending.lock().unwrap().writing = false;
+        self.written.notify_all();
+    }
+
+    /// Takes every pending save, first waiting for the writer to finish the batch it
+    /// is on so nothing is still being written once these are saved.
+    pub fn take(&self) -> Vec<DataFile> {
+        let mut pending = self.pending.lock().unwrap();
+        while pending.writing {
+            pending = self.written.wait(pending).unwrap();
+        }
+        pending.files.drain().collect()
+    }
+}
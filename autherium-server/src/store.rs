@@ -0,0 +1,121 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::types::License;
+
+/// Active licenses indexed by key and by the HWIDs that have used them.
+#[derive(Default)]
+pub struct LicenseStore {
+    by_key: HashMap<String, License>,
+    by_hwid: HashMap<String, HashSet<String>>,
+}
+
+impl LicenseStore {
+    pub fn new(licenses: Vec<License>) -> Self {
+        let mut store = Self::default();
+        for license in licenses {
+            store.insert(license);
+        }
+        store
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_key.len()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&License> {
+        self.by_key.get(key)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut License> {
+        self.by_key.get_mut(key)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.by_key.contains_key(key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &License> {
+        self.by_key.values()
+    }
+
+    /// Adds `license`, replacing any license with the same key.
+    pub fn insert(&mut self, license: License) {
+        self.remove(&license.key);
+        for hwid in &license.hwids {
+            self.by_hwid
+                .entry(hwid.clone())
+                .or_default()
+                .insert(license.key.clone());
+        }
+        self.by_key.insert(license.key.clone(), license);
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<License> {
+        let license = self.by_key.remove(key)?;
+        for hwid in &license.hwids {
+            if let Some(keys) = self.by_hwid.get_mut(hwid) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    self.by_hwid.remove(hwid);
+                }
+            }
+        }
+        Some(license)
+    }
+
+    /// Records that `hwid` used the license `key`. Returns whether it is a new HWID for it.
+    pub fn record_hwid(&mut self, key: &str, hwid: &str) -> bool {
+        let Some(license) = self.by_key.get_mut(key) else {
+            return false;
+        };
+        if license.hwids.iter().any(|known| known == hwid) {
+            return false;
+        }
+        license.hwids.push(hwid.to_string());
+        self.by_hwid
+            .entry(hwid.to_string())
+            .or_default()
+            .insert(key.to_string());
+        true
+    }
+
+    /// Licenses that `hwid` has been used with.
+    pub fn for_hwid(&self, hwid: &str) -> impl Iterator<Item = &License> {
+        self.by_hwid
+            .get(hwid)
+            .into_iter()
+            .flatten()
+            .filter_map(|key| self.by_key.get(key))
+    }
+
+    /// Clears license bans that ran out by `now`, returning the affected keys.
+    pub fn clear_expired_bans(&mut self, now: u64) -> Vec<String> {
+        let mut cleared = Vec::new();
+        for license in self.by_key.values_mut() {
+            if license.ban.as_ref().is_some_and(|ban| !ban.is_active(now)) {
+                license.ban = None;
+                cleared.push(license.key.clone());
+            }
+        }
+        cleared
+    }
+
+    /// Removes and returns every license that expired by `now`, except those whose time
+    /// is `paused`.
+    pub fn take_expired(&mut self, now: u64, paused: impl Fn(&License) -> bool) -> Vec<License> {
+        let expired = self
+            .by_key
+            .values()
+            .filter(|license| license.is_expired(now) && !paused(license))
+            .map(|license| license.key.clone())
+            .collect::<Vec<_>>();
+        expired.iter().filter_map(|key| self.remove(key)).collect()
+    }
+
+    /// All licenses ordered by key, so saves produce a stable file.
+    pub fn to_vec(&self) -> Vec<License> {
+        let mut licenses = self.by_key.values().cloned().collect::<Vec<_>>();
+        licenses.sort_by(|a, b| a.key.cmp(&b.key));
+        licenses
+    }
+}
@@ -0,0 +1,253 @@
+use actix_web::{
+    Error, HttpResponse,
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header,
+    middleware::Next,
+};
+use rustls::{
+    ServerConfig,
+    crypto::ring,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+};
+use std::{
+    fs,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use tracing::{error, info};
+
+use crate::{
+    config::{PlainHttp, TlsConfig},
+    types::ErrorResponse,
+};
+
+/// Paths that stay reachable over plain HTTP so container health checks keep working,
+/// also under `/apps/<id>` for hosted applications.
+const PLAIN_HTTP_EXEMPT: [&str; 2] = ["/healthz", "/readyz"];
+
+fn plain_http_exempt(path: &str) -> bool {
+    let path = match path.strip_prefix("/apps/") {
+        Some(rest) => match rest.find('/') {
+            Some(end) if end > 0 => &rest[end..],
+            _ => return false,
+        },
+        None => path,
+    };
+    PLAIN_HTTP_EXEMPT.contains(&path)
+}
+
+/// Serves the current certificate and swaps it out when the files on disk change.
+#[derive(Debug)]
+pub struct CertResolver {
+    cert_file: String,
+    key_file: String,
+    current: RwLock<Arc<CertifiedKey>>,
+    modified: Mutex<(Option<SystemTime>, Option<SystemTime>)>,
+}
+
+impl CertResolver {
+    pub fn new(cert_file: &str, key_file: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let certified_key = load_certified_key(cert_file, key_file)?;
+        Ok(Self {
+            cert_file: cert_file.to_string(),
+            key_file: key_file.to_string(),
+            current: RwLock::new(Arc::new(certified_key)),
+            modified: Mutex::new((modified(cert_file), modified(key_file))),
+        })
+    }
+
+    /// Reloads the certificate if either file changed since the last check.
+    ///
+    /// Returns `Ok(true)` when a new certificate was installed. A failed reload keeps
+    /// serving the previous certificate.
+    pub fn reload_if_changed(&self) -> Result<bool, Box<dyn std::error::Error>> {
+        let now = (modified(&self.cert_file), modified(&self.key_file));
+        let mut last = self.modified.lock().unwrap();
+        if *last == now {
+            return Ok(false);
+        }
+        let certified_key = load_certified_key(&self.cert_file, &self.key_file)?;
+        *self.current.write().unwrap() = Arc::new(certified_key);
+        *last = now;
+        Ok(true)
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn load_certified_key(
+    cert_file: &str,
+    key_file: &str,
+) -> Result<CertifiedKey, Box<dyn std::error::Error>> {
+    let certs = CertificateDer::pem_file_iter(cert_file)
+        .map_err(|e| format!("failed to read certificate {}: {}", cert_file, e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("failed to parse certificate {}: {}", cert_file, e))?;
+    if certs.is_empty() {
+        return Err(format!("no certificates found in {}", cert_file).into());
+    }
+    let key = PrivateKeyDer::from_pem_file(key_file)
+        .map_err(|e| format!("failed to read private key {}: {}", key_file, e))?;
+    let signing_key = ring::sign::any_supported_type(&key)
+        .map_err(|e| format!("unsupported private key {}: {}", key_file, e))?;
+    Ok(CertifiedKey::new(certs, signing_key))
+}
+
+/// Builds the rustls config for the HTTPS listeners and starts watching the certificate files.
+pub fn server_config(config: &TlsConfig) -> Result<ServerConfig, Box<dyn std::error::Error>> {
+    let resolver = Arc::new(CertResolver::new(&config.cert_file, &config.key_file)?);
+
+    let watched = resolver.clone();
+    let interval = Duration::from_secs(config.reload_interval_secs);
+    std::thread::spawn(move || {
+        loop {
+            std::thread::sleep(interval);
+            match watched.reload_if_changed() {
+                Ok(true) => info!(cert_file = %watched.cert_file, "Reloaded TLS certificate"),
+                Ok(false) => {}
+                Err(e) => error!(error = %e, "Failed to reload TLS certificate"),
+            }
+        }
+    });
+
+    let server_config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_cert_resolver(resolver);
+    Ok(server_config)
+}
+
+/// Redirects or refuses requests that arrived on a plain HTTP listener, per `tls.plain_http`.
+pub async fn enforce_https<B: MessageBody + 'static>(
+    mode: PlainHttp,
+    https_port: u16,
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    if req.app_config().secure() || mode == PlainHttp::Serve || plain_http_exempt(req.path()) {
+        return next.call(req).await.map(|res| res.map_into_boxed_body());
+    }
+
+    let response = match mode {
+        PlainHttp::Redirect => {
+            let host = req.connection_info().host().to_string();
+            let host = match host.rsplit_once(':') {
+                Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name.to_string(),
+                _ => host,
+            };
+            let authority = if https_port == 443 {
+                host
+            } else {
+                format!("{}:{}", host, https_port)
+            };
+            let path = req
+                .uri()
+                .path_and_query()
+                .map(|p| p.as_str())
+                .unwrap_or("/");
+            HttpResponse::PermanentRedirect()
+                .insert_header((header::LOCATION, format!("https://{}{}", authority, path)))
+                .finish()
+        }
+        _ => HttpResponse::Forbidden().json(ErrorResponse::new("HTTPS is required.")),
+    };
+    Ok(req.into_response(response.map_into_boxed_body()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        App,
+        http::StatusCode,
+        middleware::from_fn,
+        test::{TestRequest, call_service, init_service},
+        web,
+    };
+
+    #[test]
+    fn health_checks_are_exempt_for_every_app() {
+        for path in [
+            "/healthz",
+            "/readyz",
+            "/apps/tool2/readyz",
+            "/apps/tool2/healthz",
+        ] {
+            assert!(plain_http_exempt(path), "{}", path);
+        }
+        for path in [
+            "/",
+            "/api/v1/auth",
+            "/readyz/x",
+            "/apps/tool2",
+            "/apps//readyz",
+            "/apps/tool2/api/v1/readyz",
+            "/apps/tool2/readyz/x",
+        ] {
+            assert!(!plain_http_exempt(path), "{}", path);
+        }
+    }
+
+    #[actix_web::test]
+    async fn refuse_mode_still_serves_app_health_checks() {
+        let app = init_service(
+            App::new()
+                .wrap(from_fn(|req, next| {
+                    enforce_https(PlainHttp::Refuse, 443, req, next)
+                }))
+                .route("/readyz", web::get().to(HttpResponse::Ok))
+                .route("/apps/{id}/readyz", web::get().to(HttpResponse::Ok))
+                .route("/apps/{id}/api/v1/auth", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        for (path, status) in [
+            ("/readyz", StatusCode::OK),
+            ("/apps/tool2/readyz", StatusCode::OK),
+            ("/apps/tool2/api/v1/auth", StatusCode::FORBIDDEN),
+        ] {
+            let request = TestRequest::get().uri(path).to_request();
+            let response = call_service(&app, request).await;
+            assert_eq!(response.status(), status, "{}", path);
+        }
+    }
+
+    #[actix_web::test]
+    async fn redirect_mode_still_serves_app_health_checks() {
+        let app = init_service(
+            App::new()
+                .wrap(from_fn(|req, next| {
+                    enforce_https(PlainHttp::Redirect, 8443, req, next)
+                }))
+                .route("/apps/{id}/readyz", web::get().to(HttpResponse::Ok))
+                .route("/apps/{id}/api/v1/auth", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let request = TestRequest::get().uri("/apps/tool2/readyz").to_request();
+        assert_eq!(call_service(&app, request).await.status(), StatusCode::OK);
+
+        let request = TestRequest::get()
+            .uri("/apps/tool2/api/v1/auth")
+            .insert_header((header::HOST, "example.com:8080"))
+            .to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            response.headers().get(header::LOCATION).unwrap(),
+            "https://example.com:8443/apps/tool2/api/v1/auth"
+        );
+    }
+}
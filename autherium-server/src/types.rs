@@ -1,10 +1,33 @@
+use ipnet::IpNet;
 use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt, net::IpAddr};
 
-#[derive(Deserialize, Debug)]
+use crate::{archive::ArchivedLicense, logging::Redacted};
+
+#[derive(Deserialize)]
 pub struct AuthRequest {
     pub license: String,
     pub product_id: String,
     pub hwid: String,
+    /// Version of the client software, checked against `versions` in the config.
+    #[serde(default)]
+    pub client_version: Option<String>,
+    /// Hex X25519 public key, for agreeing on a key that encrypts payload downloads.
+    #[serde(default)]
+    pub client_public_key: Option<String>,
+}
+
+impl fmt::Debug for AuthRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthRequest")
+            .field("license", &Redacted(&self.license))
+            .field("product_id", &self.product_id)
+            .field("hwid", &self.hwid)
+            .field("client_version", &self.client_version)
+            .field("client_public_key", &self.client_public_key)
+            .finish()
+    }
 }
 
 #[derive(Serialize, Default)]
@@ -12,24 +35,252 @@ pub struct AuthResponse {
     pub license_start: u64,
     pub license_duration: u64,
     pub time_remaining: i64,
+    /// Bearer token for the endpoints that need an authenticated client, like
+    /// `/payload`.
+    pub session: String,
+    /// Hex X25519 public key of the server, sent when the client sent its own.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_public_key: Option<String>,
+    /// Set when a newer client version is available.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub update: Option<UpdateInfo>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+/// Where a client can get a newer version of itself.
+#[derive(Serialize, Clone, Debug)]
+pub struct UpdateInfo {
+    pub min_version: Option<String>,
+    pub latest_version: Option<String>,
+    pub download_url: Option<String>,
+    pub changelog: Option<String>,
+}
+
+/// Answer to an auth from a client older than the minimum supported version.
+#[derive(Serialize, Debug)]
+pub struct UpdateRequiredResponse {
+    pub error: String,
+    pub update: UpdateInfo,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct CreateRequest {
     pub days: u64,
     pub key: String,
     pub product_ids: Vec<String>,
 }
 
+impl fmt::Debug for CreateRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CreateRequest")
+            .field("days", &self.days)
+            .field("key", &Redacted(&self.key))
+            .field("product_ids", &self.product_ids)
+            .finish()
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CreateResponse {
     pub license: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize)]
 pub struct HwidRequest {
     pub hwid: String,
     pub key: String,
+    #[serde(default)]
+    pub unbanned_by: Option<String>,
+}
+
+impl fmt::Debug for HwidRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HwidRequest")
+            .field("hwid", &self.hwid)
+            .field("key", &Redacted(&self.key))
+            .field("unbanned_by", &self.unbanned_by)
+            .finish()
+    }
+}
+
+/// Details an admin can attach to any kind of ban.
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct BanOptions {
+    pub reason: Option<String>,
+    pub created_by: Option<String>,
+    /// Seconds until the ban lifts. Permanent when unset.
+    pub duration_secs: Option<u64>,
+    /// Show `reason` to the user in the `/auth` error.
+    pub show_reason: bool,
+}
+
+impl BanOptions {
+    pub fn details(&self, now: u64) -> BanDetails {
+        BanDetails {
+            reason: self.reason.clone(),
+            created_at: now,
+            created_by: self.created_by.clone(),
+            expires_at: self.duration_secs.map(|secs| now.saturating_add(secs)),
+            show_reason: self.show_reason,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BanRequest {
+    pub hwid: String,
+    pub key: String,
+    /// Also ban every license this HWID has used and every other HWID those licenses
+    /// were used on.
+    #[serde(default)]
+    pub cascade: bool,
+    #[serde(flatten)]
+    pub options: BanOptions,
+}
+
+impl fmt::Debug for BanRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BanRequest")
+            .field("hwid", &self.hwid)
+            .field("key", &Redacted(&self.key))
+            .field("cascade", &self.cascade)
+            .field("options", &self.options)
+            .finish()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LicenseBanRequest {
+    pub license: String,
+    pub key: String,
+    #[serde(flatten)]
+    pub options: BanOptions,
+}
+
+impl fmt::Debug for LicenseBanRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LicenseBanRequest")
+            .field("license", &Redacted(&self.license))
+            .field("key", &Redacted(&self.key))
+            .field("options", &self.options)
+            .finish()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LicenseUnbanRequest {
+    pub license: String,
+    pub key: String,
+    #[serde(default)]
+    pub unbanned_by: Option<String>,
+}
+
+impl fmt::Debug for LicenseUnbanRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LicenseUnbanRequest")
+            .field("license", &Redacted(&self.license))
+            .field("key", &Redacted(&self.key))
+            .field("unbanned_by", &self.unbanned_by)
+            .finish()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct IpBanRequest {
+    /// A single address or a CIDR range.
+    pub ip: String,
+    pub key: String,
+    #[serde(flatten)]
+    pub options: BanOptions,
+}
+
+impl fmt::Debug for IpBanRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IpBanRequest")
+            .field("ip", &self.ip)
+            .field("key", &Redacted(&self.key))
+            .field("options", &self.options)
+            .finish()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct IpUnbanRequest {
+    pub ip: String,
+    pub key: String,
+    #[serde(default)]
+    pub unbanned_by: Option<String>,
+}
+
+impl fmt::Debug for IpUnbanRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IpUnbanRequest")
+            .field("ip", &self.ip)
+            .field("key", &Redacted(&self.key))
+            .field("unbanned_by", &self.unbanned_by)
+            .finish()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AdminRequest {
+    pub key: String,
+}
+
+impl fmt::Debug for AdminRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AdminRequest")
+            .field("key", &Redacted(&self.key))
+            .finish()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct IpBansResponse {
+    pub bans: Vec<IpBan>,
+}
+
+#[derive(Deserialize)]
+pub struct ArchiveSearchRequest {
+    pub key: String,
+    /// Matches archived license keys starting with this.
+    pub license: Option<String>,
+    pub product_id: Option<String>,
+    pub limit: Option<usize>,
+}
+
+impl fmt::Debug for ArchiveSearchRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ArchiveSearchRequest")
+            .field("key", &Redacted(&self.key))
+            .field("license", &self.license.as_deref().map(Redacted))
+            .field("product_id", &self.product_id)
+            .field("limit", &self.limit)
+            .finish()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ArchiveSearchResponse {
+    pub licenses: Vec<ArchivedLicense>,
+}
+
+#[derive(Deserialize)]
+pub struct ArchiveRestoreRequest {
+    pub key: String,
+    pub license: String,
+    /// New duration for the restored license. Keeps the original duration when unset.
+    pub days: Option<u64>,
+}
+
+impl fmt::Debug for ArchiveRestoreRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ArchiveRestoreRequest")
+            .field("key", &Redacted(&self.key))
+            .field("license", &Redacted(&self.license))
+            .field("days", &self.days)
+            .finish()
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -45,23 +296,74 @@ impl ErrorResponse {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Debug)]
+pub struct HealthResponse {
+    pub status: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ReadinessCheck {
+    pub name: String,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ReadinessResponse {
+    pub status: String,
+    pub checks: Vec<ReadinessCheck>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct License {
     pub key: String,
     pub used: bool,
     pub start: u64,
     pub duration: u64,
     pub product_ids: Vec<String>,
+    /// HWIDs this license has been used on.
+    #[serde(default)]
+    pub hwids: Vec<String>,
+    /// Set when the license itself is banned, it is then rejected even with time left.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ban: Option<BanDetails>,
+    /// Set on honeypot licenses, whose use is recorded as a canary hit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canary: Option<Canary>,
+    /// ID of the reseller that created this license.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reseller: Option<String>,
+}
+
+impl fmt::Debug for License {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("License")
+            .field("key", &Redacted(&self.key))
+            .field("used", &self.used)
+            .field("start", &self.start)
+            .field("duration", &self.duration)
+            .field("product_ids", &self.product_ids)
+            .field("hwids", &self.hwids)
+            .field("ban", &self.ban)
+            .field("canary", &self.canary)
+            .field("reseller", &self.reseller)
+            .finish()
+    }
 }
 
 impl License {
-    pub fn new(key: String, product_ids: &Vec<String>) -> Self {
+    pub fn new(key: String, product_ids: &[String]) -> Self {
         Self {
             key,
             used: false,
             start: 0,
             duration: 0,
-            product_ids: product_ids.clone(),
+            product_ids: product_ids.to_vec(),
+            hwids: Vec::new(),
+            ban: None,
+            canary: None,
+            reseller: None,
         }
     }
 
@@ -72,9 +374,807 @@ impl License {
 
     pub fn start(&mut self) {
         self.used = true;
-        self.start = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
+        self.start = unix_now();
+    }
+
+    pub fn expires_at(&self) -> u64 {
+        self.start.saturating_add(self.duration)
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.used && self.expires_at() <= now
+    }
+
+    /// The license's ban, if it is currently in effect.
+    pub fn active_ban(&self, now: u64) -> Option<&BanDetails> {
+        self.ban.as_ref().filter(|ban| ban.is_active(now))
+    }
+}
+
+/// What was recorded about a ban when it was made.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BanDetails {
+    pub reason: Option<String>,
+    pub created_at: u64,
+    pub created_by: Option<String>,
+    /// When the ban lifts. Permanent when unset.
+    pub expires_at: Option<u64>,
+    /// Whether `reason` is shown to the user in the `/auth` error.
+    #[serde(default)]
+    pub show_reason: bool,
+}
+
+impl BanDetails {
+    /// A permanent ban without a reason.
+    pub fn permanent(created_at: u64) -> Self {
+        Self {
+            reason: None,
+            created_at,
+            created_by: None,
+            expires_at: None,
+            show_reason: false,
+        }
+    }
+
+    pub fn is_active(&self, now: u64) -> bool {
+        self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+
+    /// The `/auth` error message for a ban on `subject`, e.g. "HWID".
+    pub fn message(&self, subject: &str) -> String {
+        match &self.reason {
+            Some(reason) if self.show_reason => {
+                format!("Your {} is banned: {}", subject, reason)
+            }
+            _ => format!("Your {} is banned.", subject),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HwidBan {
+    pub hwid: String,
+    #[serde(flatten)]
+    pub details: BanDetails,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct IpBan {
+    /// Banned address or range, single addresses are stored as /32 or /128.
+    pub network: IpNet,
+    #[serde(flatten)]
+    pub details: BanDetails,
+}
+
+/// What happens to whoever uses a canary license.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CanaryAction {
+    /// Ban the HWID and IP address.
+    Ban,
+    /// Only record the hit for review.
+    #[default]
+    Flag,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Canary {
+    /// Which batch or channel the key was handed to, to trace where it leaked.
+    pub label: String,
+    pub action: CanaryAction,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CanaryHit {
+    pub id: u64,
+    pub license: String,
+    pub label: String,
+    pub action: CanaryAction,
+    pub product_id: String,
+    pub hwid: String,
+    pub ip: Option<IpAddr>,
+    pub at: u64,
+    #[serde(default)]
+    pub reviewed: bool,
+}
+
+#[derive(Deserialize)]
+pub struct CanaryCreateRequest {
+    pub key: String,
+    pub days: u64,
+    pub product_ids: Vec<String>,
+    pub label: String,
+    #[serde(default)]
+    pub action: CanaryAction,
+    #[serde(default = "default_canary_count")]
+    pub count: usize,
+}
+
+fn default_canary_count() -> usize {
+    1
+}
+
+impl fmt::Debug for CanaryCreateRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CanaryCreateRequest")
+            .field("key", &Redacted(&self.key))
+            .field("days", &self.days)
+            .field("product_ids", &self.product_ids)
+            .field("label", &self.label)
+            .field("action", &self.action)
+            .field("count", &self.count)
+            .finish()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct CanaryCreateResponse {
+    pub licenses: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CanaryHitsRequest {
+    pub key: String,
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub unreviewed_only: bool,
+}
+
+impl fmt::Debug for CanaryHitsRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CanaryHitsRequest")
+            .field("key", &Redacted(&self.key))
+            .field("label", &self.label)
+            .field("unreviewed_only", &self.unreviewed_only)
+            .finish()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct CanaryHitsResponse {
+    pub hits: Vec<CanaryHit>,
+}
+
+#[derive(Deserialize)]
+pub struct CanaryReviewRequest {
+    pub key: String,
+    pub id: u64,
+}
+
+impl fmt::Debug for CanaryReviewRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CanaryReviewRequest")
+            .field("key", &Redacted(&self.key))
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+/// A suspicious pattern in a license's auth history.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyRule {
+    /// Too many distinct IP addresses in a short window.
+    DistinctIps,
+    /// Two networks too far apart to switch between that quickly.
+    ImpossibleTravel,
+    /// Too many distinct HWIDs in a short window.
+    HwidChurn,
+}
+
+impl AnomalyRule {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnomalyRule::DistinctIps => "distinct_ips",
+            AnomalyRule::ImpossibleTravel => "impossible_travel",
+            AnomalyRule::HwidChurn => "hwid_churn",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AnomalyFlag {
+    pub id: u64,
+    pub license: String,
+    pub product_id: String,
+    pub rule: AnomalyRule,
+    /// What was seen, e.g. `7 IP addresses in 3600s`.
+    pub detail: String,
+    pub at: u64,
+    /// Whether the license was suspended because of this flag.
+    #[serde(default)]
+    pub suspended: bool,
+    #[serde(default)]
+    pub reviewed: bool,
+}
+
+#[derive(Deserialize)]
+pub struct AnomalyFlagsRequest {
+    pub key: String,
+    #[serde(default)]
+    pub license: Option<String>,
+    #[serde(default)]
+    pub unreviewed_only: bool,
+}
+
+impl fmt::Debug for AnomalyFlagsRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AnomalyFlagsRequest")
+            .field("key", &Redacted(&self.key))
+            .field("license", &self.license.as_deref().map(Redacted))
+            .field("unreviewed_only", &self.unreviewed_only)
+            .finish()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct AnomalyFlagsResponse {
+    pub flags: Vec<AnomalyFlag>,
+}
+
+#[derive(Deserialize)]
+pub struct AnomalyReviewRequest {
+    pub key: String,
+    pub id: u64,
+    /// Also lift the suspension the flag caused.
+    #[serde(default)]
+    pub lift_suspension: bool,
+}
+
+impl fmt::Debug for AnomalyReviewRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AnomalyReviewRequest")
+            .field("key", &Redacted(&self.key))
+            .field("id", &self.id)
+            .field("lift_suspension", &self.lift_suspension)
+            .finish()
+    }
+}
+
+/// Events sent to webhook endpoints.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WebhookEvent {
+    #[serde(rename = "license.created")]
+    LicenseCreated,
+    /// A license was used for the first time and its time started running.
+    #[serde(rename = "license.activated")]
+    LicenseActivated,
+    #[serde(rename = "license.expired")]
+    LicenseExpired,
+    /// A license was banned, by an admin or automatically.
+    #[serde(rename = "license.revoked")]
+    LicenseRevoked,
+    #[serde(rename = "hwid.banned")]
+    HwidBanned,
+    #[serde(rename = "anomaly.flagged")]
+    AnomalyFlagged,
+    /// More auths failed within a window than `webhooks.failure_spike_threshold`.
+    #[serde(rename = "auth.failure_spike")]
+    AuthFailureSpike,
+    /// A payment provider reported a purchase and its licenses were created.
+    #[serde(rename = "order.completed")]
+    OrderCompleted,
+    /// A purchase was refunded and its licenses were banned.
+    #[serde(rename = "order.refunded")]
+    OrderRefunded,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::LicenseCreated => "license.created",
+            WebhookEvent::LicenseActivated => "license.activated",
+            WebhookEvent::LicenseExpired => "license.expired",
+            WebhookEvent::LicenseRevoked => "license.revoked",
+            WebhookEvent::HwidBanned => "hwid.banned",
+            WebhookEvent::AnomalyFlagged => "anomaly.flagged",
+            WebhookEvent::AuthFailureSpike => "auth.failure_spike",
+            WebhookEvent::OrderCompleted => "order.completed",
+            WebhookEvent::OrderRefunded => "order.refunded",
+        }
+    }
+}
+
+/// One event on its way to one webhook endpoint.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WebhookDelivery {
+    /// Event ID, shared by the deliveries of one event to several endpoints.
+    pub id: String,
+    pub event: WebhookEvent,
+    pub url: String,
+    /// JSON body, sent unchanged on every attempt.
+    pub body: String,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    /// When the next attempt is due, or when delivery was given up on.
+    pub at: u64,
+    /// Sent to a Discord channel, which takes the body unsigned.
+    #[serde(default)]
+    pub discord: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WebhookDeadLettersResponse {
+    pub dead_letters: Vec<WebhookDelivery>,
+}
+
+#[derive(Deserialize)]
+pub struct WebhookRedeliverRequest {
+    pub key: String,
+    /// Event to send again, every dead letter when unset.
+    #[serde(default)]
+    pub id: Option<String>,
+}
+
+impl fmt::Debug for WebhookRedeliverRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebhookRedeliverRequest")
+            .field("key", &Redacted(&self.key))
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+/// An account that creates licenses with its own key, within a quota.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Reseller {
+    pub id: String,
+    /// Hex SHA-256 of the reseller's key, which is only shown once, on creation.
+    pub key_hash: String,
+    /// Products the reseller may create licenses for.
+    pub product_ids: Vec<String>,
+    /// Most licenses the reseller may create. Unlimited when unset.
+    pub max_keys: Option<u64>,
+    /// Most days of license time the reseller may hand out in total. Unlimited when
+    /// unset.
+    pub max_days: Option<u64>,
+    pub keys_created: u64,
+    pub days_created: u64,
+    pub created_at: u64,
+    /// Disabled resellers can't create licenses, their existing ones keep working.
+    #[serde(default)]
+    pub disabled: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ResellerCreateRequest {
+    pub key: String,
+    pub id: String,
+    pub product_ids: Vec<String>,
+    #[serde(default)]
+    pub max_keys: Option<u64>,
+    #[serde(default)]
+    pub max_days: Option<u64>,
+}
+
+impl fmt::Debug for ResellerCreateRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResellerCreateRequest")
+            .field("key", &Redacted(&self.key))
+            .field("id", &self.id)
+            .field("product_ids", &self.product_ids)
+            .field("max_keys", &self.max_keys)
+            .field("max_days", &self.max_days)
+            .finish()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ResellerCreateResponse {
+    pub id: String,
+    /// The key the reseller creates licenses with. Not stored, so it can't be shown
+    /// again.
+    pub reseller_key: String,
+}
+
+/// Changes to a reseller. Fields left out stay as they are.
+#[derive(Deserialize)]
+pub struct ResellerUpdateRequest {
+    pub key: String,
+    pub id: String,
+    #[serde(default)]
+    pub product_ids: Option<Vec<String>>,
+    #[serde(default)]
+    pub max_keys: Option<u64>,
+    #[serde(default)]
+    pub max_days: Option<u64>,
+    #[serde(default)]
+    pub disabled: Option<bool>,
+}
+
+impl fmt::Debug for ResellerUpdateRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResellerUpdateRequest")
+            .field("key", &Redacted(&self.key))
+            .field("id", &self.id)
+            .field("product_ids", &self.product_ids)
+            .field("max_keys", &self.max_keys)
+            .field("max_days", &self.max_days)
+            .field("disabled", &self.disabled)
+            .finish()
+    }
+}
+
+/// A reseller with what it has created so far.
+#[derive(Serialize, Debug)]
+pub struct ResellerUsage {
+    pub id: String,
+    pub product_ids: Vec<String>,
+    pub max_keys: Option<u64>,
+    pub max_days: Option<u64>,
+    pub keys_created: u64,
+    pub days_created: u64,
+    pub created_at: u64,
+    pub disabled: bool,
+    /// Licenses of the reseller still in the store, i.e. not expired.
+    pub live_licenses: usize,
+    pub unused_licenses: usize,
+    pub banned_licenses: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ResellersResponse {
+    pub resellers: Vec<ResellerUsage>,
+}
+
+/// Bans every license a reseller created, e.g. after a chargeback or a leak.
+#[derive(Deserialize)]
+pub struct ResellerRevokeRequest {
+    pub key: String,
+    pub id: String,
+    /// Also stop the reseller from creating more licenses.
+    #[serde(default)]
+    pub disable: bool,
+    #[serde(flatten)]
+    pub options: BanOptions,
+}
+
+impl fmt::Debug for ResellerRevokeRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResellerRevokeRequest")
+            .field("key", &Redacted(&self.key))
+            .field("id", &self.id)
+            .field("disable", &self.disable)
+            .field("options", &self.options)
+            .finish()
+    }
+}
+
+/// A purchase reported by a payment provider and the licenses created for it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Order {
+    /// `stripe` or `generic`.
+    pub provider: String,
+    pub order_id: String,
+    pub sku: String,
+    pub licenses: Vec<String>,
+    pub email: Option<String>,
+    /// The provider's reference to the payment, which refunds name instead of the order.
+    pub payment_ref: Option<String>,
+    pub created_at: u64,
+    pub refunded_at: Option<u64>,
+}
+
+/// Body of the generic payment webhook.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum PaymentEvent {
+    Purchase {
+        order_id: String,
+        sku: String,
+        #[serde(default)]
+        email: Option<String>,
+        #[serde(default = "default_quantity")]
+        quantity: u32,
+    },
+    Refund {
+        order_id: String,
+    },
+}
+
+fn default_quantity() -> u32 {
+    1
+}
+
+#[derive(Serialize, Debug)]
+pub struct OrderResponse {
+    pub order_id: String,
+    pub licenses: Vec<String>,
+}
+
+/// Remote variables of a product: strings, numbers or any JSON value, by name.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ProductVariables {
+    #[serde(default)]
+    pub values: BTreeMap<String, Value>,
+    /// Values that replace or add to `values` for a single license, by license key.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub license_overrides: BTreeMap<String, BTreeMap<String, Value>>,
+}
+
+impl ProductVariables {
+    /// The variables a client of `license` gets.
+    pub fn resolve(&self, license: &str) -> BTreeMap<String, Value> {
+        let mut values = self.values.clone();
+        if let Some(overrides) = self.license_overrides.get(license) {
+            values.extend(overrides.clone());
+        }
+        values
+    }
+}
+
+/// Sets a product variable, or its override for one license when `license` is set.
+#[derive(Deserialize)]
+pub struct VariableSetRequest {
+    pub key: String,
+    pub product_id: String,
+    pub name: String,
+    pub value: Value,
+    #[serde(default)]
+    pub license: Option<String>,
+}
+
+impl fmt::Debug for VariableSetRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VariableSetRequest")
+            .field("key", &Redacted(&self.key))
+            .field("product_id", &self.product_id)
+            .field("name", &self.name)
+            .field("license", &self.license.as_deref().map(Redacted))
+            .finish_non_exhaustive()
     }
 }
+
+/// Removes a product variable, or only its override for one license when `license` is
+/// set.
+#[derive(Deserialize)]
+pub struct VariableDeleteRequest {
+    pub key: String,
+    pub product_id: String,
+    pub name: String,
+    #[serde(default)]
+    pub license: Option<String>,
+}
+
+impl fmt::Debug for VariableDeleteRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VariableDeleteRequest")
+            .field("key", &Redacted(&self.key))
+            .field("product_id", &self.product_id)
+            .field("name", &self.name)
+            .field("license", &self.license.as_deref().map(Redacted))
+            .finish()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct VariablesListRequest {
+    pub key: String,
+    /// Only list the variables of this product.
+    #[serde(default)]
+    pub product_id: Option<String>,
+}
+
+impl fmt::Debug for VariablesListRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VariablesListRequest")
+            .field("key", &Redacted(&self.key))
+            .field("product_id", &self.product_id)
+            .finish()
+    }
+}
+
+#[derive(Serialize)]
+pub struct VariablesListResponse {
+    pub products: BTreeMap<String, ProductVariables>,
+}
+
+/// The variables of the product of a session.
+#[derive(Serialize, Debug)]
+pub struct VariablesResponse {
+    pub product_id: String,
+    pub variables: BTreeMap<String, Value>,
+}
+
+/// A product switched off by an admin, whose clients are turned away until it is
+/// enabled again.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DisabledProduct {
+    /// Shown to clients in place of the usual response.
+    pub message: String,
+    pub since: u64,
+    /// Whether the licenses of the product stop running down until it is enabled.
+    pub pause_time: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ProductDisableRequest {
+    pub key: String,
+    pub product_id: String,
+    /// Defaults to `maintenance.disabled_product_message`.
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub pause_time: bool,
+    #[serde(default)]
+    pub created_by: Option<String>,
+}
+
+impl fmt::Debug for ProductDisableRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProductDisableRequest")
+            .field("key", &Redacted(&self.key))
+            .field("product_id", &self.product_id)
+            .field("message", &self.message)
+            .field("pause_time", &self.pause_time)
+            .field("created_by", &self.created_by)
+            .finish()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ProductEnableRequest {
+    pub key: String,
+    pub product_id: String,
+}
+
+impl fmt::Debug for ProductEnableRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProductEnableRequest")
+            .field("key", &Redacted(&self.key))
+            .field("product_id", &self.product_id)
+            .finish()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ProductEnableResponse {
+    pub product_id: String,
+    /// Seconds added to the licenses of the product, when their time was paused.
+    pub paused_secs: u64,
+    pub licenses_extended: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DisabledProductsResponse {
+    pub products: BTreeMap<String, DisabledProduct>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    #[default]
+    Info,
+    Warning,
+    Critical,
+}
+
+/// A message shown to users of some or all products for a while.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Announcement {
+    pub id: String,
+    pub title: String,
+    /// Markdown.
+    pub body: String,
+    pub severity: Severity,
+    /// Shown from this time on, right away when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub starts_at: Option<u64>,
+    /// Shown until this time, for good when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ends_at: Option<u64>,
+    /// Products it is shown for, every product when empty.
+    #[serde(default)]
+    pub product_ids: Vec<String>,
+    pub created_at: u64,
+}
+
+impl Announcement {
+    pub fn is_live(&self, now: u64) -> bool {
+        self.starts_at.is_none_or(|starts_at| starts_at <= now)
+            && self.ends_at.is_none_or(|ends_at| now < ends_at)
+    }
+
+    pub fn targets(&self, product_id: Option<&str>) -> bool {
+        self.product_ids.is_empty()
+            || product_id
+                .is_some_and(|product_id| self.product_ids.iter().any(|id| id == product_id))
+    }
+}
+
+/// What an admin writes of an announcement.
+#[derive(Deserialize, Debug)]
+pub struct AnnouncementFields {
+    pub title: String,
+    pub body: String,
+    #[serde(default)]
+    pub severity: Severity,
+    #[serde(default)]
+    pub starts_at: Option<u64>,
+    #[serde(default)]
+    pub ends_at: Option<u64>,
+    #[serde(default)]
+    pub product_ids: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AnnouncementCreateRequest {
+    pub key: String,
+    #[serde(flatten)]
+    pub announcement: AnnouncementFields,
+}
+
+impl fmt::Debug for AnnouncementCreateRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AnnouncementCreateRequest")
+            .field("key", &Redacted(&self.key))
+            .field("announcement", &self.announcement)
+            .finish()
+    }
+}
+
+/// Replaces the content of an announcement. Fields left out are cleared, as when
+/// creating one.
+#[derive(Deserialize)]
+pub struct AnnouncementUpdateRequest {
+    pub key: String,
+    pub id: String,
+    #[serde(flatten)]
+    pub announcement: AnnouncementFields,
+}
+
+impl fmt::Debug for AnnouncementUpdateRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AnnouncementUpdateRequest")
+            .field("key", &Redacted(&self.key))
+            .field("id", &self.id)
+            .field("announcement", &self.announcement)
+            .finish()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AnnouncementDeleteRequest {
+    pub key: String,
+    pub id: String,
+}
+
+impl fmt::Debug for AnnouncementDeleteRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AnnouncementDeleteRequest")
+            .field("key", &Redacted(&self.key))
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AnnouncementsQuery {
+    /// Without it, only announcements for every product are listed.
+    #[serde(default)]
+    pub product_id: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AnnouncementsResponse {
+    pub announcements: Vec<Announcement>,
+}
+
+/// An entry of the banned HWIDs file, either a ban record or a bare HWID written by
+/// older versions.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum StoredBan {
+    Ban(HwidBan),
+    Legacy(String),
+}
+
+pub fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
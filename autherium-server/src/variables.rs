@@ -0,0 +1,163 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, Result, get, post, web};
+use tracing::info;
+
+use crate::{
+    State, bad_request, check_key, logging::Redacted, not_found, sessions, storage::DataFile,
+    types::*,
+};
+
+/// Largest a single value may be once serialized.
+const MAX_VALUE_LEN: usize = 64 * 1024;
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+/// The variables of the product of a session, with the overrides of its license.
+#[get("/variables")]
+async fn session_variables(http: HttpRequest, state: web::Data<State>) -> Result<impl Responder> {
+    let (_, session) = sessions::authorize(&http, &state)?;
+    let variables = state
+        .variables
+        .read()
+        .unwrap()
+        .get(&session.product_id)
+        .map(|variables| variables.resolve(&session.license))
+        .unwrap_or_default();
+
+    Ok(HttpResponse::Ok().json(VariablesResponse {
+        product_id: session.product_id,
+        variables,
+    }))
+}
+
+#[post("/variables/set")]
+async fn set_variable(
+    req: web::Json<VariableSetRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    check_key(&req.key, &state)?;
+
+    if req.product_id.is_empty() || !valid_name(&req.name) {
+        return Err(bad_request("Invalid variable."));
+    }
+    if req.value.to_string().len() > MAX_VALUE_LEN {
+        return Err(bad_request("Variable value too large."));
+    }
+    if let Some(license) = &req.license {
+        let licensed = state
+            .licenses
+            .read()
+            .unwrap()
+            .get(license)
+            .is_some_and(|license| license.product_ids.contains(&req.product_id));
+        if !licensed {
+            return Err(not_found("License not found."));
+        }
+    }
+
+    {
+        let mut variables = state.variables.write().unwrap();
+        let product = variables.entry(req.product_id.clone()).or_default();
+        let values = match &req.license {
+            Some(license) => product
+                .license_overrides
+                .entry(license.clone())
+                .or_default(),
+            None => &mut product.values,
+        };
+        values.insert(req.name.clone(), req.value.clone());
+    }
+    state.schedule_save(DataFile::Variables);
+    info!(
+        product_id = %req.product_id,
+        name = %req.name,
+        license = ?req.license.as_deref().map(Redacted),
+        "Variable set"
+    );
+
+    Ok(HttpResponse::Ok().json(ErrorResponse::new("Variable set successfully.")))
+}
+
+#[post("/variables/delete")]
+async fn delete_variable(
+    req: web::Json<VariableDeleteRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    check_key(&req.key, &state)?;
+
+    {
+        let mut variables = state.variables.write().unwrap();
+        let Some(product) = variables.get_mut(&req.product_id) else {
+            return Err(not_found("Variable not found."));
+        };
+        let removed = match &req.license {
+            Some(license) => {
+                let removed = product
+                    .license_overrides
+                    .get_mut(license)
+                    .and_then(|overrides| overrides.remove(&req.name));
+                if product
+                    .license_overrides
+                    .get(license)
+                    .is_some_and(|overrides| overrides.is_empty())
+                {
+                    product.license_overrides.remove(license);
+                }
+                removed
+            }
+            // Overrides of a variable that no longer exists would be all that is left
+            // of it, so they go too
+            None => {
+                let removed = product.values.remove(&req.name);
+                product.license_overrides.retain(|_, overrides| {
+                    overrides.remove(&req.name);
+                    !overrides.is_empty()
+                });
+                removed
+            }
+        };
+        if removed.is_none() {
+            return Err(not_found("Variable not found."));
+        }
+        if product.values.is_empty() && product.license_overrides.is_empty() {
+            variables.remove(&req.product_id);
+        }
+    }
+    state.schedule_save(DataFile::Variables);
+    info!(
+        product_id = %req.product_id,
+        name = %req.name,
+        license = ?req.license.as_deref().map(Redacted),
+        "Variable deleted"
+    );
+
+    Ok(HttpResponse::Ok().json(ErrorResponse::new("Variable deleted successfully.")))
+}
+
+#[post("/variables/list")]
+async fn list_variables(
+    req: web::Json<VariablesListRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    check_key(&req.key, &state)?;
+
+    let products = state
+        .variables
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|(product_id, _)| {
+            req.product_id
+                .as_ref()
+                .is_none_or(|wanted| wanted == *product_id)
+        })
+        .map(|(product_id, variables)| (product_id.clone(), variables.clone()))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(VariablesListResponse { products }))
+}
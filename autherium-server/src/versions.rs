@@ -0,0 +1,93 @@
+use std::{cmp::Ordering, str::FromStr};
+
+use crate::{config::VersionPolicy, types::UpdateInfo};
+
+/// A dotted client version like `1.4.2`, with an optional leading `v`.
+///
+/// Missing parts count as zero, so `1.4` equals `1.4.0`. A pre-release such as
+/// `1.4.2-beta.1` sorts just before its release, and build metadata after `+` is
+/// ignored.
+#[derive(PartialEq, Eq, Debug)]
+pub struct Version {
+    numbers: Vec<u64>,
+    release: bool,
+}
+
+impl FromStr for Version {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.split_once('+').map_or(s, |(version, _)| version);
+        let s = s.strip_prefix('v').unwrap_or(s);
+        let (core, release) = match s.split_once('-') {
+            Some((core, _)) => (core, false),
+            None => (s, true),
+        };
+        let mut numbers = core
+            .split('.')
+            .map(|part| part.parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("`{}` is not a valid version", s))?;
+        while numbers.last() == Some(&0) {
+            numbers.pop();
+        }
+        Ok(Self { numbers, release })
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.numbers
+            .cmp(&other.numbers)
+            .then(self.release.cmp(&other.release))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+pub enum VersionCheck {
+    Current,
+    /// The client still works but a newer version is out.
+    UpdateAvailable(UpdateInfo),
+    /// The client is older than the minimum supported version.
+    UpdateRequired(UpdateInfo),
+}
+
+/// Checks the version a client reported against the policy of its product.
+///
+/// Clients that report no version, or one that doesn't parse, predate version
+/// reporting and are treated as outdated whenever the product has a minimum.
+pub fn check(policy: Option<&VersionPolicy>, client_version: Option<&str>) -> VersionCheck {
+    let Some(policy) = policy else {
+        return VersionCheck::Current;
+    };
+    let info = || UpdateInfo {
+        min_version: policy.min_version.clone(),
+        latest_version: policy.latest_version.clone(),
+        download_url: policy.download_url.clone(),
+        changelog: policy.changelog.clone(),
+    };
+    // Policy versions are checked when the config is loaded
+    let parse = |version: &Option<String>| version.as_deref().and_then(|v| v.parse().ok());
+    let min_version: Option<Version> = parse(&policy.min_version);
+    let latest_version: Option<Version> = parse(&policy.latest_version);
+
+    let Some(version) = client_version.and_then(|v| v.parse::<Version>().ok()) else {
+        return if min_version.is_some() {
+            VersionCheck::UpdateRequired(info())
+        } else {
+            VersionCheck::Current
+        };
+    };
+    if min_version.is_some_and(|min_version| version < min_version) {
+        VersionCheck::UpdateRequired(info())
+    } else if latest_version.is_some_and(|latest_version| version < latest_version) {
+        VersionCheck::UpdateAvailable(info())
+    } else {
+        VersionCheck::Current
+    }
+}
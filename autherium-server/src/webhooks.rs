@@ -0,0 +1,842 @@
+use actix_web::{HttpResponse, Responder, Result, error, http::header, post, web};
+use ring::hmac;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    sync::{Condvar, Mutex},
+    time::Duration,
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    State, config::WebhooksConfig, discord, logging::Redacted, metrics::METRICS, storage,
+    storage::DataFile, types::*,
+};
+
+/// Deliveries waiting for their next attempt and those that ran out of attempts.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Outbox {
+    pending: Vec<WebhookDelivery>,
+    dead_letters: Vec<WebhookDelivery>,
+}
+
+#[derive(Default)]
+struct FailureWindow {
+    started: u64,
+    count: u64,
+    reported: bool,
+}
+
+/// Outgoing webhook deliveries, persisted so retries survive a restart.
+pub struct Webhooks {
+    outbox: Mutex<Outbox>,
+    wake: Condvar,
+    failures: Mutex<FailureWindow>,
+}
+
+impl Webhooks {
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            outbox: Mutex::new(storage::read_json(path)?.unwrap_or_default()),
+            wake: Condvar::new(),
+            failures: Mutex::new(FailureWindow::default()),
+        })
+    }
+
+    pub fn snapshot(&self) -> Outbox {
+        self.outbox.lock().unwrap().clone()
+    }
+
+    /// Blocks until at least one pending delivery is due and returns copies of them.
+    /// They stay in the outbox until [`Webhooks::finish`], so a crash mid-delivery
+    /// only means sending them again.
+    fn next_due(&self) -> Vec<WebhookDelivery> {
+        let mut outbox = self.outbox.lock().unwrap();
+        loop {
+            let now = unix_now();
+            let due = outbox
+                .pending
+                .iter()
+                .filter(|delivery| delivery.at <= now)
+                .cloned()
+                .collect::<Vec<_>>();
+            if !due.is_empty() {
+                return due;
+            }
+            outbox = match outbox
+                .pending
+                .iter()
+                .map(|delivery| delivery.at - now)
+                .min()
+            {
+                Some(secs) => {
+                    self.wake
+                        .wait_timeout(outbox, Duration::from_secs(secs))
+                        .unwrap()
+                        .0
+                }
+                None => self.wake.wait(outbox).unwrap(),
+            };
+        }
+    }
+
+    /// Records the outcome of an attempt, rescheduling or dead-lettering failures.
+    fn finish(
+        &self,
+        delivery: &WebhookDelivery,
+        result: Result<(), String>,
+        config: &WebhooksConfig,
+    ) {
+        let now = unix_now();
+        let mut outbox = self.outbox.lock().unwrap();
+        let Some(index) = outbox
+            .pending
+            .iter()
+            .position(|pending| pending.id == delivery.id && pending.url == delivery.url)
+        else {
+            return;
+        };
+        let error = match result {
+            Ok(()) => {
+                outbox.pending.remove(index);
+                METRICS.record_webhook_delivery("delivered");
+                info!(
+                    id = %delivery.id,
+                    event = delivery.event.as_str(),
+                    url = %delivery.url,
+                    "Webhook delivered"
+                );
+                return;
+            }
+            Err(error) => error,
+        };
+
+        let pending = &mut outbox.pending[index];
+        pending.attempts += 1;
+        pending.last_error = Some(error.clone());
+        if pending.attempts < config.max_attempts {
+            let backoff = config
+                .initial_backoff_secs
+                .saturating_mul(1 << (pending.attempts - 1).min(32))
+                .min(config.max_backoff_secs);
+            pending.at = now + backoff;
+            METRICS.record_webhook_delivery("retried");
+            warn!(
+                id = %delivery.id,
+                event = delivery.event.as_str(),
+                url = %delivery.url,
+                attempts = pending.attempts,
+                retry_in_secs = backoff,
+                error = %error,
+                "Webhook delivery failed"
+            );
+            return;
+        }
+
+        let mut dead = outbox.pending.remove(index);
+        dead.at = now;
+        outbox.dead_letters.push(dead);
+        let excess = outbox
+            .dead_letters
+            .len()
+            .saturating_sub(config.max_dead_letters);
+        outbox.dead_letters.drain(..excess);
+        METRICS.record_webhook_delivery("dead_lettered");
+        error!(
+            id = %delivery.id,
+            event = delivery.event.as_str(),
+            url = %delivery.url,
+            attempts = config.max_attempts,
+            error = %error,
+            "Webhook delivery gave up, moved to the dead-letter queue"
+        );
+    }
+}
+
+/// Queues `event` for every endpoint and Discord channel subscribed to it.
+pub fn emit(state: &State, event: WebhookEvent, data: serde_json::Value) {
+    let now = unix_now();
+    let id = format!("evt_{:016x}", rand::random::<u64>());
+    let mut deliveries = discord::deliveries(&state.config.discord, &id, event, &data, now);
+
+    let urls = state
+        .config
+        .webhooks
+        .endpoints
+        .iter()
+        .filter(|endpoint| endpoint.wants(event))
+        .map(|endpoint| endpoint.url.clone())
+        .collect::<Vec<_>>();
+    if !urls.is_empty() {
+        let body = json!({
+            "id": id,
+            "event": event,
+            "created_at": now,
+            "data": data,
+        })
+        .to_string();
+        deliveries.extend(urls.into_iter().map(|url| WebhookDelivery {
+            id: id.clone(),
+            event,
+            url,
+            body: body.clone(),
+            attempts: 0,
+            last_error: None,
+            at: now,
+            discord: false,
+        }));
+    }
+    if deliveries.is_empty() {
+        return;
+    }
+
+    state
+        .webhooks
+        .outbox
+        .lock()
+        .unwrap()
+        .pending
+        .extend(deliveries);
+    state.webhooks.wake.notify_one();
+    state.schedule_save(DataFile::WebhookOutbox);
+}
+
+pub fn license_revoked(state: &State, license: &str, ban: &BanDetails) {
+    let product_ids = state
+        .licenses
+        .read()
+        .unwrap()
+        .get(license)
+        .map(|license| license.product_ids.clone())
+        .unwrap_or_default();
+    emit(
+        state,
+        WebhookEvent::LicenseRevoked,
+        json!({ "license": license, "product_ids": product_ids, "ban": ban }),
+    );
+}
+
+pub fn hwid_banned(state: &State, hwid: &str, ban: &BanDetails) {
+    emit(
+        state,
+        WebhookEvent::HwidBanned,
+        json!({ "hwid": hwid, "ban": ban }),
+    );
+}
+
+/// Counts a failed auth and sends `auth.failure_spike` once per window when the
+/// threshold is crossed.
+pub fn record_auth_failure(state: &State) {
+    let config = &state.config.webhooks;
+    let Some(threshold) = config.failure_spike_threshold else {
+        return;
+    };
+    let now = unix_now();
+    let (failures, since) = {
+        let mut window = state.webhooks.failures.lock().unwrap();
+        if now >= window.started + config.failure_spike_window_secs {
+            *window = FailureWindow {
+                started: now,
+                ..Default::default()
+            };
+        }
+        window.count += 1;
+        if window.count <= threshold || window.reported {
+            return;
+        }
+        window.reported = true;
+        (window.count, window.started)
+    };
+    warn!(failures, since, "Auth failure spike");
+    emit(
+        state,
+        WebhookEvent::AuthFailureSpike,
+        json!({
+            "failures": failures,
+            "since": since,
+            "window_secs": config.failure_spike_window_secs,
+        }),
+    );
+}
+
+/// `t=<unix time>,v1=<hex HMAC-SHA256 of "<unix time>.<body>">`. Signing the time
+/// along with the body lets receivers reject replayed deliveries.
+pub fn signature(secret: &str, timestamp: u64, body: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let tag = hmac::sign(&key, format!("{}.{}", timestamp, body).as_bytes());
+    let hex = tag
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    format!("t={},v1={}", timestamp, hex)
+}
+
+/// Checks a signature made like [`signature`], as Stripe also signs its webhooks.
+///
+/// Accepts any of several `v1` values, which providers send while rotating secrets, and
+/// rejects signatures more than `tolerance_secs` away from `now`.
+pub fn verify_signature(
+    secret: &str,
+    header: &str,
+    body: &[u8],
+    tolerance_secs: u64,
+    now: u64,
+) -> bool {
+    let mut timestamp = None;
+    let mut tags = Vec::new();
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.parse::<u64>().ok(),
+            Some(("v1", value)) => tags.extend(decode_hex(value)),
+            _ => {}
+        }
+    }
+    let Some(timestamp) = timestamp else {
+        return false;
+    };
+    if timestamp.abs_diff(now) > tolerance_secs {
+        return false;
+    }
+    let mut message = format!("{}.", timestamp).into_bytes();
+    message.extend_from_slice(body);
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    tags.iter()
+        .any(|tag| hmac::verify(&key, &message, tag).is_ok())
+}
+
+pub fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    // from_str_radix would also take a sign
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn send(
+    client: &reqwest::blocking::Client,
+    config: &WebhooksConfig,
+    delivery: &WebhookDelivery,
+) -> Result<(), String> {
+    let mut request = client
+        .post(&delivery.url)
+        .header(header::CONTENT_TYPE.as_str(), "application/json");
+    if !delivery.discord {
+        let endpoint = config
+            .endpoints
+            .iter()
+            .find(|endpoint| endpoint.url == delivery.url)
+            .ok_or("endpoint is no longer configured")?;
+        request = request
+            .header("X-Autherium-Event", delivery.event.as_str())
+            .header("X-Autherium-Delivery", &delivery.id)
+            .header(
+                "X-Autherium-Signature",
+                signature(&endpoint.secret, unix_now(), &delivery.body),
+            );
+    }
+    let response = request
+        .body(delivery.body.clone())
+        .send()
+        .map_err(|e| e.to_string())?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("endpoint answered {}", response.status()))
+    }
+}
+
+/// Delivers queued events for as long as the server runs. Blocks, so it gets its own
+/// thread.
+pub fn run(state: &State) {
+    let config = &state.config.webhooks;
+    let client = match reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(config.timeout_secs))
+        .user_agent(concat!("autherium-server/", env!("CARGO_PKG_VERSION")))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            error!(error = %e, "Failed to create the webhook client, webhooks are disabled");
+            return;
+        }
+    };
+
+    loop {
+        for delivery in state.webhooks.next_due() {
+            let result = send(&client, config, &delivery);
+            state.webhooks.finish(&delivery, result, config);
+            state.schedule_save(DataFile::WebhookOutbox);
+        }
+    }
+}
+
+#[post("/webhooks/dead-letters")]
+async fn dead_letters(
+    req: web::Json<AdminRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    if req.key != state.config.security.api_key {
+        warn!(key = %Redacted(&req.key), "Rejected request with an invalid API key");
+        return Err(error::InternalError::from_response(
+            "Invalid API key.",
+            HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid API key.")),
+        )
+        .into());
+    }
+
+    let dead_letters = state.webhooks.outbox.lock().unwrap().dead_letters.clone();
+    Ok(HttpResponse::Ok().json(WebhookDeadLettersResponse { dead_letters }))
+}
+
+#[post("/webhooks/redeliver")]
+async fn redeliver(
+    req: web::Json<WebhookRedeliverRequest>,
+    state: web::Data<State>,
+) -> Result<impl Responder> {
+    if req.key != state.config.security.api_key {
+        warn!(key = %Redacted(&req.key), "Rejected request with an invalid API key");
+        return Err(error::InternalError::from_response(
+            "Invalid API key.",
+            HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid API key.")),
+        )
+        .into());
+    }
+
+    let now = unix_now();
+    let requeued = {
+        let mut outbox = state.webhooks.outbox.lock().unwrap();
+        let (requeue, keep) = std::mem::take(&mut outbox.dead_letters)
+            .into_iter()
+            .partition::<Vec<_>, _>(|dead| req.id.as_ref().is_none_or(|id| dead.id == *id));
+        outbox.dead_letters = keep;
+        let requeued = requeue.len();
+        outbox
+            .pending
+            .extend(requeue.into_iter().map(|delivery| WebhookDelivery {
+                attempts: 0,
+                at: now,
+                ..delivery
+            }));
+        requeued
+    };
+    if req.id.is_some() && requeued == 0 {
+        return Err(error::InternalError::from_response(
+            "Dead letter not found.",
+            HttpResponse::NotFound().json(ErrorResponse::new("Dead letter not found.")),
+        )
+        .into());
+    }
+
+    state.webhooks.wake.notify_one();
+    state.schedule_save(DataFile::WebhookOutbox);
+    info!(id = ?req.id, requeued, "Webhook dead letters requeued");
+
+    Ok(HttpResponse::Ok().json(ErrorResponse::new(&format!(
+        "Requeued {} webhook delivery(s).",
+        requeued
+    ))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WebhookEndpoint;
+    use std::{
+        collections::HashMap,
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::mpsc,
+        thread,
+    };
+
+    const SECRET: &str = "whsec_test";
+    const BODY: &str = r#"{"license":"ABC"}"#;
+
+    /// A request as the stand-in receiver saw it, with lowercase header names.
+    struct Received {
+        headers: HashMap<String, String>,
+        body: String,
+    }
+
+    /// Local stand-in for a webhook endpoint, answering a single request with `status`.
+    fn receiver(status: u16) -> (String, mpsc::Receiver<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let mut headers = HashMap::new();
+            loop {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                let Some((name, value)) = line.trim_end().split_once(':') else {
+                    break;
+                };
+                headers.insert(name.to_ascii_lowercase(), value.trim().to_string());
+            }
+            let len = headers
+                .get("content-length")
+                .and_then(|len| len.parse().ok())
+                .unwrap_or(0);
+            let mut body = vec![0; len];
+            reader.read_exact(&mut body).unwrap();
+            write!(
+                reader.into_inner(),
+                "HTTP/1.1 {} Stand-in\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                status
+            )
+            .unwrap();
+            tx.send(Received {
+                headers,
+                body: String::from_utf8(body).unwrap(),
+            })
+            .unwrap();
+        });
+        (url, rx)
+    }
+
+    fn delivery(id: &str, url: &str) -> WebhookDelivery {
+        WebhookDelivery {
+            id: id.to_string(),
+            event: WebhookEvent::LicenseCreated,
+            url: url.to_string(),
+            body: BODY.to_string(),
+            attempts: 0,
+            last_error: None,
+            at: 0,
+            discord: false,
+        }
+    }
+
+    fn config(url: &str) -> WebhooksConfig {
+        WebhooksConfig {
+            endpoints: vec![WebhookEndpoint {
+                url: url.to_string(),
+                secret: SECRET.to_string(),
+                events: Vec::new(),
+            }],
+            max_attempts: 5,
+            initial_backoff_secs: 10,
+            max_backoff_secs: 25,
+            max_dead_letters: 2,
+            ..WebhooksConfig::default()
+        }
+    }
+
+    fn webhooks(pending: Vec<WebhookDelivery>) -> Webhooks {
+        Webhooks {
+            outbox: Mutex::new(Outbox {
+                pending,
+                dead_letters: Vec::new(),
+            }),
+            wake: Condvar::new(),
+            failures: Mutex::new(FailureWindow::default()),
+        }
+    }
+
+    fn client() -> reqwest::blocking::Client {
+        reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn signature_matches_known_vector() {
+        assert_eq!(
+            signature(SECRET, 1_700_000_000, BODY),
+            "t=1700000000,v1=4f753259e65046cf22a10990bc808f427c966f184dc26b4d66026ac944da1041"
+        );
+    }
+
+    #[test]
+    fn signature_covers_timestamp_and_body() {
+        let signed = signature(SECRET, 1_700_000_000, BODY);
+        assert_ne!(signed, signature(SECRET, 1_700_000_001, BODY));
+        assert_ne!(
+            signed,
+            signature(SECRET, 1_700_000_000, r#"{"license":"ABD"}"#)
+        );
+        assert_ne!(signed, signature("other", 1_700_000_000, BODY));
+    }
+
+    #[test]
+    fn verify_accepts_a_good_signature() {
+        let header = signature(SECRET, 1_700_000_000, BODY);
+        assert!(verify_signature(
+            SECRET,
+            &header,
+            BODY.as_bytes(),
+            300,
+            1_700_000_000
+        ));
+        assert!(verify_signature(
+            SECRET,
+            &header,
+            BODY.as_bytes(),
+            300,
+            1_700_000_300
+        ));
+        assert!(verify_signature(
+            SECRET,
+            &header,
+            BODY.as_bytes(),
+            300,
+            1_699_999_700
+        ));
+    }
+
+    #[test]
+    fn verify_rejects_a_wrong_secret_or_body() {
+        let header = signature(SECRET, 1_700_000_000, BODY);
+        assert!(!verify_signature(
+            "other",
+            &header,
+            BODY.as_bytes(),
+            300,
+            1_700_000_000
+        ));
+        assert!(!verify_signature(
+            SECRET,
+            &header,
+            b"{}",
+            300,
+            1_700_000_000
+        ));
+    }
+
+    #[test]
+    fn verify_rejects_signatures_outside_the_tolerance() {
+        let header = signature(SECRET, 1_700_000_000, BODY);
+        assert!(!verify_signature(
+            SECRET,
+            &header,
+            BODY.as_bytes(),
+            300,
+            1_700_000_301
+        ));
+        assert!(!verify_signature(
+            SECRET,
+            &header,
+            BODY.as_bytes(),
+            300,
+            1_699_999_699
+        ));
+    }
+
+    #[test]
+    fn verify_accepts_any_of_several_tags() {
+        let good = signature(SECRET, 1_700_000_000, BODY);
+        let good_tag = good.split_once(",v1=").unwrap().1;
+        let other_tag = signature("rotated", 1_700_000_000, BODY);
+        let other_tag = other_tag.split_once(",v1=").unwrap().1;
+        let header = format!("t=1700000000, v1={}, v1={}", other_tag, good_tag);
+        assert!(verify_signature(
+            SECRET,
+            &header,
+            BODY.as_bytes(),
+            300,
+            1_700_000_000
+        ));
+        assert!(verify_signature(
+            "rotated",
+            &header,
+            BODY.as_bytes(),
+            300,
+            1_700_000_000
+        ));
+        // Schemes other than v1 are never trusted
+        let header = format!("t=1700000000,v0={}", good_tag);
+        assert!(!verify_signature(
+            SECRET,
+            &header,
+            BODY.as_bytes(),
+            300,
+            1_700_000_000
+        ));
+    }
+
+    #[test]
+    fn verify_rejects_malformed_headers() {
+        let good = signature(SECRET, 1_700_000_000, BODY);
+        let good_tag = good.split_once(",v1=").unwrap().1;
+        for header in [
+            String::new(),
+            format!("v1={}", good_tag),
+            format!("t=soon,v1={}", good_tag),
+            "t=1700000000".to_string(),
+            format!("t=1700000000,v1={}", &good_tag[1..]),
+            format!("t=1700000000,v1=zz{}", &good_tag[2..]),
+            format!("t=1700000000,v1={}é", &good_tag[..good_tag.len() - 2]),
+        ] {
+            assert!(
+                !verify_signature(SECRET, &header, BODY.as_bytes(), 300, 1_700_000_000),
+                "{}",
+                header
+            );
+        }
+    }
+
+    #[test]
+    fn decode_hex_rejects_bad_input() {
+        assert_eq!(decode_hex("00ff10"), Some(vec![0x00, 0xff, 0x10]));
+        assert_eq!(decode_hex("00FF"), Some(vec![0x00, 0xff]));
+        assert_eq!(decode_hex(""), Some(Vec::new()));
+        assert_eq!(decode_hex("abc"), None);
+        assert_eq!(decode_hex("zz"), None);
+        assert_eq!(decode_hex("+f"), None);
+        assert_eq!(decode_hex("é0"), None);
+    }
+
+    #[test]
+    fn successful_attempt_leaves_the_outbox() {
+        let webhooks = webhooks(vec![delivery("evt_1", "http://a")]);
+        webhooks.finish(&delivery("evt_1", "http://a"), Ok(()), &config("http://a"));
+        let outbox = webhooks.snapshot();
+        assert!(outbox.pending.is_empty());
+        assert!(outbox.dead_letters.is_empty());
+    }
+
+    #[test]
+    fn failed_attempts_back_off_exponentially_up_to_the_cap() {
+        let config = config("http://a");
+        let webhooks = webhooks(vec![delivery("evt_1", "http://a")]);
+        for (attempts, backoff) in [(1, 10), (2, 20), (3, 25), (4, 25)] {
+            let before = unix_now();
+            webhooks.finish(
+                &delivery("evt_1", "http://a"),
+                Err("endpoint answered 500".to_string()),
+                &config,
+            );
+            let after = unix_now();
+            let outbox = webhooks.snapshot();
+            let pending = &outbox.pending[0];
+            assert_eq!(pending.attempts, attempts);
+            assert!(pending.at >= before + backoff && pending.at <= after + backoff);
+            assert_eq!(pending.last_error.as_deref(), Some("endpoint answered 500"));
+        }
+    }
+
+    #[test]
+    fn last_failed_attempt_is_dead_lettered() {
+        let config = config("http://a");
+        let mut exhausted = delivery("evt_1", "http://a");
+        exhausted.attempts = config.max_attempts - 1;
+        let webhooks = webhooks(vec![exhausted, delivery("evt_2", "http://a")]);
+        webhooks.finish(
+            &delivery("evt_1", "http://a"),
+            Err("timed out".to_string()),
+            &config,
+        );
+        let outbox = webhooks.snapshot();
+        assert_eq!(outbox.pending.len(), 1);
+        assert_eq!(outbox.pending[0].id, "evt_2");
+        assert_eq!(outbox.dead_letters.len(), 1);
+        assert_eq!(outbox.dead_letters[0].id, "evt_1");
+        assert_eq!(outbox.dead_letters[0].attempts, config.max_attempts);
+        assert_eq!(
+            outbox.dead_letters[0].last_error.as_deref(),
+            Some("timed out")
+        );
+    }
+
+    #[test]
+    fn dead_letters_keep_only_the_newest() {
+        let mut config = config("http://a");
+        config.max_attempts = 1;
+        let ids = ["evt_1", "evt_2", "evt_3"];
+        let webhooks = webhooks(ids.iter().map(|id| delivery(id, "http://a")).collect());
+        for id in ids {
+            webhooks.finish(&delivery(id, "http://a"), Err("gone".to_string()), &config);
+        }
+        let outbox = webhooks.snapshot();
+        assert!(outbox.pending.is_empty());
+        let dead = outbox
+            .dead_letters
+            .iter()
+            .map(|delivery| delivery.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(dead, ["evt_2", "evt_3"]);
+    }
+
+    #[test]
+    fn outcome_of_an_unknown_delivery_is_ignored() {
+        let webhooks = webhooks(vec![delivery("evt_1", "http://a")]);
+        webhooks.finish(
+            &delivery("evt_1", "http://b"),
+            Err("gone".to_string()),
+            &config("http://a"),
+        );
+        let outbox = webhooks.snapshot();
+        assert_eq!(outbox.pending[0].attempts, 0);
+        assert!(outbox.dead_letters.is_empty());
+    }
+
+    #[test]
+    fn send_signs_the_delivery() {
+        let (url, received) = receiver(204);
+        let delivery = delivery("evt_1", &url);
+        send(&client(), &config(&url), &delivery).unwrap();
+
+        let request = received.recv().unwrap();
+        assert_eq!(request.body, BODY);
+        assert_eq!(request.headers["content-type"], "application/json");
+        assert_eq!(request.headers["x-autherium-event"], "license.created");
+        assert_eq!(request.headers["x-autherium-delivery"], "evt_1");
+        let header = &request.headers["x-autherium-signature"];
+        let timestamp = header
+            .strip_prefix("t=")
+            .and_then(|rest| rest.split(',').next())
+            .and_then(|timestamp| timestamp.parse().ok())
+            .unwrap();
+        assert_eq!(*header, signature(SECRET, timestamp, BODY));
+    }
+
+    #[test]
+    fn send_posts_discord_deliveries_unsigned() {
+        let (url, received) = receiver(204);
+        let data = serde_json::json!({ "license": "ABC", "product_ids": ["pro"] });
+        let config = crate::config::DiscordConfig {
+            default_url: Some(url.clone()),
+            ..Default::default()
+        };
+        let deliveries =
+            discord::deliveries(&config, "evt_1", WebhookEvent::LicenseExpired, &data, 0);
+        // Discord channels aren't webhook endpoints, so nothing about them is configured
+        send(&client(), &WebhooksConfig::default(), &deliveries[0]).unwrap();
+
+        let request = received.recv().unwrap();
+        assert_eq!(request.body, deliveries[0].body);
+        assert_eq!(request.headers["content-type"], "application/json");
+        assert!(!request.headers.contains_key("x-autherium-signature"));
+        assert!(!request.headers.contains_key("x-autherium-event"));
+        let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(body["embeds"][0]["title"], "License expired");
+    }
+
+    #[test]
+    fn send_fails_on_an_error_status() {
+        let (url, received) = receiver(500);
+        let error = send(&client(), &config(&url), &delivery("evt_1", &url)).unwrap_err();
+        assert!(error.contains("500"), "{}", error);
+        assert_eq!(received.recv().unwrap().body, BODY);
+    }
+
+    #[test]
+    fn send_fails_for_a_removed_endpoint() {
+        let error = send(
+            &client(),
+            &config("http://127.0.0.1:9/other"),
+            &delivery("evt_1", "http://127.0.0.1:9/hook"),
+        )
+        .unwrap_err();
+        assert_eq!(error, "endpoint is no longer configured");
+    }
+}
@@ -0,0 +1,120 @@
+//! Auth throughput benchmark against a running server.
+//!
+//! Usage: bench [base_url] [api_key] [licenses] [threads] [seconds]
+//!
+//! Creates `licenses` licenses, then hammers `/auth` with random ones from `threads`
+//! threads for `seconds` seconds and reports throughput and latency.
+
+use autherium_rs::Autherium;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+const PRODUCT_ID: &str = "bench";
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = std::env::args().collect::<Vec<_>>();
+    let arg = |index: usize, default: &str| args.get(index).cloned().unwrap_or(default.into());
+    let base_url = arg(1, "http://localhost:8080");
+    let api_key = arg(2, "super_secret_key");
+    let license_count: usize = arg(3, "100000").parse()?;
+    let threads: usize = arg(4, "8").parse()?;
+    let seconds: u64 = arg(5, "10").parse()?;
+
+    println!("Creating {} licenses on {}", license_count, base_url);
+    let started = Instant::now();
+    let created = Arc::new(AtomicUsize::new(0));
+    let mut workers = Vec::new();
+    for _ in 0..threads {
+        let base_url = base_url.clone();
+        let api_key = api_key.clone();
+        let created = created.clone();
+        workers.push(thread::spawn(move || -> Result<Vec<String>, String> {
+            let autherium = Autherium::new_allow_http(&base_url).map_err(|e| e.to_string())?;
+            let mut licenses = Vec::new();
+            while created.fetch_add(1, Ordering::Relaxed) < license_count {
+                let license = autherium
+                    .create_license(30, &api_key, vec![PRODUCT_ID])
+                    .map_err(|e| e.to_string())?;
+                licenses.push(license);
+            }
+            Ok(licenses)
+        }));
+    }
+    let mut licenses = Vec::new();
+    for worker in workers {
+        licenses.extend(worker.join().unwrap()?);
+    }
+    let elapsed = started.elapsed().as_secs_f64();
+    println!(
+        "Created {} licenses in {:.1}s ({:.0}/s)",
+        licenses.len(),
+        elapsed,
+        licenses.len() as f64 / elapsed
+    );
+
+    println!("Authenticating from {} threads for {}s", threads, seconds);
+    let licenses = Arc::new(licenses);
+    let deadline = Instant::now() + Duration::from_secs(seconds);
+    let mut workers = Vec::new();
+    for worker in 0..threads {
+        let base_url = base_url.clone();
+        let licenses = licenses.clone();
+        workers.push(thread::spawn(
+            move || -> Result<(Vec<Duration>, usize), String> {
+                let autherium = Autherium::new_allow_http(&base_url).map_err(|e| e.to_string())?;
+                let mut latencies = Vec::new();
+                let mut errors = 0;
+                // Cheap per-thread xorshift so threads hit different licenses
+                let mut seed = 0x9E37_79B9_7F4A_7C15u64 ^ (worker as u64 + 1);
+                while Instant::now() < deadline {
+                    seed ^= seed << 13;
+                    seed ^= seed >> 7;
+                    seed ^= seed << 17;
+                    let license = &licenses[seed as usize % licenses.len()];
+                    let request = Instant::now();
+                    if autherium
+                        .authenticate(license, PRODUCT_ID.to_string())
+                        .is_err()
+                    {
+                        errors += 1;
+                    }
+                    latencies.push(request.elapsed());
+                }
+                Ok((latencies, errors))
+            },
+        ));
+    }
+    let mut latencies = Vec::new();
+    let mut errors = 0;
+    for worker in workers {
+        let (worker_latencies, worker_errors) = worker.join().unwrap()?;
+        latencies.extend(worker_latencies);
+        errors += worker_errors;
+    }
+    latencies.sort();
+    let percentile = |p: f64| {
+        latencies
+            .get(((latencies.len() as f64 * p) as usize).min(latencies.len().saturating_sub(1)))
+            .copied()
+            .unwrap_or_default()
+    };
+    println!(
+        "{} auth requests, {} errors, {:.0} req/s",
+        latencies.len(),
+        errors,
+        latencies.len() as f64 / seconds as f64
+    );
+    println!(
+        "latency p50 {:?}, p99 {:?}, max {:?}",
+        percentile(0.50),
+        percentile(0.99),
+        latencies.last().copied().unwrap_or_default()
+    );
+    Ok(())
+}